@@ -0,0 +1,473 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use ical::{parser::ical::component::IcalEvent, property::Property, IcalParser};
+use log::warn;
+
+use crate::rrule::Rule;
+
+/// Stop looking for recurrences this far before the start of a time range,
+/// so long events started before it can still be found.
+const MAX_DURATION_DAYS: i64 = 366;
+
+/// The time zone a time in a calendar is in.
+#[derive(Debug, Clone, Copy)]
+pub enum Zone {
+    Utc,
+    Tz(Tz),
+    /// Floating times are in the local time zone.
+    Local,
+}
+
+impl Zone {
+    pub fn to_utc(self, t: NaiveDateTime) -> DateTime<Utc> {
+        fn resolve<Z: TimeZone>(zone: &Z, t: NaiveDateTime) -> DateTime<Utc> {
+            // Times skipped by a DST change are moved an hour later
+            zone.from_local_datetime(&t)
+                .earliest()
+                .or_else(|| {
+                    zone.from_local_datetime(&(t + Duration::hours(1)))
+                        .earliest()
+                })
+                .map_or_else(|| Utc.from_utc_datetime(&t), |t| t.with_timezone(&Utc))
+        }
+        match self {
+            Zone::Utc => Utc.from_utc_datetime(&t),
+            Zone::Tz(tz) => resolve(&tz, t),
+            Zone::Local => resolve(&chrono::Local, t),
+        }
+    }
+
+    pub fn local(self, t: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Zone::Utc => t.naive_utc(),
+            Zone::Tz(tz) => t.with_timezone(&tz).naive_local(),
+            Zone::Local => t.with_timezone(&chrono::Local).naive_local(),
+        }
+    }
+}
+
+/// A `VEVENT`, or an override of one instance of a recurring one.
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub uid: String,
+    pub summary: String,
+    pub location: Option<String>,
+    /// Wall time of the start in `zone`.
+    start: NaiveDateTime,
+    zone: Zone,
+    duration: Duration,
+    rule: Option<Rule>,
+    /// Starts of instances that don't happen or are overridden.
+    exdates: HashSet<DateTime<Utc>>,
+    /// The instance of a recurring event this one replaces.
+    recurrence_id: Option<DateTime<Utc>>,
+}
+
+/// One time an event happens.
+#[derive(Debug, Clone)]
+pub struct Occurrence {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl Event {
+    /// Occurrences that overlap with the time range from `from` to `to`.
+    /// Events without a duration end when they start.
+    pub fn occurrences(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<Occurrence> {
+        let starts = match &self.rule {
+            Some(rule) => rule
+                .expand(self.start, self.zone.local(to))
+                .into_iter()
+                .map(|t| self.zone.to_utc(t))
+                .filter(|t| !self.exdates.contains(t))
+                .collect(),
+            None => vec![self.zone.to_utc(self.start)],
+        };
+        let earliest = from - Duration::days(MAX_DURATION_DAYS);
+        starts
+            .into_iter()
+            .filter(|&start| start >= earliest && start <= to)
+            .map(|start| Occurrence {
+                start,
+                end: start + self.duration,
+            })
+            .filter(|o| o.end > from)
+            .collect()
+    }
+
+    fn parse(event: IcalEvent) -> Result<Option<Event>, String> {
+        let props = event.properties;
+        let get = |name: &str| props.iter().find(|p| p.name.eq_ignore_ascii_case(name));
+        let text = |name: &str| get(name).and_then(|p| p.value.as_deref()).map(unescape);
+
+        if text("STATUS").is_some_and(|s| s.eq_ignore_ascii_case("CANCELLED")) {
+            return Ok(None);
+        }
+        let uid = text("UID").ok_or("event without UID")?;
+        let (start, zone, all_day) = parse_time(get("DTSTART").ok_or("event without DTSTART")?)?;
+        let duration = if let Some(end) = get("DTEND") {
+            let (end, end_zone, _) = parse_time(end)?;
+            end_zone.to_utc(end) - zone.to_utc(start)
+        } else if let Some(duration) = get("DURATION").and_then(|p| p.value.as_deref()) {
+            parse_duration(duration).ok_or_else(|| format!("invalid DURATION '{}'", duration))?
+        } else if all_day {
+            Duration::days(1)
+        } else {
+            Duration::zero()
+        };
+
+        let mut rule = None;
+        if let Some(value) = get("RRULE").and_then(|p| p.value.as_deref()) {
+            match Rule::parse(value) {
+                Ok((mut r, until)) => {
+                    if let Some(until) = until {
+                        let (until, until_zone) = parse_value(&until, None)
+                            .ok_or_else(|| format!("invalid UNTIL '{}'", until))?;
+                        r.until = Some(zone.local(until_zone.to_utc(until)));
+                    }
+                    rule = Some(r);
+                }
+                Err(e) => warn!("{}: {}, only using the first instance", uid, e),
+            }
+        }
+
+        let mut exdates = HashSet::new();
+        for p in props
+            .iter()
+            .filter(|p| p.name.eq_ignore_ascii_case("EXDATE"))
+        {
+            let tzid = param(p, "TZID");
+            for value in p.value.iter().flat_map(|v| v.split(',')) {
+                let (t, z) = parse_value(value, tzid)
+                    .ok_or_else(|| format!("invalid EXDATE '{}'", value))?;
+                // Dates exclude the instance starting at the same time of day
+                let (t, z) = if value.trim().len() == 8 {
+                    (t.date().and_time(start.time()), zone)
+                } else {
+                    (t, z)
+                };
+                exdates.insert(z.to_utc(t));
+            }
+        }
+        let recurrence_id = match get("RECURRENCE-ID") {
+            Some(p) => {
+                let (t, z, _) = parse_time(p)?;
+                Some(z.to_utc(t))
+            }
+            None => None,
+        };
+
+        Ok(Some(Event {
+            summary: text("SUMMARY").unwrap_or_default(),
+            location: text("LOCATION").filter(|l| !l.is_empty()),
+            uid,
+            start,
+            zone,
+            duration,
+            rule,
+            exdates,
+            recurrence_id,
+        }))
+    }
+}
+
+/// Events read from the configured sources.
+pub struct Calendar {
+    sources: Vec<PathBuf>,
+    /// Modification times of the files read last time.
+    files: HashMap<PathBuf, SystemTime>,
+    pub events: Vec<Event>,
+}
+
+impl Calendar {
+    pub fn new(sources: Vec<PathBuf>) -> Self {
+        Calendar {
+            sources,
+            files: HashMap::new(),
+            events: vec![],
+        }
+    }
+
+    /// Read the sources again if any file changed. Returns whether they did.
+    pub fn reload(&mut self) -> bool {
+        let mut files = HashMap::new();
+        for source in &self.sources {
+            if let Err(e) = find_files(source, &mut files) {
+                warn!("cannot read {}: {}", source.display(), e);
+            }
+        }
+        if files == self.files {
+            return false;
+        }
+
+        let mut events = vec![];
+        for path in files.keys() {
+            if let Err(e) = read_file(path, &mut events) {
+                warn!("cannot read {}: {}", path.display(), e);
+            }
+        }
+        link_overrides(&mut events);
+
+        self.files = files;
+        self.events = events;
+        true
+    }
+}
+
+/// Collect `.ics` files and their modification times.
+fn find_files(path: &Path, files: &mut HashMap<PathBuf, SystemTime>) -> io::Result<()> {
+    let meta = fs::metadata(path)?;
+    if meta.is_dir() {
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+            let is_ics = path
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("ics"));
+            if is_ics || path.is_dir() {
+                find_files(&path, files)?;
+            }
+        }
+    } else {
+        files.insert(path.to_owned(), meta.modified()?);
+    }
+    Ok(())
+}
+
+/// Exclude overridden instances from their recurring events.
+fn link_overrides(events: &mut [Event]) {
+    let overrides: Vec<_> = events
+        .iter()
+        .filter_map(|e| Some((e.uid.clone(), e.recurrence_id?)))
+        .collect();
+    for (uid, id) in overrides {
+        for event in events
+            .iter_mut()
+            .filter(|e| e.uid == uid && e.recurrence_id.is_none())
+        {
+            event.exdates.insert(id);
+        }
+    }
+}
+
+fn read_file(path: &Path, events: &mut Vec<Event>) -> Result<(), String> {
+    let file = fs::File::open(path).map_err(|e| e.to_string())?;
+    read_calendar(BufReader::new(file), path, events)
+}
+
+/// Read events of a calendar, with `path` used in warnings.
+fn read_calendar(reader: impl BufRead, path: &Path, events: &mut Vec<Event>) -> Result<(), String> {
+    for calendar in IcalParser::new(reader) {
+        let calendar = calendar.map_err(|e| e.to_string())?;
+        for event in calendar.events {
+            match Event::parse(event) {
+                Ok(Some(event)) => events.push(event),
+                Ok(None) => {}
+                Err(e) => warn!("{}: {}", path.display(), e),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn param<'a>(p: &'a Property, name: &str) -> Option<&'a str> {
+    p.params
+        .as_ref()?
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .and_then(|(_, v)| v.first())
+        .map(|v| v.as_str())
+}
+
+/// Parse a `DATE` or `DATE-TIME` property. Returns whether it's a date.
+fn parse_time(p: &Property) -> Result<(NaiveDateTime, Zone, bool), String> {
+    let value = p.value.as_deref().unwrap_or_default();
+    let (t, zone) = parse_value(value, param(p, "TZID"))
+        .ok_or_else(|| format!("invalid {} '{}'", p.name, value))?;
+    let is_date =
+        value.len() == 8 || param(p, "VALUE").is_some_and(|v| v.eq_ignore_ascii_case("DATE"));
+    // Dates are always floating
+    let zone = if is_date { Zone::Local } else { zone };
+    Ok((t, zone, is_date))
+}
+
+fn parse_value(value: &str, tzid: Option<&str>) -> Option<(NaiveDateTime, Zone)> {
+    let value = value.trim();
+    if value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        return Some((date.and_hms_opt(0, 0, 0)?, Zone::Local));
+    }
+    if let Some(utc) = value.strip_suffix('Z') {
+        let t = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some((t, Zone::Utc));
+    }
+    let t = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    let zone = match tzid {
+        Some(tzid) => match tzid.trim_start_matches('/').parse::<Tz>() {
+            Ok(tz) => Zone::Tz(tz),
+            Err(_) => {
+                warn!("unknown time zone '{}', using local time", tzid);
+                Zone::Local
+            }
+        },
+        None => Zone::Local,
+    };
+    Some((t, zone))
+}
+
+/// Parse a duration like `P1DT2H30M` or `-PT15M`.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let s = s.strip_prefix('P')?;
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    for c in s.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => {}
+            _ => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                total += match c {
+                    'W' => Duration::weeks(n),
+                    'D' => Duration::days(n),
+                    'H' => Duration::hours(n),
+                    'M' => Duration::minutes(n),
+                    'S' => Duration::seconds(n),
+                    _ => return None,
+                };
+            }
+        }
+    }
+    Some(if negative { -total } else { total })
+}
+
+/// Undo the escapes of a `TEXT` value.
+fn unescape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => res.push('\n'),
+            Some(c) => res.push(c),
+            None => res.push('\\'),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(body: &str) -> Vec<Event> {
+        let ics = format!(
+            "BEGIN:VCALENDAR\nVERSION:2.0\nPRODID:test\n{}END:VCALENDAR\n",
+            body
+        );
+        let mut events = vec![];
+        read_calendar(ics.as_bytes(), Path::new("test.ics"), &mut events).unwrap();
+        link_overrides(&mut events);
+        events
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    /// Starts of occurrences in March and April 2024.
+    fn starts(event: &Event) -> Vec<String> {
+        event
+            .occurrences(utc("2024-03-01T00:00:00Z"), utc("2024-04-30T00:00:00Z"))
+            .into_iter()
+            .map(|o| o.start.format("%m-%d %H:%M").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn utc_until_with_time_zone() {
+        // 09:00 in New York is 14:00 UTC until DST starts on March 10
+        let body = |until: &str| {
+            format!(
+                "BEGIN:VEVENT\nUID:a\nDTSTART;TZID=America/New_York:20240308T090000\n\
+                 RRULE:FREQ=DAILY;UNTIL={}\nEND:VEVENT\n",
+                until
+            )
+        };
+        let events = events(&body("20240311T130000Z"));
+        assert_eq!(
+            starts(&events[0]),
+            ["03-08 14:00", "03-09 14:00", "03-10 13:00", "03-11 13:00"]
+        );
+        let events = self::events(&body("20240311T125959Z"));
+        assert_eq!(starts(&events[0]).len(), 3);
+    }
+
+    #[test]
+    fn date_exdate() {
+        let events = events(
+            "BEGIN:VEVENT\nUID:a\nDTSTART;TZID=Asia/Tokyo:20240305T080000\n\
+             RRULE:FREQ=DAILY;COUNT=3\nEXDATE;VALUE=DATE:20240306\nEND:VEVENT\n",
+        );
+        // 08:00 in Tokyo is 23:00 UTC the day before
+        assert_eq!(starts(&events[0]), ["03-04 23:00", "03-06 23:00"]);
+    }
+
+    #[test]
+    fn date_time_exdate() {
+        let events = events(
+            "BEGIN:VEVENT\nUID:a\nDTSTART:20240304T100000Z\nRRULE:FREQ=WEEKLY;COUNT=3\n\
+             EXDATE;TZID=Europe/Berlin:20240311T110000\nEND:VEVENT\n",
+        );
+        assert_eq!(starts(&events[0]), ["03-04 10:00", "03-18 10:00"]);
+    }
+
+    #[test]
+    fn recurrence_id_override() {
+        let events = events(
+            "BEGIN:VEVENT\nUID:a\nSUMMARY:Weekly\nDTSTART:20240304T100000Z\n\
+             DURATION:PT1H\nRRULE:FREQ=WEEKLY;COUNT=3\nEND:VEVENT\n\
+             BEGIN:VEVENT\nUID:a\nSUMMARY:Moved\nRECURRENCE-ID:20240311T100000Z\n\
+             DTSTART:20240312T150000Z\nDTEND:20240312T153000Z\nEND:VEVENT\n",
+        );
+        let master = events.iter().find(|e| e.summary == "Weekly").unwrap();
+        let moved = events.iter().find(|e| e.summary == "Moved").unwrap();
+        assert_eq!(starts(master), ["03-04 10:00", "03-18 10:00"]);
+        assert_eq!(starts(moved), ["03-12 15:00"]);
+        let occurrence =
+            &moved.occurrences(utc("2024-03-12T00:00:00Z"), utc("2024-03-13T00:00:00Z"))[0];
+        assert_eq!(occurrence.end - occurrence.start, Duration::minutes(30));
+    }
+
+    #[test]
+    fn cancelled() {
+        let events =
+            events("BEGIN:VEVENT\nUID:a\nSTATUS:CANCELLED\nDTSTART:20240304T100000Z\nEND:VEVENT\n");
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(
+            parse_duration("P1DT2H30M"),
+            Some(Duration::minutes(26 * 60 + 30))
+        );
+        assert_eq!(parse_duration("-PT15M"), Some(Duration::minutes(-15)));
+        assert_eq!(parse_duration("P2W"), Some(Duration::weeks(2)));
+        assert_eq!(parse_duration("1H"), None);
+    }
+}
@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+
+use nadir_backend_common::FrontendConfig;
+use nadir_types::model::MessageGroup;
+use serde::Deserialize;
+
+/// Config file for this backend
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    /// The frontend to send messages to.
+    pub frontend: FrontendConfig,
+
+    /// The group all events go into.
+    #[serde(default = "default_group")]
+    pub group: MessageGroup,
+
+    /// `.ics` files, or directories searched for them, like the collections
+    /// of a vdir synced by vdirsyncer.
+    pub sources: Vec<PathBuf>,
+
+    /// Minutes before an event starts when it's shown.
+    #[serde(default = "default_lead_time")]
+    pub lead_time: i64,
+
+    /// Seconds between two checks. Sources are read again when they change.
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+fn default_group() -> MessageGroup {
+    MessageGroup {
+        id: "calendar".into(),
+        title: "Calendar".into(),
+        ..Default::default()
+    }
+}
+
+fn default_lead_time() -> i64 {
+    15
+}
+
+fn default_interval() -> u64 {
+    30
+}
@@ -0,0 +1,128 @@
+//! A backend that reminds of events in iCalendar files.
+//!
+//! An event appears `lead_time` minutes before it starts, is pinned while it
+//! takes place, and disappears after it ends. Recurring events are expanded
+//! with the usual parts of `RRULE`, `EXDATE` and overridden instances.
+//!
+//! ```toml
+//! sources = ["/home/me/.calendars/work", "/home/me/holidays.ics"]
+//! lead_time = 10
+//!
+//! [frontend]
+//! url = "ws://localhost:7890"
+//! ```
+mod calendar;
+mod config;
+mod rrule;
+
+use std::{collections::HashMap, time::Duration};
+
+use chrono::Utc;
+use clap::Clap;
+use nadir_backend_common::{Link, Opt};
+use nadir_types::model::Message;
+
+use self::{calendar::Calendar, config::Config};
+
+/// Keeps messages in sync with the current and upcoming events.
+struct Reminder {
+    link: Link,
+    group: String,
+    lead_time: chrono::Duration,
+
+    /// Messages currently shown, and whether they are pinned.
+    shown: HashMap<String, (Message, bool)>,
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let opt = Opt::parse();
+    let config: Config = opt.load_config("./nadir-calendar.toml");
+
+    let (link, mut frontend) = Link::spawn(config.frontend);
+    let group = config.group.id.clone();
+    link.put_group(config.group);
+
+    tokio::spawn(async move {
+        // This backend doesn't react to the frontend
+        while frontend.recv().await.is_some() {}
+    });
+
+    let mut calendar = Calendar::new(config.sources);
+    let mut reminder = Reminder {
+        link,
+        group,
+        lead_time: chrono::Duration::minutes(config.lead_time.max(0)),
+        shown: HashMap::new(),
+    };
+
+    let mut timer = tokio::time::interval(Duration::from_secs(config.interval.max(1)));
+    loop {
+        timer.tick().await;
+        calendar.reload();
+        reminder.update(&calendar);
+    }
+}
+
+impl Reminder {
+    /// Send the difference between shown messages and events that should be
+    /// shown now.
+    fn update(&mut self, calendar: &Calendar) {
+        let now = Utc::now();
+        let mut current = HashMap::new();
+        for event in &calendar.events {
+            for o in event.occurrences(now, now + self.lead_time) {
+                let msg = Message {
+                    id: format!("{}/{}", event.uid, o.start.timestamp()),
+                    counter: None,
+                    tags: event.location.iter().cloned().collect(),
+                    body: event.summary.clone(),
+                    time: Some(o.start),
+                };
+                // Events that already started stay on top until they end
+                let pinned = o.start <= now;
+                current.insert(msg.id.clone(), (msg, pinned));
+            }
+        }
+
+        let mut removed = vec![];
+        let mut removed_pinned = vec![];
+        for (id, (_, pinned)) in &self.shown {
+            let moved = current.get(id).is_none_or(|(_, p)| p != pinned);
+            if moved {
+                if *pinned {
+                    removed_pinned.push(id.clone());
+                } else {
+                    removed.push(id.clone());
+                }
+            }
+        }
+
+        let mut changed = vec![];
+        let mut changed_pinned = vec![];
+        for (id, (msg, pinned)) in &current {
+            let same = self
+                .shown
+                .get(id)
+                .is_some_and(|(m, p)| p == pinned && m.body == msg.body && m.tags == msg.tags);
+            if same {
+                continue;
+            }
+            if *pinned {
+                changed_pinned.push(msg.clone());
+            } else {
+                changed.push(msg.clone());
+            }
+        }
+        // The last message is shown first, so put the soonest one last
+        changed.sort_by_key(|m| std::cmp::Reverse(m.time));
+        changed_pinned.sort_by_key(|m| std::cmp::Reverse(m.time));
+
+        self.link.remove(self.group.as_str(), removed);
+        self.link.remove_pinned(self.group.as_str(), removed_pinned);
+        self.link.put(self.group.as_str(), changed);
+        self.link.put_pinned(self.group.as_str(), changed_pinned);
+        self.shown = current;
+    }
+}
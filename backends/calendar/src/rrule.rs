@@ -0,0 +1,448 @@
+//! Expansion of recurrence rules (RFC 5545, section 3.3.10).
+//!
+//! Supported are `FREQ` of `DAILY`, `WEEKLY`, `MONTHLY` and `YEARLY` with
+//! `INTERVAL`, `COUNT`, `UNTIL`, `BYDAY`, `BYMONTHDAY` and `BYMONTH`. Other
+//! parts are ignored. Weeks start on Monday.
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Weekday};
+
+/// Stop expanding after this many periods, in case a rule never produces an
+/// occurrence.
+const MAX_PERIODS: u32 = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Freq {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    freq: Freq,
+    interval: u32,
+    count: Option<u32>,
+    /// The last possible start, in the same time zone as `DTSTART`.
+    pub until: Option<NaiveDateTime>,
+    /// Weekdays, with an optional ordinal like the `-1` in `-1FR`.
+    by_day: Vec<(Option<i32>, Weekday)>,
+    by_month_day: Vec<i32>,
+    by_month: Vec<u32>,
+}
+
+impl Rule {
+    /// Parse the value of an `RRULE` property. `UNTIL` is returned as is and
+    /// should be converted to the time zone of `DTSTART` by the caller.
+    pub fn parse(s: &str) -> Result<(Rule, Option<String>), String> {
+        let mut freq = None;
+        let mut rule = Rule {
+            freq: Freq::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: vec![],
+            by_month_day: vec![],
+            by_month: vec![],
+        };
+        let mut until = None;
+        for part in s.split(';') {
+            let (key, value) = match part.split_once('=') {
+                Some(x) => x,
+                None => continue,
+            };
+            let invalid = || format!("invalid {} '{}'", key, value);
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Freq::Daily,
+                        "WEEKLY" => Freq::Weekly,
+                        "MONTHLY" => Freq::Monthly,
+                        "YEARLY" => Freq::Yearly,
+                        _ => return Err(format!("unsupported frequency '{}'", value)),
+                    })
+                }
+                "INTERVAL" => rule.interval = value.parse().map_err(|_| invalid())?,
+                "COUNT" => rule.count = Some(value.parse().map_err(|_| invalid())?),
+                "UNTIL" => until = Some(value.to_owned()),
+                "BYDAY" => {
+                    rule.by_day = value
+                        .split(',')
+                        .map(parse_weekday)
+                        .collect::<Option<_>>()
+                        .ok_or_else(invalid)?
+                }
+                "BYMONTHDAY" => {
+                    rule.by_month_day = value
+                        .split(',')
+                        .map(|d| d.parse().ok())
+                        .collect::<Option<_>>()
+                        .ok_or_else(invalid)?
+                }
+                "BYMONTH" => {
+                    rule.by_month = value
+                        .split(',')
+                        .map(|d| d.parse().ok())
+                        .collect::<Option<_>>()
+                        .ok_or_else(invalid)?
+                }
+                _ => {}
+            }
+        }
+        rule.freq = freq.ok_or("missing FREQ")?;
+        rule.interval = rule.interval.max(1);
+        Ok((rule, until))
+    }
+
+    /// Start times of occurrences from `start` up to and including `limit`.
+    pub fn expand(&self, start: NaiveDateTime, limit: NaiveDateTime) -> Vec<NaiveDateTime> {
+        let mut res = vec![];
+        let mut emitted = 0;
+        for k in 0..MAX_PERIODS {
+            let period = k * self.interval;
+            let (begin, mut dates) = match self.period(start.date(), period) {
+                Some(x) => x,
+                None => break,
+            };
+            if begin > limit.date() {
+                break;
+            }
+            dates.sort();
+            dates.dedup();
+            for date in dates {
+                let t = date.and_time(start.time());
+                if t < start {
+                    continue;
+                }
+                if t > limit || self.until.is_some_and(|u| t > u) {
+                    return res;
+                }
+                res.push(t);
+                emitted += 1;
+                if self.count.is_some_and(|c| emitted >= c) {
+                    return res;
+                }
+            }
+        }
+        res
+    }
+
+    /// The first day of the `n`-th period after the one containing `start`,
+    /// and the candidate dates in it.
+    fn period(&self, start: NaiveDate, n: u32) -> Option<(NaiveDate, Vec<NaiveDate>)> {
+        let res = match self.freq {
+            Freq::Daily => {
+                let date = start.checked_add_signed(Duration::days(n.into()))?;
+                let ok = self.matches_month(date)
+                    && self.matches_month_day(date)
+                    && (self.by_day.is_empty()
+                        || self.by_day.iter().any(|d| d.1 == date.weekday()));
+                (date, if ok { vec![date] } else { vec![] })
+            }
+            Freq::Weekly => {
+                let monday = start - Duration::days(start.weekday().num_days_from_monday().into());
+                let begin = monday.checked_add_signed(Duration::weeks(n.into()))?;
+                let dates = if self.by_day.is_empty() {
+                    vec![begin + Duration::days(start.weekday().num_days_from_monday().into())]
+                } else {
+                    self.by_day
+                        .iter()
+                        .map(|d| begin + Duration::days(d.1.num_days_from_monday().into()))
+                        .collect()
+                };
+                let dates = dates
+                    .into_iter()
+                    .filter(|d| self.matches_month(*d))
+                    .collect();
+                (begin, dates)
+            }
+            Freq::Monthly => {
+                let (year, month) = add_months(start.year(), start.month(), n)?;
+                let begin = NaiveDate::from_ymd_opt(year, month, 1)?;
+                let dates = if self.matches_month(begin) {
+                    self.month_dates(year, month, start.day())
+                } else {
+                    vec![]
+                };
+                (begin, dates)
+            }
+            Freq::Yearly => {
+                let year = start.year().checked_add(n as i32)?;
+                let begin = NaiveDate::from_ymd_opt(year, 1, 1)?;
+                let dates = if !self.by_month.is_empty() {
+                    self.by_month
+                        .iter()
+                        .flat_map(|&m| self.month_dates(year, m, start.day()))
+                        .collect()
+                } else if !self.by_month_day.is_empty() {
+                    (1..=12)
+                        .flat_map(|m| self.month_dates(year, m, start.day()))
+                        .collect()
+                } else if !self.by_day.is_empty() {
+                    // Without BYMONTH, ordinals count weekdays in the year
+                    let end = NaiveDate::from_ymd_opt(year, 12, 31)?;
+                    self.by_day
+                        .iter()
+                        .flat_map(|&(nth, weekday)| weekdays_between(begin, end, nth, weekday))
+                        .collect()
+                } else {
+                    self.month_dates(year, start.month(), start.day())
+                };
+                (begin, dates)
+            }
+        };
+        Some(res)
+    }
+
+    /// Dates in a month selected by `BYMONTHDAY` and `BYDAY`, or the same day
+    /// as the start if there are none.
+    fn month_dates(&self, year: i32, month: u32, start_day: u32) -> Vec<NaiveDate> {
+        let len = days_in_month(year, month);
+        if !self.by_month_day.is_empty() {
+            self.by_month_day
+                .iter()
+                .filter_map(|&d| month_day(year, month, len, d))
+                .filter(|d| {
+                    self.by_day.is_empty() || self.by_day.iter().any(|w| w.1 == d.weekday())
+                })
+                .collect()
+        } else if !self.by_day.is_empty() {
+            self.by_day
+                .iter()
+                .flat_map(|&(nth, weekday)| weekdays_in_month(year, month, len, nth, weekday))
+                .collect()
+        } else {
+            NaiveDate::from_ymd_opt(year, month, start_day)
+                .into_iter()
+                .collect()
+        }
+    }
+
+    fn matches_month(&self, date: NaiveDate) -> bool {
+        self.by_month.is_empty() || self.by_month.contains(&date.month())
+    }
+
+    fn matches_month_day(&self, date: NaiveDate) -> bool {
+        let len = days_in_month(date.year(), date.month());
+        self.by_month_day.is_empty()
+            || self
+                .by_month_day
+                .iter()
+                .any(|&d| month_day(date.year(), date.month(), len, d) == Some(date))
+    }
+}
+
+/// Parse `MO`, `2TU` or `-1FR`.
+fn parse_weekday(s: &str) -> Option<(Option<i32>, Weekday)> {
+    let s = s.trim();
+    let split = s.len().checked_sub(2)?;
+    let weekday = match &s[split..] {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    };
+    let nth = match &s[..split] {
+        "" => None,
+        n => Some(n.trim_start_matches('+').parse().ok()?),
+    };
+    Some((nth, weekday))
+}
+
+fn add_months(year: i32, month: u32, n: u32) -> Option<(i32, u32)> {
+    let months = (month - 1).checked_add(n)?;
+    let year = year.checked_add((months / 12) as i32)?;
+    Some((year, months % 12 + 1))
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let (y, m) = add_months(year, month, 1).unwrap_or((year, month));
+    NaiveDate::from_ymd_opt(y, m, 1)
+        .and_then(|d| d.pred_opt())
+        .map_or(28, |d| d.day())
+}
+
+/// A day of month, counting from the end if negative.
+fn month_day(year: i32, month: u32, len: u32, day: i32) -> Option<NaiveDate> {
+    let day = if day < 0 { len as i32 + 1 + day } else { day };
+    if day < 1 {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(year, month, day as u32)
+}
+
+/// All given weekdays of a month, or only the `nth` one, counting from the
+/// end if negative.
+fn weekdays_in_month(
+    year: i32,
+    month: u32,
+    len: u32,
+    nth: Option<i32>,
+    weekday: Weekday,
+) -> Vec<NaiveDate> {
+    match (
+        NaiveDate::from_ymd_opt(year, month, 1),
+        NaiveDate::from_ymd_opt(year, month, len),
+    ) {
+        (Some(first), Some(last)) => weekdays_between(first, last, nth, weekday),
+        _ => vec![],
+    }
+}
+
+/// All given weekdays from `first` to `last` inclusive, or only the `nth`
+/// one, counting from the end if negative.
+fn weekdays_between(
+    first: NaiveDate,
+    last: NaiveDate,
+    nth: Option<i32>,
+    weekday: Weekday,
+) -> Vec<NaiveDate> {
+    let all: Vec<_> = first
+        .iter_days()
+        .take_while(|d| *d <= last)
+        .filter(|d| d.weekday() == weekday)
+        .collect();
+    match nth {
+        None => all,
+        Some(n) if n > 0 => all.get(n as usize - 1).copied().into_iter().collect(),
+        Some(n) => all
+            .len()
+            .checked_sub(n.unsigned_abs() as usize)
+            .and_then(|i| all.get(i))
+            .copied()
+            .into_iter()
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Dates of occurrences of `rule` from `start` until the end of `limit`.
+    fn dates(rule: &str, start: &str, limit: &str) -> Vec<String> {
+        let (rule, until) = Rule::parse(rule).unwrap();
+        assert_eq!(until, None);
+        let start = NaiveDate::parse_from_str(start, "%Y-%m-%d")
+            .unwrap()
+            .and_hms_opt(9, 0, 0)
+            .unwrap();
+        let limit = NaiveDate::parse_from_str(limit, "%Y-%m-%d")
+            .unwrap()
+            .and_hms_opt(23, 59, 59)
+            .unwrap();
+        rule.expand(start, limit)
+            .into_iter()
+            .map(|t| {
+                assert_eq!(t.time(), start.time());
+                t.date().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn last_friday_of_month() {
+        assert_eq!(
+            dates("FREQ=MONTHLY;BYDAY=-1FR", "2024-01-26", "2024-04-30"),
+            ["2024-01-26", "2024-02-23", "2024-03-29", "2024-04-26"]
+        );
+    }
+
+    #[test]
+    fn weekly_by_day_with_count() {
+        // The Monday before the start isn't counted
+        assert_eq!(
+            dates(
+                "FREQ=WEEKLY;BYDAY=MO,WE;COUNT=4",
+                "2024-01-03",
+                "2024-12-31"
+            ),
+            ["2024-01-03", "2024-01-08", "2024-01-10", "2024-01-15"]
+        );
+        assert_eq!(
+            dates(
+                "FREQ=WEEKLY;INTERVAL=2;BYDAY=TU",
+                "2024-01-02",
+                "2024-01-31"
+            ),
+            ["2024-01-02", "2024-01-16", "2024-01-30"]
+        );
+    }
+
+    #[test]
+    fn start_on_31st() {
+        // Months without a 31st are skipped
+        assert_eq!(
+            dates("FREQ=MONTHLY", "2024-01-31", "2024-07-31"),
+            ["2024-01-31", "2024-03-31", "2024-05-31", "2024-07-31"]
+        );
+        assert_eq!(
+            dates("FREQ=MONTHLY;BYMONTHDAY=-1", "2024-01-31", "2024-04-30"),
+            ["2024-01-31", "2024-02-29", "2024-03-31", "2024-04-30"]
+        );
+    }
+
+    #[test]
+    fn yearly() {
+        assert_eq!(
+            dates("FREQ=YEARLY", "2024-02-29", "2032-12-31"),
+            ["2024-02-29", "2028-02-29", "2032-02-29"]
+        );
+        assert_eq!(
+            dates(
+                "FREQ=YEARLY;BYMONTH=11;BYDAY=4TH",
+                "2024-11-28",
+                "2026-12-31"
+            ),
+            ["2024-11-28", "2025-11-27", "2026-11-26"]
+        );
+        assert_eq!(
+            dates(
+                "FREQ=YEARLY;BYMONTHDAY=1;COUNT=3",
+                "2024-01-01",
+                "2024-12-31"
+            ),
+            ["2024-01-01", "2024-02-01", "2024-03-01"]
+        );
+    }
+
+    #[test]
+    fn yearly_by_day_without_month() {
+        // Ordinals count weekdays in the whole year
+        assert_eq!(
+            dates("FREQ=YEARLY;BYDAY=20MO", "2024-05-13", "2026-12-31"),
+            ["2024-05-13", "2025-05-19", "2026-05-18"]
+        );
+        assert_eq!(
+            dates("FREQ=YEARLY;BYDAY=-1SU", "2024-12-29", "2025-12-31"),
+            ["2024-12-29", "2025-12-28"]
+        );
+        assert_eq!(
+            dates("FREQ=YEARLY;BYDAY=SA;COUNT=3", "2024-12-21", "2025-12-31"),
+            ["2024-12-21", "2024-12-28", "2025-01-04"]
+        );
+    }
+
+    #[test]
+    fn until() {
+        let (mut rule, until) = Rule::parse("FREQ=DAILY;UNTIL=20240103T090000Z").unwrap();
+        assert_eq!(until.as_deref(), Some("20240103T090000Z"));
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_opt(9, 0, 0)
+            .unwrap();
+        // UNTIL is inclusive
+        rule.until = Some(start + Duration::days(2));
+        assert_eq!(rule.expand(start, start + Duration::days(10)).len(), 3);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Rule::parse("FREQ=HOURLY").is_err());
+        assert!(Rule::parse("INTERVAL=2").is_err());
+        assert!(Rule::parse("FREQ=WEEKLY;BYDAY=XX").is_err());
+    }
+}
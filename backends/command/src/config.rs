@@ -0,0 +1,77 @@
+use nadir_backend_common::FrontendConfig;
+use nadir_types::model::MessageGroup;
+use serde::Deserialize;
+
+/// Config file for this backend
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    /// The frontend to send messages to.
+    pub frontend: FrontendConfig,
+
+    /// Groups created at start.
+    #[serde(default)]
+    pub groups: Vec<MessageGroup>,
+
+    /// Commands to run periodically.
+    #[serde(default)]
+    pub commands: Vec<CommandConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommandConfig {
+    /// A unique identifier of this command. Used as the prefix of message IDs.
+    pub id: String,
+
+    /// The group that messages of this command go into.
+    pub group: String,
+
+    /// The command to run. A string is run with `sh -c`, while a list is
+    /// run as program and arguments.
+    pub command: CommandLine,
+
+    /// Seconds between two runs.
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+
+    /// Seconds before the command is killed. Defaults to `interval`.
+    #[serde(default)]
+    pub timeout: Option<u64>,
+
+    /// How stdout is turned into messages.
+    #[serde(default)]
+    pub format: OutputFormat,
+
+    /// Exit codes meaning "all clear". Messages of this command are removed
+    /// when it exits with one of these codes, regardless of its output.
+    ///
+    /// Set it to `[0]` to show a command's output only when it fails. A failing
+    /// command without output shows its exit code and the first line of its
+    /// stderr instead.
+    #[serde(default)]
+    pub clear_codes: Vec<i32>,
+
+    /// Tags attached to messages produced by this command.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum CommandLine {
+    Shell(String),
+    Args(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// Every non-empty line is a message.
+    #[default]
+    Lines,
+    /// Output is a stream of JSON [`nadir_types::model::Message`]s.
+    Json,
+}
+
+fn default_interval() -> u64 {
+    60
+}
@@ -0,0 +1,48 @@
+//! A backend that periodically runs commands and shows their output.
+//!
+//! ```toml
+//! [frontend]
+//! url = "ws://localhost:7890"
+//!
+//! [[groups]]
+//! id = "status"
+//! title = "Status"
+//!
+//! [[commands]]
+//! id = "failed-units"
+//! group = "status"
+//! command = "systemctl --failed --plain --no-legend"
+//! interval = 30
+//! tags = ["systemd"]
+//!
+//! [[commands]]
+//! id = "repo"
+//! group = "status"
+//! command = ["git", "-C", "/path/to/repo", "diff", "--quiet"]
+//! clear_codes = [0]
+//! ```
+mod config;
+mod runner;
+
+use clap::Clap;
+use nadir_backend_common::{Link, Opt};
+
+use self::{config::Config, runner::Runner};
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let opt = Opt::parse();
+    let config: Config = opt.load_config("./nadir-command.toml");
+
+    let (link, mut frontend) = Link::spawn(config.frontend);
+    for group in config.groups {
+        link.put_group(group);
+    }
+    for command in config.commands {
+        tokio::spawn(Runner::new(command, link.clone()).run());
+    }
+
+    // This backend doesn't react to the frontend
+    while frontend.recv().await.is_some() {}
+}
@@ -0,0 +1,265 @@
+use std::{process::Stdio, time::Duration};
+
+use chrono::Utc;
+use indexmap::IndexMap;
+use log::{debug, warn};
+use nadir_backend_common::Link;
+use nadir_types::model::Message;
+use tokio::process::Command;
+
+use crate::config::{CommandConfig, CommandLine, OutputFormat};
+
+/// Periodically runs a command and keeps its messages in sync with its
+/// output.
+pub struct Runner {
+    config: CommandConfig,
+    link: Link,
+
+    /// Messages sent for the last run, keyed by their IDs.
+    last: IndexMap<String, Message>,
+}
+
+impl Runner {
+    pub fn new(config: CommandConfig, link: Link) -> Self {
+        Runner {
+            config,
+            link,
+            last: IndexMap::new(),
+        }
+    }
+
+    pub async fn run(mut self) {
+        let mut timer = tokio::time::interval(Duration::from_secs(self.config.interval.max(1)));
+        loop {
+            timer.tick().await;
+            let msgs = self.run_once().await;
+            self.update(msgs);
+        }
+    }
+
+    /// Run the command once and collect the messages it should show.
+    async fn run_once(&self) -> Vec<Message> {
+        let mut command = match &self.config.command {
+            CommandLine::Shell(s) => {
+                let mut c = Command::new("sh");
+                c.arg("-c").arg(s);
+                c
+            }
+            CommandLine::Args(args) => match args.split_first() {
+                Some((program, args)) => {
+                    let mut c = Command::new(program);
+                    c.args(args);
+                    c
+                }
+                None => return vec![self.error_message("empty command")],
+            },
+        };
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let timeout = self.config.timeout.unwrap_or(self.config.interval);
+        let output =
+            match tokio::time::timeout(Duration::from_secs(timeout), command.output()).await {
+                Ok(Ok(output)) => output,
+                Ok(Err(e)) => return vec![self.error_message(&format!("failed to run: {}", e))],
+                Err(_) => return vec![self.error_message("timed out")],
+            };
+
+        if !output.stderr.is_empty() {
+            debug!(
+                "{}: stderr: {}",
+                self.config.id,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        if let Some(code) = output.status.code() {
+            if self.config.clear_codes.contains(&code) {
+                return vec![];
+            }
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let msgs = self.parse(&stdout);
+        if msgs.is_empty() && !output.status.success() {
+            // Failing silently would look the same as all clear
+            let status = match output.status.code() {
+                Some(code) => format!("exited with {}", code),
+                None => "killed by a signal".to_owned(),
+            };
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return vec![
+                match stderr.lines().map(str::trim).find(|l| !l.is_empty()) {
+                    Some(line) => self.error_message(&format!("{}: {}", status, line)),
+                    None => self.error_message(&status),
+                },
+            ];
+        }
+        msgs
+    }
+
+    /// Turn stdout into messages according to the output format.
+    fn parse(&self, stdout: &str) -> Vec<Message> {
+        match self.config.format {
+            OutputFormat::Lines => stdout
+                .lines()
+                .map(str::trim_end)
+                .filter(|l| !l.is_empty())
+                .enumerate()
+                .map(|(i, line)| Message {
+                    id: format!("{}/{}", self.config.id, i),
+                    tags: self.config.tags.clone(),
+                    body: line.to_owned(),
+                    ..Default::default()
+                })
+                .collect(),
+            OutputFormat::Json => {
+                let parsed = serde_json::Deserializer::from_str(stdout)
+                    .into_iter::<Message>()
+                    .collect::<Result<Vec<_>, _>>();
+                match parsed {
+                    Ok(msgs) => msgs
+                        .into_iter()
+                        .map(|mut m| {
+                            m.id = format!("{}/{}", self.config.id, m.id);
+                            if m.tags.is_empty() {
+                                m.tags = self.config.tags.clone();
+                            }
+                            m
+                        })
+                        .collect(),
+                    Err(e) => vec![self.error_message(&format!("invalid output: {}", e))],
+                }
+            }
+        }
+    }
+
+    fn error_message(&self, reason: &str) -> Message {
+        warn!("{}: {}", self.config.id, reason);
+        Message {
+            id: format!("{}/error", self.config.id),
+            tags: self.config.tags.clone(),
+            body: format!("{}: {}", self.config.id, reason),
+            ..Default::default()
+        }
+    }
+
+    /// Send the difference between the last run and this run.
+    fn update(&mut self, msgs: Vec<Message>) {
+        let now = Utc::now();
+        let mut current = IndexMap::new();
+        let mut changed = false;
+        for mut msg in msgs {
+            match self.last.get(&msg.id) {
+                Some(last) if same_content(last, &msg) => msg = last.clone(),
+                _ => {
+                    changed = true;
+                    msg.time.get_or_insert(now);
+                }
+            }
+            current.insert(msg.id.clone(), msg);
+        }
+
+        let removed: Vec<_> = self
+            .last
+            .keys()
+            .filter(|k| !current.contains_key(*k))
+            .cloned()
+            .collect();
+        self.link.remove(self.config.group.as_str(), removed);
+
+        if changed {
+            // Later messages are shown first, so send them in reverse order to
+            // keep the output order on screen.
+            let items = current.values().rev().cloned().collect();
+            self.link.put(self.config.group.as_str(), items);
+        }
+        self.last = current;
+    }
+}
+
+/// Whether a newly parsed message shows the same thing as the last sent one.
+/// Messages without a time get one when sent, so it's not compared.
+fn same_content(last: &Message, new: &Message) -> bool {
+    last.body == new.body
+        && last.tags == new.tags
+        && last.counter == new.counter
+        && (new.time.is_none() || new.time == last.time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runner(config: serde_json::Value) -> Runner {
+        let mut command = serde_json::json!({ "id": "cmd", "group": "g", "tags": ["t"] });
+        command
+            .as_object_mut()
+            .unwrap()
+            .extend(config.as_object().unwrap().clone());
+        let frontend = serde_json::from_value(serde_json::json!({ "url": "ws://127.0.0.1:1" }));
+        let (link, _) = Link::spawn(frontend.unwrap());
+        Runner::new(serde_json::from_value(command).unwrap(), link)
+    }
+
+    fn bodies(msgs: &[Message]) -> Vec<(&str, &str)> {
+        msgs.iter()
+            .map(|m| (m.id.as_str(), m.body.as_str()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn lines() {
+        let runner = runner(serde_json::json!({ "command": "printf 'a\\n\\n  b  \\n'" }));
+        let msgs = runner.run_once().await;
+        assert_eq!(bodies(&msgs), [("cmd/0", "a"), ("cmd/1", "  b")]);
+        assert_eq!(msgs[0].tags, ["t"]);
+    }
+
+    #[tokio::test]
+    async fn json() {
+        let runner = runner(serde_json::json!({
+            "command": r#"echo '{"id": "x", "body": "a"} {"id": "y", "body": "b", "tags": ["u"]}'"#,
+            "format": "json",
+        }));
+        let msgs = runner.run_once().await;
+        assert_eq!(bodies(&msgs), [("cmd/x", "a"), ("cmd/y", "b")]);
+        assert_eq!(msgs[0].tags, ["t"]);
+        assert_eq!(msgs[1].tags, ["u"]);
+
+        let runner = self::runner(serde_json::json!({ "command": "echo nope", "format": "json" }));
+        let msgs = runner.run_once().await;
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0].id, "cmd/error");
+        assert!(msgs[0].body.starts_with("cmd: invalid output: "));
+    }
+
+    #[tokio::test]
+    async fn clear_codes() {
+        let runner = runner(serde_json::json!({
+            "command": ["sh", "-c", "echo dirty; exit 1"],
+            "clear_codes": [1],
+        }));
+        assert!(runner.run_once().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn failing_without_output() {
+        let runner = runner(serde_json::json!({ "command": "echo oops >&2; echo >&2; exit 3" }));
+        let msgs = runner.run_once().await;
+        assert_eq!(bodies(&msgs), [("cmd/error", "cmd: exited with 3: oops")]);
+
+        let runner = self::runner(serde_json::json!({ "command": "exit 4" }));
+        let msgs = runner.run_once().await;
+        assert_eq!(bodies(&msgs), [("cmd/error", "cmd: exited with 4")]);
+
+        // Output is shown as is, and succeeding silently is all clear
+        let runner = self::runner(serde_json::json!({ "command": "echo out; exit 3" }));
+        assert_eq!(bodies(&runner.run_once().await), [("cmd/0", "out")]);
+        let runner = self::runner(serde_json::json!({ "command": "true" }));
+        assert!(runner.run_once().await.is_empty());
+    }
+}
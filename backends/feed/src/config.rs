@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+
+use nadir_backend_common::FrontendConfig;
+use nadir_types::model::MessageGroup;
+use serde::Deserialize;
+
+/// Config file for this backend
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    /// The frontend to send messages to.
+    pub frontend: FrontendConfig,
+
+    /// Where IDs of already seen entries are stored.
+    #[serde(default = "default_state_file")]
+    pub state_file: PathBuf,
+
+    /// Groups created at start.
+    #[serde(default)]
+    pub groups: Vec<MessageGroup>,
+
+    /// Feeds to poll.
+    #[serde(default)]
+    pub feeds: Vec<FeedConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeedConfig {
+    /// An `http(s)://` URL or a local file path of an RSS or Atom feed.
+    pub source: String,
+
+    /// The group that entries of this feed go into.
+    pub group: String,
+
+    /// Tag shown beside entries. Defaults to the title of the feed.
+    #[serde(default)]
+    pub title: Option<String>,
+
+    /// Seconds between two polls.
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+fn default_state_file() -> PathBuf {
+    "./nadir-feed-state.json".into()
+}
+
+fn default_interval() -> u64 {
+    900
+}
@@ -0,0 +1,163 @@
+//! A backend that polls RSS and Atom feeds and shows new entries.
+//!
+//! The counter of each group shows how many entries arrived since the user
+//! last clicked on a message in it.
+//!
+//! ```toml
+//! [frontend]
+//! url = "ws://localhost:7890"
+//!
+//! [[groups]]
+//! id = "news"
+//! title = "News"
+//!
+//! [[feeds]]
+//! source = "https://blog.rust-lang.org/feed.xml"
+//! group = "news"
+//! interval = 1800
+//! ```
+mod config;
+mod state;
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use clap::Clap;
+use log::warn;
+use nadir_backend_common::{click, err_and_exit, Link, Opt};
+use nadir_types::model::Message;
+use parking_lot::Mutex;
+use url::Url;
+
+use self::{
+    config::{Config, FeedConfig},
+    state::SeenState,
+};
+
+struct Shared {
+    link: Link,
+    seen: Mutex<SeenState>,
+    /// Unread entries of each group
+    unread: Mutex<HashMap<String, u64>>,
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let opt = Opt::parse();
+    let config: Config = opt.load_config("./nadir-feed.toml");
+
+    let seen = SeenState::load(&config.state_file).unwrap_or_else(|e| {
+        err_and_exit(format_args!(
+            "Cannot read state file at '{}'.\nReason: {}",
+            config.state_file.display(),
+            e
+        ))
+    });
+
+    let (link, mut frontend) = Link::spawn(config.frontend);
+    for group in config.groups {
+        link.put_group(group);
+    }
+
+    let shared = Arc::new(Shared {
+        link,
+        seen: Mutex::new(seen),
+        unread: Mutex::new(HashMap::new()),
+    });
+    let client = reqwest::Client::new();
+    for feed in config.feeds {
+        tokio::spawn(poll_feed(feed, client.clone(), shared.clone()));
+    }
+
+    while let Some(msg) = frontend.recv().await {
+        if let Some(action) = click(msg) {
+            let mut unread = shared.unread.lock();
+            if let Some(count) = unread.get_mut(&action.group) {
+                *count = 0;
+                shared.link.set_counter(action.group, 0);
+            }
+        }
+    }
+}
+
+async fn poll_feed(config: FeedConfig, client: reqwest::Client, shared: Arc<Shared>) {
+    let mut timer = tokio::time::interval(Duration::from_secs(config.interval.max(1)));
+    loop {
+        timer.tick().await;
+        let feed = match fetch(&config.source, &client).await {
+            Ok(feed) => feed,
+            Err(e) => {
+                warn!("failed to fetch {}: {}", config.source, e);
+                continue;
+            }
+        };
+
+        let feed_title = feed.title.map(|t| t.content);
+        let tag = config
+            .title
+            .clone()
+            .or(feed_title)
+            .unwrap_or_else(|| config.source.clone());
+
+        let mut new_entries = vec![];
+        {
+            let mut seen = shared.seen.lock();
+            for entry in feed.entries {
+                if seen.is_seen(&config.source, &entry.id) {
+                    continue;
+                }
+                seen.mark_seen(&config.source, entry.id.clone());
+                new_entries.push(Message {
+                    // Entry IDs are only unique within a feed, and feeds may
+                    // share a group
+                    id: format!("{}/{}", config.source, entry.id),
+                    counter: None,
+                    tags: vec![tag.clone()],
+                    body: entry
+                        .title
+                        .or(entry.summary)
+                        .map(|t| t.content)
+                        .unwrap_or_default(),
+                    time: entry.published.or(entry.updated),
+                });
+            }
+            if new_entries.is_empty() {
+                continue;
+            }
+            if let Err(e) = seen.save() {
+                warn!("failed to save state: {}", e);
+            }
+        }
+
+        // The last message is shown first, so put the newest one last
+        new_entries.sort_by_key(|m| m.time);
+        let count = new_entries.len() as u64;
+        shared.link.put(config.group.as_str(), new_entries);
+
+        let mut unread = shared.unread.lock();
+        let unread = unread.entry(config.group.clone()).or_insert(0);
+        *unread += count;
+        shared.link.set_counter(config.group.as_str(), *unread);
+    }
+}
+
+async fn fetch(source: &str, client: &reqwest::Client) -> Result<feed_rs::model::Feed, String> {
+    let body = match Url::parse(source) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => client
+            .get(url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| e.to_string())?
+            .bytes()
+            .await
+            .map_err(|e| e.to_string())?
+            .to_vec(),
+        Ok(url) if url.scheme() == "file" => {
+            let path = url.to_file_path().map_err(|_| "invalid file URL")?;
+            tokio::fs::read(path).await.map_err(|e| e.to_string())?
+        }
+        _ => tokio::fs::read(source).await.map_err(|e| e.to_string())?,
+    };
+    feed_rs::parser::parse(body.as_slice()).map_err(|e| e.to_string())
+}
@@ -0,0 +1,51 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// At most this many entry IDs are remembered for each feed.
+const MAX_SEEN: usize = 1000;
+
+/// Entry IDs already posted, stored across restarts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SeenState {
+    #[serde(skip)]
+    path: PathBuf,
+    /// Feed source to entry IDs, oldest first
+    feeds: HashMap<String, VecDeque<String>>,
+}
+
+impl SeenState {
+    /// Load the state file, or start with an empty state if it doesn't exist.
+    pub fn load(path: &Path) -> std::io::Result<SeenState> {
+        let mut state: SeenState = match std::fs::read(path) {
+            Ok(file) => serde_json::from_slice(&file)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => SeenState::default(),
+            Err(e) => return Err(e),
+        };
+        state.path = path.to_owned();
+        Ok(state)
+    }
+
+    pub fn is_seen(&self, feed: &str, id: &str) -> bool {
+        self.feeds
+            .get(feed)
+            .is_some_and(|ids| ids.iter().any(|x| x == id))
+    }
+
+    pub fn mark_seen(&mut self, feed: &str, id: String) {
+        let ids = self.feeds.entry(feed.to_owned()).or_default();
+        ids.push_back(id);
+        while ids.len() > MAX_SEEN {
+            ids.pop_front();
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec(self)?)?;
+        std::fs::rename(tmp, &self.path)
+    }
+}
@@ -0,0 +1,52 @@
+use std::path::PathBuf;
+
+use nadir_backend_common::FrontendConfig;
+use nadir_types::model::MessageGroup;
+use serde::Deserialize;
+
+/// Config file for this backend
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    /// The frontend to send messages to.
+    pub frontend: FrontendConfig,
+
+    /// Groups created at start. Groups of watches not listed here are created
+    /// with their ID as the title.
+    #[serde(default)]
+    pub groups: Vec<MessageGroup>,
+
+    /// Directories to watch.
+    #[serde(default)]
+    pub watches: Vec<WatchConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WatchConfig {
+    /// The directory to watch.
+    pub path: PathBuf,
+
+    /// The group that messages of this directory go into.
+    pub group: String,
+
+    /// Also watch subdirectories.
+    #[serde(default)]
+    pub recursive: bool,
+
+    /// Only files matching one of these globs are shown. Globs are matched
+    /// against paths relative to the watched directory. Defaults to every
+    /// file.
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    /// Files matching one of these globs are never shown.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+
+    /// Tag shown beside files. Defaults to the directory containing the file.
+    #[serde(default)]
+    pub tag: Option<String>,
+
+    /// Show files that already exist at start.
+    #[serde(default)]
+    pub existing: bool,
+}
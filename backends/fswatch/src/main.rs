@@ -0,0 +1,85 @@
+//! A backend that watches directories with inotify and shows new or modified
+//! files.
+//!
+//! Every file is a message with its name as the body and its directory as the
+//! tag. The message is removed when the file is deleted or moved away.
+//!
+//! ```toml
+//! [frontend]
+//! url = "ws://localhost:7890"
+//!
+//! [[watches]]
+//! path = "/home/me/Downloads"
+//! group = "downloads"
+//! exclude = ["*.part", "*.crdownload"]
+//!
+//! [[watches]]
+//! path = "/srv/build/artifacts"
+//! group = "builds"
+//! recursive = true
+//! include = ["**/*.tar.gz"]
+//! ```
+mod config;
+mod watcher;
+
+use std::collections::HashSet;
+
+use clap::Clap;
+use futures::StreamExt;
+use inotify::Inotify;
+use nadir_backend_common::{err_and_exit, Link, Opt};
+use nadir_types::model::MessageGroup;
+
+use self::{
+    config::Config,
+    watcher::{Watch, Watcher},
+};
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let opt = Opt::parse();
+    let config: Config = opt.load_config("./nadir-fswatch.toml");
+
+    let (link, mut frontend) = Link::spawn(config.frontend);
+    let mut known_groups = HashSet::new();
+    for group in config.groups {
+        known_groups.insert(group.id.clone());
+        link.put_group(group);
+    }
+    // Create groups that aren't configured
+    for watch in &config.watches {
+        if known_groups.insert(watch.group.clone()) {
+            link.put_group(MessageGroup {
+                id: watch.group.clone(),
+                title: watch.group.clone(),
+                ..Default::default()
+            });
+        }
+    }
+
+    let watches = config
+        .watches
+        .into_iter()
+        .map(Watch::new)
+        .collect::<Result<Vec<_>, String>>()
+        .unwrap_or_else(|e| err_and_exit(format_args!("Invalid glob.\nReason: {}", e)));
+
+    let mut events = Inotify::init()
+        .and_then(|i| i.into_event_stream([0; 4096]))
+        .unwrap_or_else(|e| err_and_exit(format_args!("Cannot use inotify.\nReason: {}", e)));
+    let mut watcher = Watcher::new(link, events.watches(), watches);
+    watcher.start();
+
+    tokio::spawn(async move {
+        // This backend doesn't react to the frontend
+        while frontend.recv().await.is_some() {}
+    });
+
+    while let Some(event) = events.next().await {
+        match event {
+            Ok(event) => watcher.handle(event),
+            Err(e) => err_and_exit(format_args!("Cannot read inotify events.\nReason: {}", e)),
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use inotify::{Event, EventMask, WatchDescriptor, WatchMask, Watches};
+use log::{debug, warn};
+use nadir_backend_common::Link;
+use nadir_types::model::Message;
+
+use crate::config::WatchConfig;
+
+/// A compiled [`WatchConfig`].
+pub struct Watch {
+    config: WatchConfig,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    /// IDs of messages currently shown.
+    shown: HashSet<String>,
+}
+
+impl Watch {
+    pub fn new(config: WatchConfig) -> Result<Watch, String> {
+        let include = if config.include.is_empty() {
+            None
+        } else {
+            Some(glob_set(&config.include)?)
+        };
+        let exclude = glob_set(&config.exclude)?;
+        Ok(Watch {
+            config,
+            include,
+            exclude,
+            shown: HashSet::new(),
+        })
+    }
+
+    fn is_match(&self, rel: &Path) -> bool {
+        self.include.as_ref().is_none_or(|i| i.is_match(rel)) && !self.exclude.is_match(rel)
+    }
+}
+
+fn glob_set(globs: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob).map_err(|e| e.to_string())?);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Keeps one message for every matching file in the watched directories.
+pub struct Watcher {
+    link: Link,
+    handle: Watches,
+    watches: Vec<Watch>,
+    /// Watched directories, and the watches they belong to.
+    dirs: HashMap<WatchDescriptor, Vec<(usize, PathBuf)>>,
+}
+
+impl Watcher {
+    pub fn new(link: Link, handle: Watches, watches: Vec<Watch>) -> Self {
+        Watcher {
+            link,
+            handle,
+            watches,
+            dirs: HashMap::new(),
+        }
+    }
+
+    /// Start watching the configured directories.
+    pub fn start(&mut self) {
+        for i in 0..self.watches.len() {
+            let root = self.watches[i].config.path.clone();
+            let existing = self.watches[i].config.existing;
+            if let Err(e) = self.add_dir(i, &root, existing) {
+                warn!("cannot watch {}: {}", root.display(), e);
+            }
+        }
+    }
+
+    /// Watch a directory, and its subdirectories if the watch is recursive.
+    /// Files already in them are shown if `announce` is set.
+    fn add_dir(&mut self, watch: usize, dir: &Path, announce: bool) -> io::Result<()> {
+        let wd = self.handle.add(dir, dir_mask())?;
+        let dirs = self.dirs.entry(wd).or_default();
+        if !dirs.iter().any(|(w, _)| *w == watch) {
+            dirs.push((watch, dir.to_owned()));
+        }
+        debug!("watching {}", dir.display());
+
+        let mut files = vec![];
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                if self.watches[watch].config.recursive {
+                    if let Err(e) = self.add_dir(watch, &path, announce) {
+                        warn!("cannot watch {}: {}", path.display(), e);
+                    }
+                }
+            } else if announce {
+                files.extend(self.message(watch, &path));
+            }
+        }
+        self.show(watch, files);
+        Ok(())
+    }
+
+    pub fn handle(&mut self, event: Event<OsString>) {
+        if event.mask.contains(EventMask::IGNORED) {
+            // The directory is gone
+            self.dirs.remove(&event.wd);
+            return;
+        }
+        let name = match &event.name {
+            Some(name) => name,
+            None => return,
+        };
+        let dirs = match self.dirs.get(&event.wd) {
+            Some(dirs) => dirs.clone(),
+            None => return,
+        };
+
+        for (watch, dir) in dirs {
+            let path = dir.join(name);
+            if event.mask.contains(EventMask::ISDIR) {
+                self.handle_dir(watch, &path, event.mask);
+            } else if event
+                .mask
+                .intersects(EventMask::CLOSE_WRITE | EventMask::MOVED_TO)
+            {
+                let msg = self.message(watch, &path);
+                self.show(watch, msg.into_iter().collect());
+            } else if event
+                .mask
+                .intersects(EventMask::DELETE | EventMask::MOVED_FROM)
+            {
+                let id = self.id(watch, &path);
+                self.hide(watch, |shown| *shown == id);
+            }
+        }
+    }
+
+    fn handle_dir(&mut self, watch: usize, path: &Path, mask: EventMask) {
+        if !self.watches[watch].config.recursive {
+            return;
+        }
+        if mask.intersects(EventMask::CREATE | EventMask::MOVED_TO) {
+            // Files in a new directory are new too
+            if let Err(e) = self.add_dir(watch, path, true) {
+                warn!("cannot watch {}: {}", path.display(), e);
+            }
+        } else if mask.contains(EventMask::MOVED_FROM) {
+            // Deleted directories are cleaned up by events of their files, but
+            // moved ones need to be forgotten here
+            let prefix = format!("{}/", self.id(watch, path));
+            self.hide(watch, |shown| shown.starts_with(&prefix));
+
+            let moved: Vec<_> = self
+                .dirs
+                .iter()
+                .filter(|(_, dirs)| dirs.iter().any(|(w, d)| *w == watch && d.starts_with(path)))
+                .map(|(wd, _)| wd.clone())
+                .collect();
+            for wd in moved {
+                let _ = self.handle.remove(wd.clone());
+                self.dirs.remove(&wd);
+            }
+        }
+    }
+
+    /// The message of a file, if it should be shown.
+    fn message(&self, watch: usize, path: &Path) -> Option<Message> {
+        let w = &self.watches[watch];
+        let rel = path.strip_prefix(&w.config.path).ok()?;
+        if !w.is_match(rel) {
+            return None;
+        }
+        let meta = match fs::metadata(path) {
+            Ok(m) if m.is_file() => m,
+            // Already gone, or not a regular file
+            _ => return None,
+        };
+        let tag = match &w.config.tag {
+            Some(tag) => tag.clone(),
+            None => display_dir(path.parent()?),
+        };
+        Some(Message {
+            id: self.id(watch, path),
+            counter: None,
+            tags: vec![tag],
+            body: path.file_name()?.to_string_lossy().into_owned(),
+            time: meta.modified().ok().map(DateTime::<Utc>::from),
+        })
+    }
+
+    /// Message ID of a file, its path relative to the watched directory.
+    fn id(&self, watch: usize, path: &Path) -> String {
+        let root = &self.watches[watch].config.path;
+        path.strip_prefix(root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn show(&mut self, watch: usize, mut msgs: Vec<Message>) {
+        // The last message is shown first, so put the newest one last
+        msgs.sort_by_key(|m| m.time);
+        let w = &mut self.watches[watch];
+        w.shown.extend(msgs.iter().map(|m| m.id.clone()));
+        self.link.put(w.config.group.as_str(), msgs);
+    }
+
+    fn hide(&mut self, watch: usize, pred: impl Fn(&String) -> bool) {
+        let w = &mut self.watches[watch];
+        let ids: Vec<_> = w.shown.iter().filter(|id| pred(id)).cloned().collect();
+        for id in &ids {
+            w.shown.remove(id);
+        }
+        self.link.remove(w.config.group.as_str(), ids);
+    }
+}
+
+fn dir_mask() -> WatchMask {
+    WatchMask::CLOSE_WRITE
+        | WatchMask::CREATE
+        | WatchMask::DELETE
+        | WatchMask::MOVED_FROM
+        | WatchMask::MOVED_TO
+        | WatchMask::ONLYDIR
+}
+
+/// Show directories under home as `~/...`.
+fn display_dir(dir: &Path) -> String {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    match home.as_deref().and_then(|h| dir.strip_prefix(h).ok()) {
+        Some(rel) if rel.as_os_str().is_empty() => "~".into(),
+        Some(rel) => format!("~/{}", rel.display()),
+        None => dir.display().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watch(include: &[&str], exclude: &[&str]) -> Result<Watch, String> {
+        let globs = |g: &[&str]| g.iter().map(|g| g.to_string()).collect();
+        Watch::new(WatchConfig {
+            path: "/downloads".into(),
+            group: "downloads".into(),
+            recursive: true,
+            include: globs(include),
+            exclude: globs(exclude),
+            tag: None,
+            existing: false,
+        })
+    }
+
+    #[test]
+    fn globs() {
+        let all = watch(&[], &[]).unwrap();
+        assert!(all.is_match(Path::new("a.txt")));
+        assert!(all.is_match(Path::new("sub/.hidden")));
+
+        let watch = watch(&["*.pdf", "books/**"], &["*.part", "**/.*"]).unwrap();
+        assert!(watch.is_match(Path::new("paper.pdf")));
+        // `*` also matches across directories
+        assert!(watch.is_match(Path::new("sub/paper.pdf")));
+        assert!(watch.is_match(Path::new("books/novel.epub")));
+        assert!(!watch.is_match(Path::new("music.flac")));
+        assert!(!watch.is_match(Path::new("books/novel.epub.part")));
+        assert!(!watch.is_match(Path::new("books/.cache")));
+    }
+
+    #[test]
+    fn invalid_glob() {
+        assert!(watch(&["a[b"], &[]).is_err());
+        assert!(watch(&[], &["{a,b"]).is_err());
+    }
+}
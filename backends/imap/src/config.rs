@@ -0,0 +1,72 @@
+use nadir_backend_common::FrontendConfig;
+use nadir_types::model::MessageGroup;
+use serde::Deserialize;
+
+/// Config file for this backend
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    /// The frontend to send messages to.
+    pub frontend: FrontendConfig,
+
+    /// Groups created at start. Groups of accounts that aren't listed here are
+    /// created with their ID as the title.
+    #[serde(default)]
+    pub groups: Vec<MessageGroup>,
+
+    /// IMAP accounts to watch.
+    pub accounts: Vec<AccountConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccountConfig {
+    pub host: String,
+
+    /// Defaults to 993 with TLS and 143 without.
+    #[serde(default)]
+    pub port: Option<u16>,
+
+    /// Connect with implicit TLS. STARTTLS isn't supported.
+    #[serde(default = "default_tls")]
+    pub tls: bool,
+
+    pub username: String,
+
+    pub password: String,
+
+    /// The group messages of this account go into.
+    pub group: String,
+
+    /// Folders to watch, each with its own connection.
+    #[serde(default = "default_folders")]
+    pub folders: Vec<String>,
+
+    /// How many of the newest unread messages of a folder are shown.
+    #[serde(default = "default_recent")]
+    pub recent: usize,
+
+    /// Seconds between two checks on servers without IDLE support.
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: u64,
+}
+
+impl AccountConfig {
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(if self.tls { 993 } else { 143 })
+    }
+}
+
+fn default_tls() -> bool {
+    true
+}
+
+fn default_folders() -> Vec<String> {
+    vec!["INBOX".into()]
+}
+
+fn default_recent() -> usize {
+    5
+}
+
+fn default_poll_interval() -> u64 {
+    60
+}
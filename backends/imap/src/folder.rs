@@ -0,0 +1,390 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io,
+    sync::Arc,
+    time::Duration,
+};
+
+use async_imap::{error::Result, imap_proto::types::Envelope, types::Fetch, Client};
+use chrono::Utc;
+use futures::TryStreamExt;
+use log::{info, warn};
+use nadir_backend_common::Link;
+use nadir_types::model::Message;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::{config::AccountConfig, mime::decode_header, stream::Stream};
+
+/// Servers may drop IDLE connections after 30 minutes (RFC 2177).
+const IDLE_TIMEOUT: Duration = Duration::from_secs(29 * 60);
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+type Session = async_imap::Session<Stream>;
+
+/// Keeps the unread count and newest unread messages of one folder.
+///
+/// The unread count is a pinned message with the folder name as its ID. Unread
+/// messages have IDs like `INBOX/42`, with their UID after the folder name.
+pub struct Folder {
+    link: Link,
+    account: Arc<AccountConfig>,
+    name: String,
+
+    /// Unread messages shown, by UID.
+    shown: BTreeMap<u32, Message>,
+    /// The unread count shown, 0 if none is.
+    unread: usize,
+    /// UIDs are only valid as long as this stays the same.
+    uid_validity: Option<u32>,
+}
+
+impl Folder {
+    pub fn new(link: Link, account: Arc<AccountConfig>, name: String) -> Self {
+        Folder {
+            link,
+            account,
+            name,
+            shown: BTreeMap::new(),
+            unread: 0,
+            uid_validity: None,
+        }
+    }
+
+    /// Watch the folder forever. `seen` receives UIDs of messages to mark as
+    /// seen.
+    pub async fn run(mut self, mut seen: UnboundedReceiver<u32>) {
+        loop {
+            if let Err(e) = self.watch(&mut seen).await {
+                warn!(
+                    "{} on {}: {}, reconnecting in {}s",
+                    self.name,
+                    self.account.host,
+                    e,
+                    RECONNECT_DELAY.as_secs()
+                );
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn watch(&mut self, seen: &mut UnboundedReceiver<u32>) -> Result<()> {
+        let mut session = self.connect().await?;
+        let mailbox = session.select(&self.name).await?;
+        if mailbox.uid_validity != self.uid_validity {
+            // Shown messages may be different ones with the same UIDs now
+            let ids = self.shown.keys().map(|uid| self.id(*uid)).collect();
+            self.link.remove(self.account.group.as_str(), ids);
+            self.shown.clear();
+            self.uid_validity = mailbox.uid_validity;
+        }
+        let idle = session.capabilities().await?.has_str("IDLE");
+        info!(
+            "watching {} on {}{}",
+            self.name,
+            self.account.host,
+            if idle { "" } else { " by polling" }
+        );
+
+        loop {
+            self.refresh(&mut session).await?;
+            let uid = if idle {
+                let mut handle = session.idle();
+                handle.init().await?;
+                let (wait, _stop) = handle.wait_with_timeout(IDLE_TIMEOUT);
+                let uid = tokio::select! {
+                    res = wait => {
+                        res?;
+                        None
+                    }
+                    uid = seen.recv() => uid,
+                };
+                session = handle.done().await?;
+                uid
+            } else {
+                let poll = Duration::from_secs(self.account.poll_interval.max(1));
+                tokio::select! {
+                    _ = tokio::time::sleep(poll) => None,
+                    uid = seen.recv() => uid,
+                }
+            };
+
+            if let Some(uid) = uid {
+                session
+                    .uid_store(uid.to_string(), "+FLAGS (\\Seen)")
+                    .await?
+                    .try_collect::<Vec<_>>()
+                    .await?;
+            }
+        }
+    }
+
+    async fn connect(&self) -> Result<Session> {
+        let account = &self.account;
+        let stream = Stream::connect(&account.host, account.port(), account.tls).await?;
+        let mut client = Client::new(stream);
+        client.read_response().await.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "closed before greeting")
+        })??;
+        client
+            .login(&account.username, &account.password)
+            .await
+            .map_err(|(e, _)| e)
+    }
+
+    /// Send the difference between shown messages and the server.
+    async fn refresh(&mut self, session: &mut Session) -> Result<()> {
+        let unseen = session.uid_search("UNSEEN").await?;
+        let mut newest: Vec<_> = unseen.iter().copied().collect();
+        newest.sort_unstable_by(|a, b| b.cmp(a));
+        newest.truncate(self.account.recent);
+
+        let missing: Vec<_> = newest
+            .iter()
+            .filter(|uid| !self.shown.contains_key(uid))
+            .map(|uid| uid.to_string())
+            .collect();
+        let mut added = vec![];
+        if !missing.is_empty() {
+            let fetches: Vec<Fetch> = session
+                .uid_fetch(missing.join(","), "(UID ENVELOPE INTERNALDATE)")
+                .await?
+                .try_collect()
+                .await?;
+            added.extend(fetches.iter().filter_map(|f| self.message(f)));
+        }
+        // Nothing uses untagged responses, they are only drained so the
+        // session doesn't block on them
+        while session.unsolicited_responses.try_recv().is_ok() {}
+
+        let newest: HashSet<_> = newest.into_iter().collect();
+        let removed: Vec<_> = self
+            .shown
+            .keys()
+            .filter(|uid| !newest.contains(uid))
+            .copied()
+            .collect();
+        for uid in &removed {
+            self.shown.remove(uid);
+        }
+        self.link.remove(
+            self.account.group.as_str(),
+            removed.iter().map(|uid| self.id(*uid)).collect(),
+        );
+
+        // The last message is shown first, so put the newest one last
+        added.sort_by_key(|(uid, _)| *uid);
+        for (uid, msg) in &added {
+            self.shown.insert(*uid, msg.clone());
+        }
+        self.link.put(
+            self.account.group.as_str(),
+            added.into_iter().map(|(_, msg)| msg).collect(),
+        );
+
+        if unseen.len() != self.unread {
+            self.unread = unseen.len();
+            if self.unread == 0 {
+                self.link
+                    .remove_pinned(self.account.group.as_str(), vec![self.name.clone()]);
+            } else {
+                self.link.put_pinned(
+                    self.account.group.as_str(),
+                    vec![Message {
+                        id: self.name.clone(),
+                        counter: Some(self.unread as u64),
+                        tags: vec![],
+                        body: format!("Unread in {}", self.name),
+                        time: Some(Utc::now()),
+                    }],
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn message(&self, fetch: &Fetch) -> Option<(u32, Message)> {
+        let uid = fetch.uid?;
+        let envelope = fetch.envelope();
+        let subject = envelope
+            .and_then(|e| e.subject.as_deref())
+            .map(decode_header)
+            .filter(|s| !s.trim().is_empty())
+            .unwrap_or_else(|| "(no subject)".into());
+        let msg = Message {
+            id: self.id(uid),
+            counter: None,
+            tags: envelope.and_then(sender).into_iter().collect(),
+            body: subject,
+            time: fetch.internal_date().map(|t| t.with_timezone(&Utc)),
+        };
+        Some((uid, msg))
+    }
+
+    fn id(&self, uid: u32) -> String {
+        format!("{}/{}", self.name, uid)
+    }
+}
+
+/// The name of the first sender, or their address if there's no name.
+fn sender(envelope: &Envelope) -> Option<String> {
+    let from = envelope.from.as_ref()?.first()?;
+    if let Some(name) = from.name.as_deref().map(decode_header) {
+        if !name.trim().is_empty() {
+            return Some(name);
+        }
+    }
+    let mailbox = String::from_utf8_lossy(from.mailbox.as_deref()?);
+    Some(match from.host.as_deref() {
+        Some(host) => format!("{}@{}", mailbox, String::from_utf8_lossy(host)),
+        None => mailbox.into_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use nadir_backend_common::FrontendConfig;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Messages on the fake server by UID, with whether they are seen.
+    type Mailbox = Arc<Mutex<BTreeMap<u32, (bool, &'static str)>>>;
+
+    /// Serve one folder over plain IMAP, answering only what `Folder` asks.
+    async fn serve(mailbox: Mailbox) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (conn, _) = listener.accept().await.unwrap();
+            let (read, mut write) = conn.into_split();
+            let mut lines = BufReader::new(read).lines();
+            write.write_all(b"* OK ready\r\n").await.unwrap();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let (tag, command) = line.split_once(' ').unwrap();
+                let reply = respond(&mailbox, command);
+                let reply = format!("{}{} OK done\r\n", reply, tag);
+                write.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+        port
+    }
+
+    fn respond(mailbox: &Mailbox, command: &str) -> String {
+        let mut mailbox = mailbox.lock().unwrap();
+        let seq =
+            |mailbox: &BTreeMap<_, _>, uid| mailbox.keys().position(|u| *u == uid).unwrap() + 1;
+        let uids = |set: &str| {
+            set.split(',')
+                .map(|u| u.parse().unwrap())
+                .collect::<Vec<u32>>()
+        };
+        let words: Vec<_> = command.split(' ').collect();
+        match words[..] {
+            ["SELECT", _] => format!(
+                "* {} EXISTS\r\n* OK [UIDVALIDITY 7] UIDs valid\r\n",
+                mailbox.len()
+            ),
+            ["UID", "SEARCH", "UNSEEN"] => {
+                let unseen: Vec<_> = mailbox
+                    .iter()
+                    .filter(|(_, (seen, _))| !seen)
+                    .map(|(uid, _)| format!(" {}", uid))
+                    .collect();
+                format!("* SEARCH{}\r\n", unseen.concat())
+            }
+            ["UID", "FETCH", set, ..] => uids(set)
+                .into_iter()
+                .map(|uid| {
+                    format!(
+                        "* {} FETCH (UID {} INTERNALDATE \"01-Mar-2024 10:00:00 +0000\" \
+                         ENVELOPE (NIL \"{}\" ((\"=?UTF-8?Q?Ren=C3=A9?=\" NIL \"rene\" \
+                         \"example.org\")) NIL NIL NIL NIL NIL NIL NIL))\r\n",
+                        seq(&mailbox, uid),
+                        uid,
+                        mailbox[&uid].1
+                    )
+                })
+                .collect(),
+            ["UID", "STORE", set, "+FLAGS", "(\\Seen)"] => uids(set)
+                .into_iter()
+                .map(|uid| {
+                    mailbox.get_mut(&uid).unwrap().0 = true;
+                    format!(
+                        "* {} FETCH (UID {} FLAGS (\\Seen))\r\n",
+                        seq(&mailbox, uid),
+                        uid
+                    )
+                })
+                .collect(),
+            _ => String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn sync_unread() {
+        let mailbox: Mailbox = Arc::new(Mutex::new(
+            vec![
+                (1, (false, "first")),
+                (2, (true, "seen")),
+                (3, (false, "=?UTF-8?B?w6l0w6k=?=")),
+                (4, (false, "newest")),
+            ]
+            .into_iter()
+            .collect(),
+        ));
+        let port = serve(mailbox.clone()).await;
+        let (link, _) = Link::spawn(FrontendConfig {
+            url: "ws://127.0.0.1:1".parse().unwrap(),
+            secret: None,
+            tls_cert: None,
+        });
+        let account = AccountConfig {
+            host: "127.0.0.1".into(),
+            port: Some(port),
+            tls: false,
+            username: "me".into(),
+            password: "secret".into(),
+            group: "mail".into(),
+            folders: vec!["INBOX".into()],
+            recent: 2,
+            poll_interval: 60,
+        };
+        let mut folder = Folder::new(link, Arc::new(account), "INBOX".into());
+        let mut session = folder.connect().await.unwrap();
+        session.select("INBOX").await.unwrap();
+
+        // Only the newest unread messages are shown
+        folder.refresh(&mut session).await.unwrap();
+        assert_eq!(folder.shown.keys().collect::<Vec<_>>(), [&3, &4]);
+        assert_eq!(folder.unread, 3);
+        let msg = &folder.shown[&3];
+        assert_eq!(msg.id, "INBOX/3");
+        assert_eq!(msg.body, "été");
+        assert_eq!(msg.tags, ["René"]);
+        assert_eq!(msg.time.unwrap().to_rfc3339(), "2024-03-01T10:00:00+00:00");
+
+        // Seen elsewhere, so the next unread one takes its place
+        mailbox.lock().unwrap().get_mut(&4).unwrap().0 = true;
+        folder.refresh(&mut session).await.unwrap();
+        assert_eq!(folder.shown.keys().collect::<Vec<_>>(), [&1, &3]);
+        assert_eq!(folder.unread, 2);
+
+        // Marked as seen from the frontend
+        session
+            .uid_store("3", "+FLAGS (\\Seen)")
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert!(mailbox.lock().unwrap()[&3].0);
+        folder.refresh(&mut session).await.unwrap();
+        assert_eq!(folder.shown.keys().collect::<Vec<_>>(), [&1]);
+        assert_eq!(folder.unread, 1);
+    }
+}
@@ -0,0 +1,93 @@
+//! A backend that watches folders on IMAP servers with IDLE.
+//!
+//! Every folder with unread mail has a pinned message counting it, followed by
+//! the subjects of its newest unread messages. Activating a message marks it as
+//! seen on the server.
+//!
+//! ```toml
+//! [frontend]
+//! url = "ws://localhost:7890"
+//!
+//! [[accounts]]
+//! host = "imap.example.com"
+//! username = "me@example.com"
+//! password = "hunter2"
+//! group = "mail"
+//! folders = ["INBOX", "Lists/rust"]
+//! recent = 3
+//! ```
+mod config;
+mod folder;
+mod mime;
+mod stream;
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use clap::Clap;
+use nadir_backend_common::{click, err_and_exit, Link, Opt};
+use nadir_types::model::MessageGroup;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+use self::{config::Config, folder::Folder};
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let opt = Opt::parse();
+    let config: Config = opt.load_config("./nadir-imap.toml");
+
+    let (link, mut frontend) = Link::spawn(config.frontend);
+    let mut known_groups = HashSet::new();
+    for group in config.groups {
+        known_groups.insert(group.id.clone());
+        link.put_group(group);
+    }
+
+    // Senders of UIDs to mark as seen, by group and folder
+    let mut folders: HashMap<(String, String), UnboundedSender<u32>> = HashMap::new();
+    for account in config.accounts {
+        // Create groups that aren't configured
+        if known_groups.insert(account.group.clone()) {
+            link.put_group(MessageGroup {
+                id: account.group.clone(),
+                title: account.group.clone(),
+                ..Default::default()
+            });
+        }
+        let account = Arc::new(account);
+        for name in &account.folders {
+            let key = (account.group.clone(), name.clone());
+            if folders.contains_key(&key) {
+                err_and_exit(format_args!(
+                    "Folder {} is watched twice in group {}.\nReason: message IDs would clash",
+                    name, account.group
+                ));
+            }
+            let (tx, rx) = unbounded_channel();
+            folders.insert(key, tx);
+            let folder = Folder::new(link.clone(), account.clone(), name.clone());
+            tokio::spawn(folder.run(rx));
+        }
+    }
+
+    while let Some(msg) = frontend.recv().await {
+        if let Some(action) = click(msg) {
+            // Message IDs are the folder and the UID, and folders may contain
+            // slashes themselves
+            let (name, uid) = match action.message.rsplit_once('/') {
+                Some(x) => x,
+                None => continue,
+            };
+            let uid = match uid.parse() {
+                Ok(uid) => uid,
+                Err(_) => continue,
+            };
+            if let Some(tx) = folders.get(&(action.group, name.to_owned())) {
+                let _ = tx.send(uid);
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! Decoding of MIME encoded words (RFC 2047) in headers.
+use base64::Engine;
+
+/// Decode encoded words like `=?UTF-8?B?...?=` in a header value. Charsets
+/// other than UTF-8, ASCII and Latin-1 are decoded as UTF-8 lossily.
+pub fn decode_header(raw: &[u8]) -> String {
+    let s = String::from_utf8_lossy(raw);
+    let mut res = String::new();
+    let mut rest = &*s;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let word = rest[start..].get(2..).and_then(decode_word);
+        let (text, len, decoded) = match word {
+            Some((decoded, len)) => (decoded, len + 2, true),
+            None => ("=?".to_owned(), 2, false),
+        };
+        let between = &rest[..start];
+        // Whitespace between two encoded words is dropped
+        if !(after_word && decoded && between.trim().is_empty()) {
+            res.push_str(between);
+        }
+        res.push_str(&text);
+        after_word = decoded;
+        rest = &rest[start + len..];
+    }
+    res.push_str(rest);
+    res
+}
+
+/// Decode `charset?encoding?text?=`, returning the text and the length
+/// consumed.
+fn decode_word(s: &str) -> Option<(String, usize)> {
+    let mut parts = s.splitn(3, '?');
+    let charset = parts.next()?;
+    let encoding = parts.next()?;
+    let rest = parts.next()?;
+    let end = rest.find("?=")?;
+    let text = &rest[..end];
+    let len = charset.len() + encoding.len() + end + 4;
+    let bytes = match encoding {
+        "B" | "b" => base64::engine::general_purpose::STANDARD
+            .decode(text)
+            .ok()?,
+        "Q" | "q" => decode_q(text)?,
+        _ => return None,
+    };
+    // Language suffixes like `UTF-8*en` are ignored
+    let charset = charset.split('*').next()?.to_ascii_lowercase();
+    let decoded = match charset.as_str() {
+        "iso-8859-1" | "latin1" => bytes.iter().map(|&b| b as char).collect(),
+        _ => String::from_utf8_lossy(&bytes).into_owned(),
+    };
+    Some((decoded, len))
+}
+
+fn decode_q(text: &str) -> Option<Vec<u8>> {
+    let mut res = vec![];
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'_' => res.push(b' '),
+            b'=' => {
+                let hex = text.get(i + 1..i + 3)?;
+                res.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 2;
+            }
+            b => res.push(b),
+        }
+        i += 1;
+    }
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoded_words() {
+        assert_eq!(decode_header(b"plain subject"), "plain subject");
+        assert_eq!(decode_header(b"=?UTF-8?B?w6l0w6k=?="), "été");
+        assert_eq!(
+            decode_header(b"=?utf-8?q?caf=C3=A9_au_lait?="),
+            "café au lait"
+        );
+        assert_eq!(
+            decode_header(b"=?ISO-8859-1?Q?Andr=E9?= Pirard"),
+            "André Pirard"
+        );
+        assert_eq!(decode_header(b"=?UTF-8*en?Q?hello?="), "hello");
+    }
+
+    #[test]
+    fn whitespace_between_words() {
+        assert_eq!(decode_header(b"=?UTF-8?Q?a?= =?UTF-8?Q?b?="), "ab");
+        assert_eq!(decode_header(b"=?UTF-8?Q?a?=\r\n =?UTF-8?Q?b?="), "ab");
+        assert_eq!(decode_header(b"=?UTF-8?Q?a?= x =?UTF-8?Q?b?="), "a x b");
+        assert_eq!(decode_header(b"Re: =?UTF-8?Q?a?="), "Re: a");
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(decode_header(b"=?UTF-8?X?abc?="), "=?UTF-8?X?abc?=");
+        assert_eq!(decode_header(b"1 + 1 =? 2"), "1 + 1 =? 2");
+        assert_eq!(decode_header(b"=?UTF-8?Q?bad=Z?="), "=?UTF-8?Q?bad=Z?=");
+        assert_eq!(decode_header(b"=?UTF-8?B?w6l0w6k="), "=?UTF-8?B?w6l0w6k=");
+    }
+}
@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+
+use nadir_backend_common::FrontendConfig;
+use nadir_types::model::MessageGroup;
+use serde::Deserialize;
+
+/// Config file for this backend
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    /// The frontend to send messages to.
+    pub frontend: FrontendConfig,
+
+    /// Groups created at start.
+    #[serde(default)]
+    pub groups: Vec<MessageGroup>,
+
+    /// Log files to follow.
+    #[serde(default)]
+    pub files: Vec<FileConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileConfig {
+    /// Path of the log file. The file is reopened when it's rotated.
+    pub path: PathBuf,
+
+    /// The group that messages of this file go into, unless a rule says
+    /// otherwise.
+    pub group: String,
+
+    /// Read the file from the beginning instead of only new lines.
+    #[serde(default)]
+    pub from_start: bool,
+
+    /// Milliseconds between two checks of the file.
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: u64,
+
+    /// Rules to match lines against. The first matching rule wins, and lines
+    /// matching no rule are ignored.
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+}
+
+/// A rule turning matching lines into messages.
+///
+/// `id`, `body` and `tags` are templates, where `$name` or `${name}` is
+/// replaced by the named capture group and `$1` by the numbered one.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RuleConfig {
+    /// The regular expression to match lines against.
+    pub pattern: String,
+
+    /// Overrides the group of the file.
+    #[serde(default)]
+    pub group: Option<String>,
+
+    /// Message ID template. Lines producing the same ID coalesce into one
+    /// message with its counter increased, as long as the message is still
+    /// within the capacity of its group. Defaults to the whole line.
+    #[serde(default)]
+    pub id: Option<String>,
+
+    /// Message body template. Defaults to the whole line.
+    #[serde(default)]
+    pub body: Option<String>,
+
+    /// Tag templates. Tags that expand to empty strings are dropped.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+fn default_poll_interval() -> u64 {
+    1000
+}
@@ -0,0 +1,119 @@
+//! A backend that follows log files and turns matching lines into messages.
+//!
+//! ```toml
+//! [frontend]
+//! url = "ws://localhost:7890"
+//!
+//! [[groups]]
+//! id = "security"
+//! title = "Security"
+//!
+//! [[files]]
+//! path = "/var/log/auth.log"
+//! group = "security"
+//!
+//! [[files.rules]]
+//! pattern = 'sshd\[\d+\]: Accepted (?P<method>\S+) for (?P<user>\S+) from (?P<ip>\S+)'
+//! id = "login-$user-$ip"
+//! body = "$user logged in from $ip"
+//! tags = ["sshd", "$method"]
+//! ```
+mod config;
+mod rule;
+mod tail;
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use chrono::Utc;
+use clap::Clap;
+use indexmap::IndexMap;
+use nadir_backend_common::{err_and_exit, Link, Opt};
+use nadir_types::model::Message;
+
+use self::{
+    config::{Config, FileConfig},
+    rule::{Counts, Rule},
+    tail::Tail,
+};
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let opt = Opt::parse();
+    let config: Config = opt.load_config("./nadir-logtail.toml");
+
+    let (link, mut frontend) = Link::spawn(config.frontend);
+    let capacities: Arc<HashMap<_, _>> = Arc::new(
+        config
+            .groups
+            .iter()
+            .map(|g| (g.id.clone(), g.capacity as usize))
+            .collect(),
+    );
+    for group in config.groups {
+        link.put_group(group);
+    }
+    for file in config.files {
+        let rules = file
+            .rules
+            .iter()
+            .cloned()
+            .map(Rule::new)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_else(|e| {
+                err_and_exit(format_args!(
+                    "Invalid rule for '{}'.\nReason: {}",
+                    file.path.display(),
+                    e
+                ))
+            });
+        tokio::spawn(follow(file, rules, capacities.clone(), link.clone()));
+    }
+
+    // This backend doesn't react to the frontend
+    while frontend.recv().await.is_some() {}
+}
+
+async fn follow(
+    config: FileConfig,
+    rules: Vec<Rule>,
+    capacities: Arc<HashMap<String, usize>>,
+    link: Link,
+) {
+    let mut tail = Tail::open(config.path.clone(), config.from_start).await;
+    let mut timer = tokio::time::interval(Duration::from_millis(config.poll_interval.max(10)));
+    let mut counts = Counts::new(capacities);
+
+    loop {
+        timer.tick().await;
+        let now = Utc::now();
+        let mut batch = IndexMap::<String, IndexMap<String, Message>>::new();
+
+        for line in tail.read_lines().await {
+            let m = match rules.iter().find_map(|r| r.matches(&line)) {
+                Some(m) => m,
+                None => continue,
+            };
+            let group = m.group.unwrap_or_else(|| config.group.clone());
+            let count = counts.count(&group, &m.id);
+
+            let msgs = batch.entry(group).or_default();
+            // Keep the latest occurrence as the newest message
+            msgs.shift_remove(&m.id);
+            msgs.insert(
+                m.id.clone(),
+                Message {
+                    id: m.id,
+                    counter: Some(count),
+                    tags: m.tags,
+                    body: m.body,
+                    time: Some(now),
+                },
+            );
+        }
+
+        for (group, msgs) in batch {
+            link.put(group, msgs.into_iter().map(|(_, m)| m).collect());
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::{collections::HashMap, sync::Arc};
+
+use indexmap::IndexMap;
+use nadir_types::model::MessageGroup;
+use regex::{Captures, Regex};
+
+use crate::config::RuleConfig;
+
+/// A compiled [`RuleConfig`].
+#[derive(Debug)]
+pub struct Rule {
+    pattern: Regex,
+    config: RuleConfig,
+}
+
+/// The result of a line matching a rule.
+#[derive(Debug)]
+pub struct Match {
+    pub group: Option<String>,
+    pub id: String,
+    pub body: String,
+    pub tags: Vec<String>,
+}
+
+impl Rule {
+    pub fn new(config: RuleConfig) -> Result<Rule, regex::Error> {
+        Ok(Rule {
+            pattern: Regex::new(&config.pattern)?,
+            config,
+        })
+    }
+
+    pub fn matches(&self, line: &str) -> Option<Match> {
+        let caps = self.pattern.captures(line)?;
+        let expand_or_line = |template: &Option<String>| match template {
+            Some(t) => expand(&caps, t),
+            None => line.to_owned(),
+        };
+        Some(Match {
+            group: self.config.group.clone(),
+            id: expand_or_line(&self.config.id),
+            body: expand_or_line(&self.config.body),
+            tags: self
+                .config
+                .tags
+                .iter()
+                .map(|t| expand(&caps, t))
+                .filter(|t| !t.is_empty())
+                .collect(),
+        })
+    }
+}
+
+fn expand(caps: &Captures, template: &str) -> String {
+    let mut res = String::new();
+    caps.expand(template, &mut res);
+    res
+}
+
+/// How many times each ID has been seen in each group, least recently seen
+/// first. Only as many IDs as the group shows are remembered, so the count
+/// restarts for messages that have been pushed off the screen.
+#[derive(Debug)]
+pub struct Counts {
+    capacities: Arc<HashMap<String, usize>>,
+    seen: HashMap<String, IndexMap<String, u64>>,
+}
+
+impl Counts {
+    pub fn new(capacities: Arc<HashMap<String, usize>>) -> Counts {
+        Counts {
+            capacities,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Count another line producing `id`, and return how many there are.
+    pub fn count(&mut self, group: &str, id: &str) -> u64 {
+        let counts = self.seen.entry(group.to_owned()).or_default();
+        let count = counts.shift_remove(id).unwrap_or(0) + 1;
+        counts.insert(id.to_owned(), count);
+        let capacity = self
+            .capacities
+            .get(group)
+            .copied()
+            .unwrap_or(MessageGroup::default().capacity as usize);
+        while counts.len() > capacity.max(1) {
+            counts.shift_remove_index(0);
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, id: Option<&str>, body: Option<&str>, tags: &[&str]) -> Rule {
+        Rule::new(RuleConfig {
+            pattern: pattern.into(),
+            group: None,
+            id: id.map(Into::into),
+            body: body.map(Into::into),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn templates() {
+        let rule = rule(
+            r"Accepted (?P<method>\S+) for (?P<user>\S+) from (\S+)(?: via (?P<via>\S+))?",
+            Some("login-$user-$3"),
+            Some("${user} logged in from $3"),
+            &["sshd", "$method", "$via"],
+        );
+        let m = rule
+            .matches("sshd[1]: Accepted publickey for alice from 10.0.0.1")
+            .unwrap();
+        assert_eq!(m.id, "login-alice-10.0.0.1");
+        assert_eq!(m.body, "alice logged in from 10.0.0.1");
+        // `$via` didn't match, so its tag is dropped
+        assert_eq!(m.tags, ["sshd", "publickey"]);
+        assert!(rule.matches("sshd[1]: Failed password for bob").is_none());
+    }
+
+    #[test]
+    fn defaults_to_the_line() {
+        let m = rule("error", None, None, &[]).matches("an error").unwrap();
+        assert_eq!((m.id.as_str(), m.body.as_str()), ("an error", "an error"));
+        assert_eq!(m.group, None);
+    }
+
+    #[test]
+    fn counts_within_capacity() {
+        let capacities = vec![("small".to_owned(), 2)].into_iter().collect();
+        let mut counts = Counts::new(Arc::new(capacities));
+        assert_eq!(counts.count("small", "a"), 1);
+        assert_eq!(counts.count("small", "a"), 2);
+        assert_eq!(counts.count("small", "b"), 1);
+        // Seeing `a` again keeps it on screen
+        assert_eq!(counts.count("small", "a"), 3);
+        // `b` is pushed off by `c`, so it starts over
+        assert_eq!(counts.count("small", "c"), 1);
+        assert_eq!(counts.count("small", "b"), 1);
+        assert_eq!(counts.count("small", "a"), 1);
+
+        // Groups are counted separately, with the default capacity
+        assert_eq!(counts.count("other", "a"), 1);
+        for id in 0..MessageGroup::default().capacity {
+            counts.count("other", &id.to_string());
+        }
+        assert_eq!(counts.count("other", "a"), 1);
+    }
+}
@@ -0,0 +1,183 @@
+use std::{io::SeekFrom, os::unix::fs::MetadataExt, path::PathBuf};
+
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+
+/// Follows a file like `tail -F`, surviving rotation and truncation.
+pub struct Tail {
+    path: PathBuf,
+    file: Option<File>,
+    inode: u64,
+    offset: u64,
+    /// Bytes after the last complete line.
+    partial: Vec<u8>,
+}
+
+impl Tail {
+    /// Start following `path`. If `from_start` is false, only lines written
+    /// after this call are read from the currently existing file.
+    pub async fn open(path: PathBuf, from_start: bool) -> Tail {
+        let mut tail = Tail {
+            path,
+            file: None,
+            inode: 0,
+            offset: 0,
+            partial: vec![],
+        };
+        tail.reopen().await;
+        if !from_start {
+            if let Some(file) = &mut tail.file {
+                tail.offset = file.seek(SeekFrom::End(0)).await.unwrap_or(0);
+            }
+        }
+        tail
+    }
+
+    async fn reopen(&mut self) {
+        self.partial.clear();
+        self.offset = 0;
+        self.file = match File::open(&self.path).await {
+            Ok(f) => {
+                self.inode = f.metadata().await.map(|m| m.ino()).unwrap_or(0);
+                Some(f)
+            }
+            Err(e) => {
+                log::debug!("cannot open {}: {}", self.path.display(), e);
+                None
+            }
+        };
+    }
+
+    /// Read complete lines written since the last call.
+    pub async fn read_lines(&mut self) -> Vec<String> {
+        let mut lines = vec![];
+        self.read_available(&mut lines).await;
+
+        match tokio::fs::metadata(&self.path).await {
+            // Rotated: the rest of the old file was read above, so continue
+            // with the new one.
+            Ok(meta) if self.file.is_none() || meta.ino() != self.inode => {
+                self.reopen().await;
+                self.read_available(&mut lines).await;
+            }
+            // Truncated in place
+            Ok(meta) if meta.len() < self.offset => {
+                if let Some(file) = &mut self.file {
+                    let _ = file.seek(SeekFrom::Start(0)).await;
+                }
+                self.offset = 0;
+                self.partial.clear();
+                self.read_available(&mut lines).await;
+            }
+            _ => {}
+        }
+        lines
+    }
+
+    async fn read_available(&mut self, lines: &mut Vec<String>) {
+        let file = match &mut self.file {
+            Some(f) => f,
+            None => return,
+        };
+        let mut buf = vec![];
+        match file.read_to_end(&mut buf).await {
+            Ok(n) => self.offset += n as u64,
+            Err(e) => {
+                log::warn!("failed to read {}: {}", self.path.display(), e);
+                return;
+            }
+        }
+
+        self.partial.extend_from_slice(&buf);
+        let mut start = 0;
+        for (i, &b) in self.partial.iter().enumerate() {
+            if b == b'\n' {
+                let line = String::from_utf8_lossy(&self.partial[start..i]);
+                lines.push(line.trim_end_matches('\r').to_owned());
+                start = i + 1;
+            }
+        }
+        self.partial.drain(..start);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn append(path: &PathBuf, text: &str) {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("nadir-logtail-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn partial_lines() {
+        let dir = temp_dir("partial");
+        let path = dir.join("log");
+        append(&path, "old\n");
+        let mut tail = Tail::open(path.clone(), false).await;
+        assert!(tail.read_lines().await.is_empty());
+
+        append(&path, "a\r\nb");
+        assert_eq!(tail.read_lines().await, ["a"]);
+        append(&path, "c\n");
+        assert_eq!(tail.read_lines().await, ["bc"]);
+
+        let mut tail = Tail::open(path, true).await;
+        assert_eq!(tail.read_lines().await, ["old", "a", "bc"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rotation() {
+        let dir = temp_dir("rotation");
+        let path = dir.join("log");
+        let mut tail = Tail::open(path.clone(), false).await;
+        assert!(tail.read_lines().await.is_empty());
+
+        // Created after starting
+        append(&path, "a\n");
+        assert_eq!(tail.read_lines().await, ["a"]);
+
+        // Lines written to the old file before the new one appears are kept
+        append(&path, "b\n");
+        std::fs::rename(&path, dir.join("log.1")).unwrap();
+        append(&dir.join("log.1"), "c\n");
+        append(&path, "d\n");
+        assert_eq!(tail.read_lines().await, ["b", "c", "d"]);
+        append(&path, "e\n");
+        assert_eq!(tail.read_lines().await, ["e"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn truncation() {
+        let dir = temp_dir("truncation");
+        let path = dir.join("log");
+        append(&path, "a long line\n");
+        let mut tail = Tail::open(path.clone(), true).await;
+        assert_eq!(tail.read_lines().await, ["a long line"]);
+
+        std::fs::write(&path, "b\n").unwrap();
+        assert_eq!(tail.read_lines().await, ["b"]);
+        append(&path, "c\n");
+        assert_eq!(tail.read_lines().await, ["c"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
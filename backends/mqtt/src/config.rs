@@ -0,0 +1,120 @@
+use nadir_backend_common::FrontendConfig;
+use nadir_types::model::MessageGroup;
+use serde::Deserialize;
+
+/// Config file for this backend
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    /// The frontend to send messages to.
+    pub frontend: FrontendConfig,
+
+    /// The MQTT broker to subscribe to.
+    pub broker: BrokerConfig,
+
+    /// Groups created at start. Groups used by subscriptions but not listed
+    /// here are created on their first message.
+    #[serde(default)]
+    pub groups: Vec<MessageGroup>,
+
+    /// Topic filters to subscribe to.
+    #[serde(default)]
+    pub subscriptions: Vec<SubscriptionConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BrokerConfig {
+    pub host: String,
+
+    #[serde(default = "default_port")]
+    pub port: u16,
+
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+
+    #[serde(default)]
+    pub username: Option<String>,
+
+    #[serde(default)]
+    pub password: Option<String>,
+
+    /// Keep alive interval in seconds.
+    #[serde(default = "default_keep_alive")]
+    pub keep_alive: u64,
+}
+
+/// A topic filter and how its messages are shown. Every string except
+/// `filter` is a template, see [`nadir_backend_common::template`].
+///
+/// Templates are evaluated with the payload as the current value, which is
+/// the parsed JSON if the payload is valid JSON, or a string otherwise. The
+/// root value contains `topic`, the topic split by `/` as `segments` and
+/// `payload`. For example, `{$.segments[1]}` is the second topic segment.
+///
+/// An empty payload removes the message of its topic. There's no payload to
+/// read then, so removing only works if `group` and `id` are built from the
+/// topic alone.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscriptionConfig {
+    /// An MQTT topic filter, e.g. `sensors/+/temperature`.
+    pub filter: String,
+
+    /// Quality of service level, 0 to 2.
+    #[serde(default)]
+    pub qos: u8,
+
+    /// The group messages go into. Should only refer to the topic if empty
+    /// payloads are to remove messages.
+    pub group: String,
+
+    /// Message ID. Defaults to the topic, so each topic shows its latest
+    /// message. Should only refer to the topic if empty payloads are to remove
+    /// messages.
+    #[serde(default = "default_id")]
+    pub id: String,
+
+    /// Message body. Defaults to the whole payload.
+    #[serde(default = "default_body")]
+    pub body: String,
+
+    /// Message tags. Tags rendering to empty strings are dropped.
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// Message counter. Should refer to a number.
+    #[serde(default)]
+    pub counter: Option<String>,
+
+    /// Message time. Should refer to an RFC 3339 string or a UNIX timestamp.
+    /// Defaults to the time the message is received.
+    #[serde(default)]
+    pub time: Option<String>,
+
+    /// Show retained messages in the pinned slot, so the last known state of
+    /// each topic stays on top of live updates.
+    #[serde(default = "default_pin_retained")]
+    pub pin_retained: bool,
+}
+
+fn default_port() -> u16 {
+    1883
+}
+
+fn default_client_id() -> String {
+    "nadir-mqtt".into()
+}
+
+fn default_keep_alive() -> u64 {
+    30
+}
+
+fn default_id() -> String {
+    "{$.topic}".into()
+}
+
+fn default_body() -> String {
+    "{$.payload}".into()
+}
+
+fn default_pin_retained() -> bool {
+    true
+}
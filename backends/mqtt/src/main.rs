@@ -0,0 +1,145 @@
+//! A backend that subscribes to topics on an MQTT broker and shows what's
+//! published to them.
+//!
+//! Each topic shows its latest message. Retained messages are pinned, and an
+//! empty payload removes the message of its topic, as long as the group and
+//! ID of the subscription only refer to the topic.
+//!
+//! ```toml
+//! [frontend]
+//! url = "ws://localhost:7890"
+//!
+//! [broker]
+//! host = "localhost"
+//!
+//! [[subscriptions]]
+//! filter = "sensors/+/temperature"
+//! group = "sensors"
+//! body = "{$.segments[1]}: {.value} °C"
+//! time = "{.timestamp}"
+//!
+//! [[subscriptions]]
+//! filter = "services/#"
+//! group = "{$.segments[1]}"
+//! tags = ["{.host}"]
+//! body = "{.status|$.payload}"
+//! ```
+mod config;
+mod subscription;
+
+use std::{collections::HashSet, time::Duration};
+
+use clap::Clap;
+use log::{info, warn};
+use nadir_backend_common::{err_and_exit, Link, Opt};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, Publish, SubscribeFilter};
+
+use self::{
+    config::Config,
+    subscription::{Subscription, Update},
+};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+struct State {
+    link: Link,
+    subscriptions: Vec<Subscription>,
+    /// Messages in the pinned slot, as `(group, id)`
+    pinned: HashSet<(String, String)>,
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let opt = Opt::parse();
+    let config: Config = opt.load_config("./nadir-mqtt.toml");
+
+    let (link, mut frontend) = Link::spawn(config.frontend);
+    for group in config.groups {
+        link.put_group(group);
+    }
+
+    let subscriptions = config
+        .subscriptions
+        .into_iter()
+        .map(Subscription::new)
+        .collect::<Result<Vec<_>, String>>()
+        .unwrap_or_else(|e| err_and_exit(format_args!("Invalid subscription.\nReason: {}", e)));
+
+    let broker = config.broker;
+    let mut options = MqttOptions::new(broker.client_id, broker.host, broker.port);
+    options.set_keep_alive(Duration::from_secs(broker.keep_alive.max(5)));
+    if let Some(username) = broker.username {
+        options.set_credentials(username, broker.password.unwrap_or_default());
+    }
+    let (client, mut events) = AsyncClient::new(options, 16);
+
+    tokio::spawn(async move {
+        // This backend doesn't react to the frontend
+        while frontend.recv().await.is_some() {}
+    });
+
+    let mut state = State {
+        link,
+        subscriptions,
+        pinned: HashSet::new(),
+    };
+    loop {
+        match events.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                info!("connected to broker");
+                // Subscriptions don't survive a clean session, so subscribe
+                // again on every connection
+                let filters = state
+                    .subscriptions
+                    .iter()
+                    .map(|s| SubscribeFilter::new(s.filter.clone(), s.qos))
+                    .collect::<Vec<_>>();
+                if !filters.is_empty() {
+                    if let Err(e) = client.try_subscribe_many(filters) {
+                        warn!("failed to subscribe: {}", e);
+                    }
+                }
+            }
+            Ok(Event::Incoming(Packet::Publish(publish))) => state.handle(publish),
+            Ok(_) => {}
+            Err(e) => {
+                warn!("broker connection failed: {}", e);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+impl State {
+    fn handle(&mut self, publish: Publish) {
+        let sub = match self
+            .subscriptions
+            .iter()
+            .find(|s| s.matches(&publish.topic))
+        {
+            Some(sub) => sub,
+            None => return,
+        };
+        match sub.process(&publish.topic, &publish.payload) {
+            Update::Put { group, msg } => {
+                let key = (group.clone(), msg.id.clone());
+                // Topics that had a retained message keep their updates in the
+                // pinned slot
+                if sub.pin_retained && publish.retain && self.pinned.insert(key.clone()) {
+                    self.link.remove(group.as_str(), vec![msg.id.clone()]);
+                }
+                if self.pinned.contains(&key) {
+                    self.link.put_pinned(group, vec![msg]);
+                } else {
+                    self.link.put(group, vec![msg]);
+                }
+            }
+            Update::Remove { group, id } => {
+                self.pinned.remove(&(group.clone(), id.clone()));
+                self.link.remove(group.as_str(), vec![id.clone()]);
+                self.link.remove_pinned(group, vec![id]);
+            }
+        }
+    }
+}
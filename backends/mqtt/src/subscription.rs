@@ -0,0 +1,194 @@
+use chrono::Utc;
+use nadir_backend_common::template::{as_time, as_u64, Scope, Template};
+use nadir_types::model::Message;
+use rumqttc::QoS;
+use serde_json::{json, Value};
+
+use crate::config::SubscriptionConfig;
+
+/// A compiled [`SubscriptionConfig`].
+#[derive(Debug)]
+pub struct Subscription {
+    pub filter: String,
+    pub qos: QoS,
+    pub pin_retained: bool,
+    group: Template,
+    id: Template,
+    body: Template,
+    tags: Vec<Template>,
+    counter: Option<Template>,
+    time: Option<Template>,
+}
+
+/// What a published message means to the frontend.
+#[derive(Debug)]
+pub enum Update {
+    Put { group: String, msg: Message },
+    Remove { group: String, id: String },
+}
+
+impl Subscription {
+    pub fn new(config: SubscriptionConfig) -> Result<Subscription, String> {
+        if !rumqttc::valid_filter(&config.filter) {
+            return Err(format!("invalid topic filter '{}'", config.filter));
+        }
+        let qos = rumqttc::qos(config.qos)
+            .map_err(|_| format!("invalid QoS {} of '{}'", config.qos, config.filter))?;
+        let parse = |s: &str| Template::parse(s);
+        Ok(Subscription {
+            qos,
+            pin_retained: config.pin_retained,
+            group: parse(&config.group)?,
+            id: parse(&config.id)?,
+            body: parse(&config.body)?,
+            tags: config
+                .tags
+                .iter()
+                .map(|t| parse(t))
+                .collect::<Result<_, _>>()?,
+            counter: config.counter.as_deref().map(parse).transpose()?,
+            time: config.time.as_deref().map(parse).transpose()?,
+            filter: config.filter,
+        })
+    }
+
+    pub fn matches(&self, topic: &str) -> bool {
+        rumqttc::matches(topic, &self.filter)
+    }
+
+    /// Turn a published message into an update. An empty payload removes the
+    /// message of its topic, with the group and ID rendered from the empty
+    /// payload. Templates reading the payload then render as if it had no
+    /// fields.
+    pub fn process(&self, topic: &str, payload: &[u8]) -> Update {
+        let payload = serde_json::from_slice(payload)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(payload).into_owned()));
+        let removed = payload.as_str().is_some_and(str::is_empty);
+        let root = json!({
+            "topic": topic,
+            "segments": topic.split('/').collect::<Vec<_>>(),
+            "payload": payload,
+        });
+        let scope = Scope {
+            root: &root,
+            current: &root["payload"],
+        };
+
+        let group = self.group.render(scope);
+        let id = self.id.render(scope);
+        if removed {
+            return Update::Remove { group, id };
+        }
+
+        let msg = Message {
+            id,
+            counter: self
+                .counter
+                .as_ref()
+                .and_then(|c| c.select(scope))
+                .and_then(as_u64),
+            tags: self
+                .tags
+                .iter()
+                .map(|t| t.render(scope))
+                .filter(|t| !t.is_empty())
+                .collect(),
+            body: self.body.render(scope),
+            time: Some(
+                self.time
+                    .as_ref()
+                    .and_then(|t| t.select(scope))
+                    .and_then(as_time)
+                    .unwrap_or_else(Utc::now),
+            ),
+        };
+        Update::Put { group, msg }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscription(config: Value) -> Subscription {
+        Subscription::new(serde_json::from_value(config).unwrap()).unwrap()
+    }
+
+    fn put(update: Update) -> (String, Message) {
+        match update {
+            Update::Put { group, msg } => (group, msg),
+            u => panic!("expected a put, got {:?}", u),
+        }
+    }
+
+    #[test]
+    fn json_payload() {
+        let sub = subscription(json!({
+            "filter": "sensors/+/temperature",
+            "group": "sensors",
+            "body": "{$.segments[1]}: {.value} °C",
+            "tags": ["{.unit}", "{.host}"],
+            "counter": "{.count}",
+            "time": "{.timestamp}",
+        }));
+        assert!(sub.matches("sensors/kitchen/temperature"));
+        assert!(!sub.matches("sensors/kitchen/humidity"));
+
+        let payload = br#"{"value": 21.5, "host": "pi", "count": "3", "timestamp": 0}"#;
+        let (group, msg) = put(sub.process("sensors/kitchen/temperature", payload));
+        assert_eq!(group, "sensors");
+        assert_eq!(msg.id, "sensors/kitchen/temperature");
+        assert_eq!(msg.body, "kitchen: 21.5 °C");
+        assert_eq!(msg.tags, ["pi"]);
+        assert_eq!(msg.counter, Some(3));
+        assert_eq!(msg.time.unwrap().timestamp(), 0);
+    }
+
+    #[test]
+    fn text_payload() {
+        let sub = subscription(json!({
+            "filter": "services/#",
+            "group": "{$.segments[1]}",
+            "body": "{.status|$.payload}",
+        }));
+        let (group, msg) = put(sub.process("services/web/state", b"down"));
+        assert_eq!(group, "web");
+        assert_eq!(msg.body, "down");
+        assert_eq!(msg.counter, None);
+        assert!(msg.time.is_some());
+
+        let (_, msg) = put(sub.process("services/web/state", br#"{"status": "up"}"#));
+        assert_eq!(msg.body, "up");
+    }
+
+    #[test]
+    fn empty_payload_removes() {
+        let sub = subscription(json!({ "filter": "services/#", "group": "{$.segments[1]}" }));
+        match sub.process("services/web/state", b"") {
+            Update::Remove { group, id } => {
+                assert_eq!(group, "web");
+                assert_eq!(id, "services/web/state");
+            }
+            u => panic!("expected a removal, got {:?}", u),
+        }
+        // An empty JSON string is an empty payload too
+        assert!(matches!(
+            sub.process("services/web/state", br#""""#),
+            Update::Remove { .. }
+        ));
+        assert!(matches!(
+            sub.process("services/web/state", b"{}"),
+            Update::Put { .. }
+        ));
+    }
+
+    #[test]
+    fn invalid() {
+        let config = |filter: &str, qos: u8| {
+            serde_json::from_value(json!({ "filter": filter, "qos": qos, "group": "g" })).unwrap()
+        };
+        assert!(Subscription::new(config("a/#/b", 0)).is_err());
+        assert!(Subscription::new(config("a/+", 3)).is_err());
+        assert!(Subscription::new(config("a/+", 2)).is_ok());
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use nadir_backend_common::Link;
+use nadir_types::model::{Message, MessageGroup};
+use parking_lot::Mutex;
+
+use crate::config::{Config, UrgencyConfig};
+
+/// Why a notification was closed, as reported by `NotificationClosed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum CloseReason {
+    Expired = 1,
+    Dismissed = 2,
+    Closed = 3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl Urgency {
+    pub fn from_hint(v: u8) -> Urgency {
+        match v {
+            0 => Urgency::Low,
+            2 => Urgency::Critical,
+            _ => Urgency::Normal,
+        }
+    }
+
+    fn tag(self) -> Option<&'static str> {
+        match self {
+            Urgency::Low => Some("low"),
+            Urgency::Normal => None,
+            Urgency::Critical => Some("critical"),
+        }
+    }
+}
+
+/// A `Notify` call.
+#[derive(Debug)]
+pub struct Notify {
+    pub app_name: String,
+    pub replaces_id: u32,
+    pub summary: String,
+    pub body: String,
+    /// Action keys, without their display names.
+    pub actions: Vec<String>,
+    pub urgency: Urgency,
+    pub category: Option<String>,
+    pub resident: bool,
+}
+
+/// A notification shown by [`Bridge::notify`].
+#[derive(Debug)]
+pub struct Shown {
+    pub id: u32,
+    /// Identifies this version of the notification, see [`Bridge::expire`].
+    pub serial: u64,
+    /// Notifications dropped because their group is full.
+    pub dropped: Vec<u32>,
+}
+
+/// What to do after the user acted on a notification.
+#[derive(Debug)]
+pub struct Invoked {
+    pub id: u32,
+    /// The action to report with `ActionInvoked`, if the notification has one.
+    pub action: Option<String>,
+    /// Whether the notification was dismissed by the action.
+    pub closed: bool,
+}
+
+struct Notification {
+    /// Increases with every notification shown, so the oldest one in a group
+    /// has the smallest serial.
+    serial: u64,
+    group: String,
+    urgency: Urgency,
+    actions: Vec<String>,
+    resident: bool,
+}
+
+struct Inner {
+    next_id: u32,
+    next_serial: u64,
+    notifications: HashMap<u32, Notification>,
+    /// Groups sent to the frontend, and the importance they were sent with.
+    groups: HashMap<String, (MessageGroup, i32)>,
+}
+
+/// Keeps notifications on the bus and messages on the frontend in sync.
+pub struct Bridge {
+    link: Link,
+    urgency: UrgencyConfig,
+    apps: HashMap<String, String>,
+    /// Groups from the config file.
+    configured: HashMap<String, MessageGroup>,
+    inner: Mutex<Inner>,
+}
+
+impl Bridge {
+    pub fn new(config: &Config, link: Link) -> Self {
+        let mut groups = HashMap::new();
+        for group in &config.groups {
+            link.put_group(group.clone());
+            groups.insert(group.id.clone(), (group.clone(), group.importance));
+        }
+        Bridge {
+            link,
+            urgency: config.urgency.clone(),
+            apps: config.apps.clone(),
+            configured: config
+                .groups
+                .iter()
+                .map(|g| (g.id.clone(), g.clone()))
+                .collect(),
+            inner: Mutex::new(Inner {
+                next_id: 1,
+                next_serial: 0,
+                notifications: HashMap::new(),
+                groups,
+            }),
+        }
+    }
+
+    /// Show a notification. Notifications beyond the capacity of its group
+    /// are dropped, oldest first.
+    pub fn notify(&self, n: Notify) -> Shown {
+        let app = if n.app_name.is_empty() {
+            "unknown"
+        } else {
+            n.app_name.as_str()
+        };
+        let group = self.apps.get(app).map_or(app, String::as_str).to_owned();
+
+        let mut inner = self.inner.lock();
+        // IDs of closed notifications may not be replaced, as they could be
+        // handed out again
+        let id = if inner.notifications.contains_key(&n.replaces_id) {
+            n.replaces_id
+        } else {
+            let mut id = inner.next_id;
+            while id == 0 || inner.notifications.contains_key(&id) {
+                id = id.wrapping_add(1);
+            }
+            inner.next_id = id.wrapping_add(1);
+            id
+        };
+        let serial = inner.next_serial;
+        inner.next_serial += 1;
+
+        let mut tags = vec![];
+        tags.extend(n.category);
+        tags.extend(n.urgency.tag().map(str::to_owned));
+        let body = if n.body.is_empty() {
+            n.summary
+        } else {
+            format!("{}: {}", n.summary, n.body.replace('\n', " "))
+        };
+
+        let old = inner.notifications.insert(
+            id,
+            Notification {
+                serial,
+                group: group.clone(),
+                urgency: n.urgency,
+                actions: n.actions,
+                resident: n.resident,
+            },
+        );
+        if let Some(old) = old.filter(|old| old.group != group) {
+            self.link.remove(old.group.as_str(), vec![id.to_string()]);
+            self.update_group(&mut inner, &old.group);
+        }
+
+        let dropped = self.drop_oldest(&mut inner, &group);
+        self.update_group(&mut inner, &group);
+        self.link.put(
+            group,
+            vec![Message {
+                id: id.to_string(),
+                counter: None,
+                tags,
+                body,
+                time: Some(Utc::now()),
+            }],
+        );
+        Shown {
+            id,
+            serial,
+            dropped,
+        }
+    }
+
+    /// Remove a notification whose timeout passed, unless it has been
+    /// replaced since. Returns whether it was removed.
+    pub fn expire(&self, id: u32, serial: u64) -> bool {
+        let current = self.inner.lock().notifications.get(&id).map(|n| n.serial);
+        current == Some(serial) && self.close(id)
+    }
+
+    /// Remove a notification. Returns whether it existed.
+    pub fn close(&self, id: u32) -> bool {
+        let mut inner = self.inner.lock();
+        match inner.notifications.remove(&id) {
+            Some(n) => {
+                self.link.remove(n.group.as_str(), vec![id.to_string()]);
+                self.update_group(&mut inner, &n.group);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The user acted on a message on the frontend.
+    pub fn invoke(&self, group: &str, message: &str) -> Option<Invoked> {
+        let id = message.parse().ok()?;
+        let (action, resident) = {
+            let inner = self.inner.lock();
+            let n = inner.notifications.get(&id).filter(|n| n.group == group)?;
+            let action = n
+                .actions
+                .iter()
+                .find(|a| *a == "default")
+                .or_else(|| n.actions.first())
+                .cloned();
+            (action, n.resident)
+        };
+        let closed = !resident && self.close(id);
+        Some(Invoked { id, action, closed })
+    }
+
+    /// Forget the oldest notifications of a group that don't fit in it
+    /// anymore, so they don't pile up while the frontend no longer shows them.
+    fn drop_oldest(&self, inner: &mut Inner, group: &str) -> Vec<u32> {
+        let capacity = self
+            .configured
+            .get(group)
+            .map_or(MessageGroup::default().capacity, |g| g.capacity)
+            .max(1) as usize;
+        let mut held: Vec<_> = inner
+            .notifications
+            .iter()
+            .filter(|(_, n)| n.group == group)
+            .map(|(id, n)| (n.serial, *id))
+            .collect();
+        if held.len() <= capacity {
+            return vec![];
+        }
+        held.sort_unstable();
+        let dropped: Vec<_> = held[..held.len() - capacity]
+            .iter()
+            .map(|(_, id)| *id)
+            .collect();
+        for id in &dropped {
+            inner.notifications.remove(id);
+        }
+        self.link
+            .remove(group, dropped.iter().map(|id| id.to_string()).collect());
+        dropped
+    }
+
+    /// Send the group of an application if it's new or its importance
+    /// changed.
+    fn update_group(&self, inner: &mut Inner, group: &str) {
+        let base = self
+            .configured
+            .get(group)
+            .map_or_else(|| self.importance(Urgency::Normal), |g| g.importance);
+        let importance = inner
+            .notifications
+            .values()
+            .filter(|n| n.group == group)
+            .map(|n| self.importance(n.urgency))
+            .fold(base, i32::max);
+
+        let (meta, sent) = inner.groups.entry(group.to_owned()).or_insert_with(|| {
+            let meta = self
+                .configured
+                .get(group)
+                .cloned()
+                .unwrap_or_else(|| MessageGroup {
+                    id: group.to_owned(),
+                    title: group.to_owned(),
+                    ..Default::default()
+                });
+            // Make sure the group is sent the first time
+            (meta, i32::MIN)
+        });
+        if *sent != importance {
+            *sent = importance;
+            meta.importance = importance;
+            self.link.put_group(meta.clone());
+        }
+    }
+
+    fn importance(&self, urgency: Urgency) -> i32 {
+        match urgency {
+            Urgency::Low => self.urgency.low,
+            Urgency::Normal => self.urgency.normal,
+            Urgency::Critical => self.urgency.critical,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nadir_backend_common::FrontendConfig;
+
+    use super::*;
+
+    fn bridge(groups: Vec<MessageGroup>) -> Bridge {
+        let frontend = FrontendConfig {
+            url: "ws://127.0.0.1:1".parse().unwrap(),
+            secret: None,
+            tls_cert: None,
+        };
+        let config = Config {
+            frontend: frontend.clone(),
+            bus: None,
+            groups,
+            apps: vec![("Mail App".to_owned(), "mail".to_owned())]
+                .into_iter()
+                .collect(),
+            urgency: Default::default(),
+        };
+        Bridge::new(&config, Link::spawn(frontend).0)
+    }
+
+    fn notify(app: &str, replaces_id: u32, actions: &[&str], resident: bool) -> Notify {
+        Notify {
+            app_name: app.to_owned(),
+            replaces_id,
+            summary: "summary".to_owned(),
+            body: String::new(),
+            actions: actions.iter().map(|a| a.to_string()).collect(),
+            urgency: Urgency::Normal,
+            category: None,
+            resident,
+        }
+    }
+
+    #[tokio::test]
+    async fn replaces_id() {
+        let bridge = bridge(vec![]);
+        assert_eq!(bridge.notify(notify("a", 0, &[], false)).id, 1);
+        assert_eq!(bridge.notify(notify("a", 1, &[], false)).id, 1);
+        // Unknown IDs are not taken over
+        assert_eq!(bridge.notify(notify("a", 7, &[], false)).id, 2);
+        assert!(bridge.close(1));
+        assert!(!bridge.close(1));
+        assert_eq!(bridge.notify(notify("a", 1, &[], false)).id, 3);
+        // Replacing with another application moves it to that group
+        assert_eq!(bridge.notify(notify("b", 3, &[], false)).id, 3);
+        assert!(bridge.invoke("a", "3").is_none());
+        assert!(bridge.invoke("b", "3").is_some());
+    }
+
+    #[tokio::test]
+    async fn expire() {
+        let bridge = bridge(vec![]);
+        let first = bridge.notify(notify("a", 0, &[], false));
+        let replaced = bridge.notify(notify("a", first.id, &[], false));
+        assert_eq!(first.id, replaced.id);
+        // The timeout of the first version no longer applies
+        assert!(!bridge.expire(first.id, first.serial));
+        assert!(bridge.expire(replaced.id, replaced.serial));
+        assert!(!bridge.expire(replaced.id, replaced.serial));
+    }
+
+    #[tokio::test]
+    async fn drops_oldest() {
+        let bridge = bridge(vec![MessageGroup {
+            id: "mail".into(),
+            capacity: 2,
+            ..Default::default()
+        }]);
+        let ids: Vec<_> = (0..3)
+            .map(|_| bridge.notify(notify("Mail App", 0, &[], false)))
+            .map(|s| (s.id, s.dropped))
+            .collect();
+        assert_eq!(ids, [(1, vec![]), (2, vec![]), (3, vec![1])]);
+        assert!(!bridge.close(1));
+    }
+
+    #[tokio::test]
+    async fn invoke() {
+        let bridge = bridge(vec![]);
+        let id = bridge
+            .notify(notify("a", 0, &["open", "default"], false))
+            .id;
+        assert!(bridge.invoke("a", "x").is_none());
+        let invoked = bridge.invoke("a", &id.to_string()).unwrap();
+        assert_eq!(invoked.action.as_deref(), Some("default"));
+        assert!(invoked.closed);
+        assert!(bridge.invoke("a", &id.to_string()).is_none());
+
+        let id = bridge.notify(notify("a", 0, &["open"], true)).id;
+        let invoked = bridge.invoke("a", &id.to_string()).unwrap();
+        assert_eq!(invoked.action.as_deref(), Some("open"));
+        assert!(!invoked.closed);
+
+        let id = bridge.notify(notify("a", 0, &[], false)).id;
+        let invoked = bridge.invoke("a", &id.to_string()).unwrap();
+        assert_eq!(invoked.action, None);
+        assert!(invoked.closed);
+    }
+}
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+
+use nadir_backend_common::FrontendConfig;
+use nadir_types::model::MessageGroup;
+use serde::Deserialize;
+
+/// Config file for this backend
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    /// The frontend to send messages to.
+    pub frontend: FrontendConfig,
+
+    /// Address of the D-Bus bus to serve on. Defaults to the session bus.
+    #[serde(default)]
+    pub bus: Option<String>,
+
+    /// Groups created at start. Notifications of an application go into the
+    /// group whose ID is the application name, so groups listed here can set
+    /// the title and importance of specific applications.
+    #[serde(default)]
+    pub groups: Vec<MessageGroup>,
+
+    /// Rename applications before using them as group IDs, e.g.
+    /// `{ "Mozilla Firefox" = "firefox" }`.
+    #[serde(default)]
+    pub apps: HashMap<String, String>,
+
+    /// Importance of an application's group while it holds a notification
+    /// of each urgency.
+    #[serde(default)]
+    pub urgency: UrgencyConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct UrgencyConfig {
+    pub low: i32,
+    pub normal: i32,
+    pub critical: i32,
+}
+
+impl Default for UrgencyConfig {
+    fn default() -> Self {
+        UrgencyConfig {
+            low: -10,
+            normal: 0,
+            critical: 10,
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! A backend that serves `org.freedesktop.Notifications` on D-Bus, so desktop
+//! applications can send notifications to nadir.
+//!
+//! Notifications of each application go into a group named after it. Clicking
+//! a notification invokes its default action and dismisses it. Notifications
+//! expire after the timeout their application asks for, or when newer ones
+//! push them out of their group.
+//!
+//! ```toml
+//! [frontend]
+//! url = "ws://localhost:7890"
+//!
+//! [apps]
+//! "Mozilla Firefox" = "firefox"
+//!
+//! [[groups]]
+//! id = "firefox"
+//! title = "Firefox"
+//!
+//! [urgency]
+//! critical = 20
+//! ```
+mod bridge;
+mod config;
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use clap::Clap;
+use log::{info, warn};
+use nadir_backend_common::{click, err_and_exit, Link, Opt};
+use zbus::{dbus_interface, zvariant::Value, Connection, ConnectionBuilder, SignalContext};
+
+use self::{
+    bridge::{Bridge, CloseReason, Notify, Urgency},
+    config::Config,
+};
+
+const BUS_NAME: &str = "org.freedesktop.Notifications";
+const OBJECT_PATH: &str = "/org/freedesktop/Notifications";
+
+struct Notifications {
+    bridge: Arc<Bridge>,
+}
+
+#[dbus_interface(name = "org.freedesktop.Notifications")]
+impl Notifications {
+    #[allow(clippy::too_many_arguments)]
+    async fn notify(
+        &self,
+        app_name: String,
+        replaces_id: u32,
+        _app_icon: String,
+        summary: String,
+        body: String,
+        actions: Vec<String>,
+        hints: HashMap<String, Value<'_>>,
+        expire_timeout: i32,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> u32 {
+        let urgency = match hints.get("urgency") {
+            Some(Value::U8(u)) => Urgency::from_hint(*u),
+            _ => Urgency::Normal,
+        };
+        let category = match hints.get("category") {
+            Some(Value::Str(s)) => Some(s.to_string()),
+            _ => None,
+        };
+        let resident = matches!(hints.get("resident"), Some(Value::Bool(true)));
+        // Actions are pairs of keys and display names
+        let actions = actions.into_iter().step_by(2).collect();
+
+        let shown = self.bridge.notify(Notify {
+            app_name,
+            replaces_id,
+            summary,
+            body,
+            actions,
+            urgency,
+            category,
+            resident,
+        });
+        for &id in &shown.dropped {
+            if let Err(e) = Self::notification_closed(&ctxt, id, CloseReason::Expired as u32).await
+            {
+                warn!("failed to send signal: {}", e);
+            }
+        }
+
+        // Notifications without a timeout stay until they are closed or
+        // pushed out of their group
+        if expire_timeout > 0 {
+            let bridge = self.bridge.clone();
+            let ctxt = ctxt.to_owned();
+            let (id, serial) = (shown.id, shown.serial);
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(expire_timeout as u64)).await;
+                if bridge.expire(id, serial) {
+                    let reason = CloseReason::Expired as u32;
+                    if let Err(e) = Self::notification_closed(&ctxt, id, reason).await {
+                        warn!("failed to send signal: {}", e);
+                    }
+                }
+            });
+        }
+        shown.id
+    }
+
+    async fn close_notification(
+        &self,
+        id: u32,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> zbus::fdo::Result<()> {
+        if self.bridge.close(id) {
+            Self::notification_closed(&ctxt, id, CloseReason::Closed as u32).await?;
+        }
+        Ok(())
+    }
+
+    fn get_capabilities(&self) -> Vec<&str> {
+        vec!["actions", "body", "persistence"]
+    }
+
+    fn get_server_information(&self) -> (&str, &str, &str, &str) {
+        ("nadir", "nadir", env!("CARGO_PKG_VERSION"), "1.2")
+    }
+
+    #[dbus_interface(signal)]
+    async fn notification_closed(
+        ctxt: &SignalContext<'_>,
+        id: u32,
+        reason: u32,
+    ) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn action_invoked(
+        ctxt: &SignalContext<'_>,
+        id: u32,
+        action_key: &str,
+    ) -> zbus::Result<()>;
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let opt = Opt::parse();
+    let config: Config = opt.load_config("./nadir-notifications.toml");
+
+    let (link, mut frontend) = Link::spawn(config.frontend.clone());
+    let bridge = Arc::new(Bridge::new(&config, link));
+
+    let conn = match connect(&config, bridge.clone()).await {
+        Ok(conn) => conn,
+        Err(e) => err_and_exit(format_args!(
+            "Cannot serve {} on D-Bus.\nReason: {}",
+            BUS_NAME, e
+        )),
+    };
+    info!("serving {} on D-Bus", BUS_NAME);
+
+    while let Some(msg) = frontend.recv().await {
+        if let Some(action) = click(msg) {
+            let invoked = match bridge.invoke(&action.group, &action.message) {
+                Some(invoked) => invoked,
+                None => continue,
+            };
+            if let Err(e) = emit(&conn, invoked).await {
+                warn!("failed to send signal: {}", e);
+            }
+        }
+    }
+}
+
+async fn connect(config: &Config, bridge: Arc<Bridge>) -> zbus::Result<Connection> {
+    let builder = match &config.bus {
+        Some(addr) => ConnectionBuilder::address(addr.as_str())?,
+        None => ConnectionBuilder::session()?,
+    };
+    builder
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, Notifications { bridge })?
+        .build()
+        .await
+}
+
+async fn emit(conn: &Connection, invoked: bridge::Invoked) -> zbus::Result<()> {
+    let iface = conn
+        .object_server()
+        .interface::<_, Notifications>(OBJECT_PATH)
+        .await?;
+    let ctxt = iface.signal_context();
+    if let Some(action) = &invoked.action {
+        Notifications::action_invoked(ctxt, invoked.id, action).await?;
+    }
+    if invoked.closed {
+        Notifications::notification_closed(ctxt, invoked.id, CloseReason::Dismissed as u32).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,238 @@
+//! `nadir-send`: send notifications to a Nadir frontend from the command line.
+//!
+//! ```sh
+//! make && nadir-send --group builds --title Builds --tag make "build finished"
+//! ```
+use std::{io::Read, path::PathBuf};
+
+use chrono::Utc;
+use clap::Clap;
+use futures::SinkExt;
+use nadir_backend_common::{err_and_exit, FrontendConfig};
+use nadir_types::{
+    message::{ApiMessage, PutGroupMsg, PutMsg, RemoveMsg, SetGroupCounterMsg},
+    model::{Message, MessageGroup},
+};
+use serde::Deserialize;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use url::Url;
+
+/// Send notifications to a Nadir frontend.
+#[derive(Debug, Clap)]
+pub struct Opt {
+    /// Config file path. The file may contain `url`, `secret` and `tls_cert`.
+    #[clap(short, long, env = "NADIR_SEND_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// WebSocket address of the frontend. Overrides the config file.
+    #[clap(long, env = "NADIR_URL")]
+    pub url: Option<Url>,
+
+    /// Pre-shared secret of the frontend. Overrides the config file.
+    #[clap(long, env = "NADIR_SECRET")]
+    pub secret: Option<String>,
+
+    /// Certificate to trust when connecting with `wss://`. Overrides the
+    /// config file.
+    #[clap(long)]
+    pub tls_cert: Option<PathBuf>,
+
+    /// The group to operate on.
+    #[clap(short, long)]
+    pub group: Option<String>,
+
+    /// Create or update the group with this title before sending.
+    #[clap(long)]
+    pub title: Option<String>,
+
+    /// Importance of the group. Implies creating or updating the group.
+    #[clap(long)]
+    pub importance: Option<i32>,
+
+    /// Create or update the group before sending, titled with its ID unless
+    /// `--title` is given. The frontend ignores messages to groups that don't
+    /// exist, but putting a group replaces its title and importance, so leave
+    /// this out for groups owned by another backend.
+    #[clap(long)]
+    pub create_group: bool,
+
+    /// Tags of the message. Can be supplied multiple times.
+    #[clap(short, long, number_of_values = 1, multiple_occurrences = true)]
+    pub tag: Vec<String>,
+
+    /// ID of the message. Messages with the same ID replace each other.
+    /// Defaults to a timestamp so every message is kept.
+    #[clap(short, long)]
+    pub id: Option<String>,
+
+    /// Counter of the message.
+    #[clap(long)]
+    pub counter: Option<u64>,
+
+    /// Remove messages with the given IDs from the group. Can be supplied
+    /// multiple times.
+    #[clap(long, number_of_values = 1, multiple_occurrences = true)]
+    pub remove: Vec<String>,
+
+    /// Set the counter of the group.
+    #[clap(long)]
+    pub set_counter: Option<u64>,
+
+    /// Put the message into, or remove messages from, the pinned slot.
+    #[clap(long)]
+    pub pinned: bool,
+
+    /// Read protocol messages as JSON from stdin instead of building them from
+    /// arguments.
+    #[clap(long, conflicts_with_all = &["group", "body"])]
+    pub json: bool,
+
+    /// Body of the message to send.
+    pub body: Option<String>,
+}
+
+/// Config file for `nadir-send`. Every field can be overridden in the command
+/// line.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub url: Option<Url>,
+    pub secret: Option<String>,
+    pub tls_cert: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() {
+    let opt = Opt::parse();
+
+    let frontend = match frontend_config(&opt) {
+        Ok(f) => f,
+        Err(e) => err_and_exit(format_args!("{}", e)),
+    };
+
+    let messages = if opt.json {
+        read_json_messages()
+    } else {
+        build_messages(&opt)
+    };
+    let messages = match messages {
+        Ok(m) if m.is_empty() => err_and_exit(format_args!("Nothing to send")),
+        Ok(m) => m,
+        Err(e) => err_and_exit(format_args!("{}", e)),
+    };
+
+    let mut conn = match nadir_backend_common::connect(&frontend).await {
+        Ok(c) => c,
+        Err(e) => err_and_exit(format_args!(
+            "Cannot connect to '{}'.\nReason: {}",
+            frontend.url, e
+        )),
+    };
+
+    for msg in messages {
+        let text = serde_json::to_string(&msg).expect("messages are always serializable");
+        if let Err(e) = conn.send(WsMessage::Text(text)).await {
+            err_and_exit(format_args!("Failed to send message.\nReason: {}", e))
+        }
+    }
+    let _ = conn.close(None).await;
+}
+
+fn frontend_config(opt: &Opt) -> Result<FrontendConfig, String> {
+    let config = match &opt.config {
+        Some(path) => {
+            let file = std::fs::read(path).map_err(|e| {
+                format!(
+                    "Cannot read config file at path '{}'.\nReason: {}",
+                    path.display(),
+                    e
+                )
+            })?;
+            toml::from_slice(&file).map_err(|e| {
+                format!(
+                    "Failed to parse config file at '{}'\nReason: {}",
+                    path.display(),
+                    e
+                )
+            })?
+        }
+        None => Config::default(),
+    };
+
+    let url = opt
+        .url
+        .clone()
+        .or(config.url)
+        .ok_or("No frontend address. Supply one with --url or in the config file")?;
+    Ok(FrontendConfig {
+        url,
+        secret: opt.secret.clone().or(config.secret),
+        tls_cert: opt.tls_cert.clone().or(config.tls_cert),
+    })
+}
+
+/// Read a stream of whitespace-separated JSON values from stdin.
+fn read_json_messages() -> Result<Vec<ApiMessage>, String> {
+    let mut input = String::new();
+    std::io::stdin()
+        .read_to_string(&mut input)
+        .map_err(|e| format!("Failed to read stdin: {}", e))?;
+    serde_json::Deserializer::from_str(&input)
+        .into_iter::<ApiMessage>()
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Invalid message in stdin: {}", e))
+}
+
+fn build_messages(opt: &Opt) -> Result<Vec<ApiMessage>, String> {
+    let group = opt
+        .group
+        .clone()
+        .ok_or("A group is required, use --group")?;
+    let mut messages = vec![];
+
+    if opt.create_group || opt.title.is_some() || opt.importance.is_some() {
+        messages.push(ApiMessage::PutGroup(PutGroupMsg {
+            group: MessageGroup {
+                id: group.clone(),
+                title: opt.title.clone().unwrap_or_else(|| group.clone()),
+                importance: opt.importance.unwrap_or(0),
+                ..Default::default()
+            },
+        }));
+    }
+
+    if !opt.remove.is_empty() {
+        messages.push(ApiMessage::Remove(RemoveMsg {
+            group: group.clone(),
+            items: opt.remove.clone(),
+            pinned: opt.pinned,
+        }));
+    }
+
+    if let Some(counter) = opt.set_counter {
+        messages.push(ApiMessage::SetGroupCounter(SetGroupCounterMsg {
+            group: group.clone(),
+            counter,
+        }));
+    }
+
+    if let Some(body) = &opt.body {
+        let now = Utc::now();
+        messages.push(ApiMessage::Put(PutMsg {
+            group,
+            items: vec![Message {
+                id: opt
+                    .id
+                    .clone()
+                    .unwrap_or_else(|| now.timestamp_nanos_opt().unwrap_or_default().to_string()),
+                counter: opt.counter,
+                tags: opt.tag.clone(),
+                body: body.clone(),
+                time: Some(now),
+            }],
+            pinned: opt.pinned,
+        }));
+    }
+
+    Ok(messages)
+}
@@ -0,0 +1,129 @@
+use std::path::PathBuf;
+
+use nadir_backend_common::FrontendConfig;
+use nadir_types::model::MessageGroup;
+use serde::Deserialize;
+
+/// Config file for this backend
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    /// The frontend to send messages to.
+    pub frontend: FrontendConfig,
+
+    /// The group all messages go into.
+    #[serde(default = "default_group")]
+    pub group: MessageGroup,
+
+    /// Seconds between two checks.
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+
+    /// Values at which a message appears.
+    #[serde(default)]
+    pub thresholds: Thresholds,
+
+    /// A message only disappears after its value recovers past the threshold
+    /// by this fraction of it, so values near the threshold don't flap.
+    #[serde(default = "default_hysteresis")]
+    pub hysteresis: f64,
+
+    /// Checks that are not run.
+    #[serde(default)]
+    pub disable: Vec<Check>,
+
+    /// Mount points to check. Defaults to every mounted block device.
+    #[serde(default)]
+    pub mounts: Vec<PathBuf>,
+
+    /// Where procfs is mounted.
+    #[serde(default = "default_proc")]
+    pub proc_path: PathBuf,
+
+    /// Where sysfs is mounted.
+    #[serde(default = "default_sys")]
+    pub sys_path: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    Load,
+    Memory,
+    Swap,
+    Pressure,
+    Filesystem,
+    Temperature,
+    Battery,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Thresholds {
+    /// 1-minute load average per CPU.
+    pub load: f64,
+    /// Percent of memory in use.
+    pub memory: f64,
+    /// Percent of swap in use.
+    pub swap: f64,
+    /// Percent of time some tasks are stalled in the last 10 seconds, for
+    /// each of CPU, memory and IO.
+    pub pressure: f64,
+    /// Percent of a filesystem in use.
+    pub filesystem: f64,
+    /// Degrees Celsius of a thermal zone.
+    pub temperature: f64,
+    /// Percent of a discharging battery left. Messages appear below this.
+    pub battery: f64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            load: 1.5,
+            memory: 90.0,
+            swap: 80.0,
+            pressure: 40.0,
+            filesystem: 90.0,
+            temperature: 85.0,
+            battery: 15.0,
+        }
+    }
+}
+
+impl Thresholds {
+    pub fn get(&self, check: Check) -> f64 {
+        match check {
+            Check::Load => self.load,
+            Check::Memory => self.memory,
+            Check::Swap => self.swap,
+            Check::Pressure => self.pressure,
+            Check::Filesystem => self.filesystem,
+            Check::Temperature => self.temperature,
+            Check::Battery => self.battery,
+        }
+    }
+}
+
+fn default_group() -> MessageGroup {
+    MessageGroup {
+        id: "system".into(),
+        title: "System".into(),
+        ..Default::default()
+    }
+}
+
+fn default_interval() -> u64 {
+    10
+}
+
+fn default_hysteresis() -> f64 {
+    0.05
+}
+
+fn default_proc() -> PathBuf {
+    "/proc".into()
+}
+
+fn default_sys() -> PathBuf {
+    "/sys".into()
+}
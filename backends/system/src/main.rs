@@ -0,0 +1,251 @@
+//! A backend that watches the health of a Linux system through procfs and
+//! sysfs.
+//!
+//! Messages appear in the "System" group when a value crosses its threshold
+//! and disappear after it recovers. The counter of each message shows the
+//! measured value, usually in percent.
+//!
+//! ```toml
+//! interval = 30
+//! disable = ["battery"]
+//!
+//! [frontend]
+//! url = "ws://localhost:7890"
+//!
+//! [thresholds]
+//! memory = 85
+//! filesystem = 95
+//! ```
+mod config;
+mod probe;
+
+use std::{collections::HashSet, time::Duration};
+
+use chrono::Utc;
+use clap::Clap;
+use indexmap::IndexMap;
+use log::warn;
+use nadir_backend_common::{Link, Opt};
+use nadir_types::model::Message;
+
+use self::{
+    config::{Check, Config, Thresholds},
+    probe::{Probe, Reading},
+};
+
+const CHECKS: [Check; 7] = [
+    Check::Load,
+    Check::Memory,
+    Check::Swap,
+    Check::Pressure,
+    Check::Filesystem,
+    Check::Temperature,
+    Check::Battery,
+];
+
+/// Keeps messages in sync with values over their thresholds.
+struct Monitor {
+    link: Link,
+    group: String,
+    thresholds: Thresholds,
+    hysteresis: f64,
+
+    /// Messages currently shown and their checks, keyed by their IDs.
+    active: IndexMap<String, (Check, Message)>,
+    /// Checks that failed last time, so errors are only logged once.
+    failing: HashSet<Check>,
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let opt = Opt::parse();
+    let config: Config = opt.load_config("./nadir-system.toml");
+
+    let (link, mut frontend) = Link::spawn(config.frontend);
+    let group = config.group.id.clone();
+    link.put_group(config.group);
+
+    tokio::spawn(async move {
+        // This backend doesn't react to the frontend
+        while frontend.recv().await.is_some() {}
+    });
+
+    let disable = config.disable;
+    let checks: Vec<_> = CHECKS
+        .iter()
+        .copied()
+        .filter(|c| !disable.contains(c))
+        .collect();
+    let probe = Probe {
+        proc_path: config.proc_path,
+        sys_path: config.sys_path,
+        mounts: config.mounts,
+    };
+    let mut monitor = Monitor {
+        link,
+        group,
+        thresholds: config.thresholds,
+        hysteresis: config.hysteresis,
+        active: IndexMap::new(),
+        failing: HashSet::new(),
+    };
+
+    let mut timer = tokio::time::interval(Duration::from_secs(config.interval.max(1)));
+    loop {
+        timer.tick().await;
+        let mut readings = vec![];
+        for &check in &checks {
+            match probe.read(check) {
+                Ok(r) => {
+                    monitor.failing.remove(&check);
+                    readings.extend(r);
+                }
+                Err(e) => {
+                    if monitor.failing.insert(check) {
+                        warn!("{:?} check failed: {}", check, e);
+                    }
+                }
+            }
+        }
+        monitor.update(readings);
+    }
+}
+
+impl Monitor {
+    /// Whether a reading should be shown, given whether it's shown now.
+    fn is_alert(&self, reading: &Reading, active: bool) -> bool {
+        let threshold = self.thresholds.get(reading.check);
+        // Batteries alert on low values, everything else on high ones
+        let low = reading.check == Check::Battery;
+        let margin = if active {
+            threshold * self.hysteresis
+        } else {
+            0.0
+        };
+        if low {
+            reading.value <= threshold + margin
+        } else {
+            reading.value >= threshold - margin
+        }
+    }
+
+    /// Send the difference between shown messages and new readings. Messages
+    /// of failing checks are kept as they are, since nothing is known about
+    /// them.
+    fn update(&mut self, readings: Vec<Reading>) {
+        let now = Utc::now();
+        let mut current: IndexMap<_, _> = self
+            .active
+            .iter()
+            .filter(|(_, (check, _))| self.failing.contains(check))
+            .map(|(id, shown)| (id.clone(), shown.clone()))
+            .collect();
+        let mut changed = vec![];
+        for reading in readings {
+            let last = self.active.get(&reading.id).map(|(_, m)| m);
+            if !self.is_alert(&reading, last.is_some()) {
+                continue;
+            }
+            let msg = Message {
+                id: reading.id,
+                counter: Some(reading.counter),
+                tags: vec![],
+                body: reading.body,
+                time: Some(last.and_then(|m| m.time).unwrap_or(now)),
+            };
+            if last.is_none_or(|m| m.body != msg.body || m.counter != msg.counter) {
+                changed.push(msg.clone());
+            }
+            current.insert(msg.id.clone(), (reading.check, msg));
+        }
+
+        let removed: Vec<_> = self
+            .active
+            .keys()
+            .filter(|k| !current.contains_key(*k))
+            .cloned()
+            .collect();
+        self.link.remove(self.group.as_str(), removed);
+        self.link.put(self.group.as_str(), changed);
+        self.active = current;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nadir_backend_common::FrontendConfig;
+
+    use super::*;
+
+    fn monitor() -> Monitor {
+        let (link, _) = Link::spawn(FrontendConfig {
+            url: "ws://127.0.0.1:1".parse().unwrap(),
+            secret: None,
+            tls_cert: None,
+        });
+        Monitor {
+            link,
+            group: "system".into(),
+            thresholds: Thresholds {
+                memory: 90.0,
+                battery: 20.0,
+                ..Default::default()
+            },
+            hysteresis: 0.1,
+            active: IndexMap::new(),
+            failing: HashSet::new(),
+        }
+    }
+
+    fn reading(check: Check, value: f64) -> Reading {
+        Reading {
+            check,
+            id: format!("{:?}", check),
+            value,
+            body: format!("{:?} at {}", check, value),
+            counter: value as u64,
+        }
+    }
+
+    #[tokio::test]
+    async fn hysteresis() {
+        let monitor = monitor();
+        let alert = |check, value, active| monitor.is_alert(&reading(check, value), active);
+        assert!(!alert(Check::Memory, 89.0, false));
+        assert!(alert(Check::Memory, 90.0, false));
+        assert!(alert(Check::Memory, 81.0, true));
+        assert!(!alert(Check::Memory, 80.9, true));
+
+        // Batteries alert on low values
+        assert!(!alert(Check::Battery, 21.0, false));
+        assert!(alert(Check::Battery, 20.0, false));
+        assert!(alert(Check::Battery, 22.0, true));
+        assert!(!alert(Check::Battery, 22.1, true));
+    }
+
+    #[tokio::test]
+    async fn update() {
+        let mut monitor = monitor();
+        monitor.update(vec![
+            reading(Check::Memory, 95.0),
+            reading(Check::Battery, 50.0),
+        ]);
+        assert_eq!(monitor.active.keys().collect::<Vec<_>>(), ["Memory"]);
+        let time = monitor.active["Memory"].1.time;
+
+        // Still over the threshold with hysteresis, and shown since the start
+        monitor.update(vec![reading(Check::Memory, 85.0)]);
+        let (_, msg) = &monitor.active["Memory"];
+        assert_eq!((msg.counter, msg.time), (Some(85), time));
+
+        // Nothing is known about a failing check, so its message stays
+        monitor.failing.insert(Check::Memory);
+        monitor.update(vec![]);
+        assert!(monitor.active.contains_key("Memory"));
+
+        monitor.failing.clear();
+        monitor.update(vec![reading(Check::Memory, 50.0)]);
+        assert!(monitor.active.is_empty());
+    }
+}
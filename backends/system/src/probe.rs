@@ -0,0 +1,343 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::CString,
+    fs, io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+
+use crate::config::Check;
+
+/// A value measured by a check.
+#[derive(Debug)]
+pub struct Reading {
+    pub check: Check,
+    /// Unique among all readings, used as the message ID.
+    pub id: String,
+    /// The value compared against the threshold.
+    pub value: f64,
+    pub body: String,
+    pub counter: u64,
+}
+
+/// Reads system state from procfs and sysfs.
+pub struct Probe {
+    pub proc_path: PathBuf,
+    pub sys_path: PathBuf,
+    pub mounts: Vec<PathBuf>,
+}
+
+impl Probe {
+    pub fn read(&self, check: Check) -> io::Result<Vec<Reading>> {
+        match check {
+            Check::Load => self.load(),
+            Check::Memory => self.memory(),
+            Check::Swap => self.swap(),
+            Check::Pressure => self.pressure(),
+            Check::Filesystem => self.filesystems(),
+            Check::Temperature => self.temperature(),
+            Check::Battery => self.battery(),
+        }
+    }
+
+    fn load(&self) -> io::Result<Vec<Reading>> {
+        let loadavg = fs::read_to_string(self.proc_path.join("loadavg"))?;
+        let load: f64 = loadavg
+            .split_whitespace()
+            .next()
+            .and_then(|l| l.parse().ok())
+            .ok_or_else(|| invalid("loadavg"))?;
+        let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let per_cpu = load / cpus as f64;
+        Ok(vec![Reading {
+            check: Check::Load,
+            id: "load".into(),
+            value: per_cpu,
+            body: format!("Load {:.2} on {} CPUs", load, cpus),
+            counter: percent(per_cpu * 100.0),
+        }])
+    }
+
+    fn meminfo(&self) -> io::Result<HashMap<String, u64>> {
+        let meminfo = fs::read_to_string(self.proc_path.join("meminfo"))?;
+        Ok(meminfo
+            .lines()
+            .filter_map(|l| {
+                let (key, value) = l.split_once(':')?;
+                let kib = value.split_whitespace().next()?.parse().ok()?;
+                Some((key.to_owned(), kib))
+            })
+            .collect())
+    }
+
+    fn memory(&self) -> io::Result<Vec<Reading>> {
+        let info = self.meminfo()?;
+        let (total, available) = match (info.get("MemTotal"), info.get("MemAvailable")) {
+            (Some(&t), Some(&a)) if t > 0 => (t, a),
+            _ => return Err(invalid("meminfo")),
+        };
+        let used = 100.0 * total.saturating_sub(available) as f64 / total as f64;
+        Ok(vec![Reading {
+            check: Check::Memory,
+            id: "memory".into(),
+            value: used,
+            body: format!(
+                "Memory {:.0}% used, {} available",
+                used,
+                size(available * 1024)
+            ),
+            counter: percent(used),
+        }])
+    }
+
+    fn swap(&self) -> io::Result<Vec<Reading>> {
+        let info = self.meminfo()?;
+        let (total, free) = match (info.get("SwapTotal"), info.get("SwapFree")) {
+            (Some(&t), Some(&f)) => (t, f),
+            _ => return Err(invalid("meminfo")),
+        };
+        if total == 0 {
+            return Ok(vec![]);
+        }
+        let used = 100.0 * total.saturating_sub(free) as f64 / total as f64;
+        Ok(vec![Reading {
+            check: Check::Swap,
+            id: "swap".into(),
+            value: used,
+            body: format!("Swap {:.0}% used, {} free", used, size(free * 1024)),
+            counter: percent(used),
+        }])
+    }
+
+    fn pressure(&self) -> io::Result<Vec<Reading>> {
+        let mut res = vec![];
+        for (resource, name) in [("cpu", "CPU"), ("memory", "Memory"), ("io", "IO")] {
+            let content = match fs::read_to_string(self.proc_path.join("pressure").join(resource)) {
+                Ok(c) => c,
+                // Kernels without PSI don't have these files
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            let avg10 = content
+                .lines()
+                .find(|l| l.starts_with("some "))
+                .and_then(|l| l.split_whitespace().find_map(|f| f.strip_prefix("avg10=")))
+                .and_then(|v| v.parse::<f64>().ok())
+                .ok_or_else(|| invalid("pressure"))?;
+            res.push(Reading {
+                check: Check::Pressure,
+                id: format!("pressure/{}", resource),
+                value: avg10,
+                body: format!("{} pressure {:.0}%", name, avg10),
+                counter: percent(avg10),
+            });
+        }
+        Ok(res)
+    }
+
+    fn filesystems(&self) -> io::Result<Vec<Reading>> {
+        let mounts = if self.mounts.is_empty() {
+            self.block_mounts()?
+        } else {
+            self.mounts.clone()
+        };
+
+        let mut res = vec![];
+        for mount in mounts {
+            // One unreachable mount shouldn't hide the others
+            let (used, avail) = match statvfs(&mount) {
+                Ok(Some(x)) => x,
+                Ok(None) => continue,
+                Err(e) => {
+                    debug!("skipped {}: {}", mount.display(), e);
+                    continue;
+                }
+            };
+            let used_percent = 100.0 * used as f64 / (used + avail) as f64;
+            res.push(Reading {
+                check: Check::Filesystem,
+                id: format!("fs:{}", mount.display()),
+                value: used_percent,
+                body: format!(
+                    "{} {:.0}% full, {} free",
+                    mount.display(),
+                    used_percent,
+                    size(avail)
+                ),
+                counter: percent(used_percent),
+            });
+        }
+        Ok(res)
+    }
+
+    /// Mount points of block devices, each device once.
+    fn block_mounts(&self) -> io::Result<Vec<PathBuf>> {
+        let mounts = fs::read_to_string(self.proc_path.join("mounts"))?;
+        let mut devices = HashSet::new();
+        Ok(mounts
+            .lines()
+            .filter_map(|l| {
+                let mut fields = l.split_whitespace();
+                let device = fields.next()?;
+                let mount = fields.next()?;
+                if device.starts_with('/') && devices.insert(device) {
+                    Some(PathBuf::from(unescape_mount(mount)))
+                } else {
+                    None
+                }
+            })
+            .collect())
+    }
+
+    fn temperature(&self) -> io::Result<Vec<Reading>> {
+        let mut res = vec![];
+        for zone in self.class_dir("thermal")? {
+            let name = file_name(&zone);
+            if !name.starts_with("thermal_zone") {
+                continue;
+            }
+            let millis: f64 = match read_trimmed(&zone.join("temp")).map(|t| t.parse()) {
+                Ok(Ok(t)) => t,
+                // Some zones can't be read when their device is asleep
+                _ => continue,
+            };
+            let celsius = millis / 1000.0;
+            let kind = read_trimmed(&zone.join("type")).unwrap_or_else(|_| name.clone());
+            res.push(Reading {
+                check: Check::Temperature,
+                id: format!("thermal/{}", name),
+                value: celsius,
+                body: format!("{} {:.0} °C", kind, celsius),
+                counter: percent(celsius),
+            });
+        }
+        Ok(res)
+    }
+
+    fn battery(&self) -> io::Result<Vec<Reading>> {
+        let mut res = vec![];
+        for supply in self.class_dir("power_supply")? {
+            if read_trimmed(&supply.join("type")).ok().as_deref() != Some("Battery") {
+                continue;
+            }
+            // Only discharging batteries need attention
+            if read_trimmed(&supply.join("status")).ok().as_deref() != Some("Discharging") {
+                continue;
+            }
+            let capacity: f64 = match read_trimmed(&supply.join("capacity")).map(|c| c.parse()) {
+                Ok(Ok(c)) => c,
+                _ => continue,
+            };
+            let name = file_name(&supply);
+            res.push(Reading {
+                check: Check::Battery,
+                id: format!("battery/{}", name),
+                value: capacity,
+                body: format!("{} {:.0}% left", name, capacity),
+                counter: percent(capacity),
+            });
+        }
+        Ok(res)
+    }
+
+    /// Entries of a directory in `/sys/class`, sorted. Missing classes have no
+    /// entries.
+    fn class_dir(&self, class: &str) -> io::Result<Vec<PathBuf>> {
+        let dir = match fs::read_dir(self.sys_path.join("class").join(class)) {
+            Ok(d) => d,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        let mut entries = dir
+            .map(|e| e.map(|e| e.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+        Ok(entries)
+    }
+}
+
+/// Bytes used and available to unprivileged users of the filesystem at
+/// `path`, or `None` for filesystems without a size.
+fn statvfs(path: &Path) -> io::Result<Option<(u64, u64)>> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `c_path` is a valid C string and `stat` is only read after the
+    // call succeeds.
+    let stat = unsafe {
+        if libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        stat.assume_init()
+    };
+    if stat.f_blocks == 0 {
+        return Ok(None);
+    }
+    let frsize = stat.f_frsize as u64;
+    let used = (stat.f_blocks as u64 - stat.f_bfree as u64) * frsize;
+    let avail = stat.f_bavail as u64 * frsize;
+    Ok(Some((used, avail)))
+}
+
+/// Undo the octal escapes of whitespace in `/proc/mounts`.
+fn unescape_mount(s: &str) -> String {
+    let mut res = String::new();
+    let mut rest = s;
+    while let Some(i) = rest.find('\\') {
+        res.push_str(&rest[..i]);
+        let code = rest
+            .get(i + 1..i + 4)
+            .and_then(|c| u8::from_str_radix(c, 8).ok());
+        match code {
+            Some(c) => {
+                res.push(c as char);
+                rest = &rest[i + 4..];
+            }
+            None => {
+                res.push('\\');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    res.push_str(rest);
+    res
+}
+
+fn read_trimmed(path: &Path) -> io::Result<String> {
+    fs::read_to_string(path).map(|s| s.trim().to_owned())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn invalid(file: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected format of {}", file),
+    )
+}
+
+fn percent(v: f64) -> u64 {
+    v.round().max(0.0) as u64
+}
+
+/// Format a size in bytes for humans.
+fn size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,110 @@
+use std::net::SocketAddr;
+
+use nadir_backend_common::FrontendConfig;
+use nadir_types::model::MessageGroup;
+use serde::Deserialize;
+
+/// Config file for this backend
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    /// The frontend to send messages to.
+    pub frontend: FrontendConfig,
+
+    /// The address to receive webhooks at.
+    pub listen: SocketAddr,
+
+    /// Groups created at start. Groups used by hooks but not listed here are
+    /// created with their IDs as titles.
+    #[serde(default)]
+    pub groups: Vec<MessageGroup>,
+
+    /// Webhook endpoints.
+    #[serde(default)]
+    pub hooks: Vec<HookConfig>,
+}
+
+/// A webhook endpoint. Every string except `path` is a template, see
+/// [`nadir_backend_common::template`]. Fields left empty are filled by the
+/// preset, if any.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HookConfig {
+    /// The HTTP path receiving `POST` requests, e.g. `/alertmanager`.
+    pub path: String,
+
+    /// The group messages go into.
+    pub group: String,
+
+    /// Fill unset fields with templates for a well-known payload.
+    #[serde(default)]
+    pub preset: Option<Preset>,
+
+    /// A selector for an array in the payload. One message is produced for
+    /// each element, otherwise one for the whole payload.
+    #[serde(default)]
+    pub each: Option<String>,
+
+    /// Message ID. Defaults to a unique value for every message.
+    #[serde(default)]
+    pub id: Option<String>,
+
+    /// Message body.
+    #[serde(default)]
+    pub body: Option<String>,
+
+    /// Message tags. Tags rendering to empty strings are dropped.
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+
+    /// Message counter. Should refer to a number.
+    #[serde(default)]
+    pub counter: Option<String>,
+
+    /// Message time. Should refer to an RFC 3339 string or a UNIX timestamp.
+    /// Defaults to the time the webhook is received.
+    #[serde(default)]
+    pub time: Option<String>,
+
+    /// Remove the message with the rendered ID instead of adding one when
+    /// this condition holds.
+    #[serde(default)]
+    pub remove_when: Option<Condition>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Condition {
+    /// Template to render.
+    pub value: String,
+    /// The string the rendered value is compared against.
+    pub equals: String,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Preset {
+    /// Prometheus Alertmanager. Firing alerts are added and resolved ones are
+    /// removed.
+    Alertmanager,
+}
+
+impl Preset {
+    pub fn apply(self, hook: &mut HookConfig) {
+        match self {
+            Preset::Alertmanager => {
+                hook.each.get_or_insert_with(|| ".alerts".into());
+                hook.id
+                    .get_or_insert_with(|| "{.fingerprint|.labels.alertname}".into());
+                hook.body.get_or_insert_with(|| {
+                    "{.annotations.summary|.annotations.description|.labels.alertname}".into()
+                });
+                hook.tags.get_or_insert_with(|| {
+                    vec!["{.labels.severity}".into(), "{.labels.instance}".into()]
+                });
+                hook.time.get_or_insert_with(|| "{.startsAt}".into());
+                hook.remove_when.get_or_insert_with(|| Condition {
+                    value: "{.status}".into(),
+                    equals: "resolved".into(),
+                });
+            }
+        }
+    }
+}
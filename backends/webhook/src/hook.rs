@@ -0,0 +1,242 @@
+use chrono::Utc;
+use nadir_backend_common::template::{as_time, as_u64, Scope, Selector, Template};
+use nadir_types::{
+    message::{ApiMessage, PutMsg, RemoveMsg},
+    model::Message,
+};
+use serde_json::Value;
+
+use crate::config::HookConfig;
+
+/// A compiled [`HookConfig`].
+#[derive(Debug)]
+pub struct Hook {
+    group: Template,
+    each: Option<Selector>,
+    id: Option<Template>,
+    body: Template,
+    tags: Vec<Template>,
+    counter: Option<Template>,
+    time: Option<Template>,
+    remove_when: Option<(Template, String)>,
+}
+
+impl Hook {
+    pub fn new(mut config: HookConfig) -> Result<Hook, String> {
+        if let Some(preset) = config.preset {
+            preset.apply(&mut config);
+        }
+        let parse = |t: &Option<String>| t.as_deref().map(Template::parse).transpose();
+        let body = config
+            .body
+            .as_deref()
+            .ok_or_else(|| format!("hook '{}' has no body template", config.path))?;
+
+        Ok(Hook {
+            group: Template::parse(&config.group)?,
+            each: config.each.as_deref().map(Selector::parse).transpose()?,
+            id: parse(&config.id)?,
+            body: Template::parse(body)?,
+            tags: config
+                .tags
+                .iter()
+                .flatten()
+                .map(|t| Template::parse(t))
+                .collect::<Result<_, _>>()?,
+            counter: parse(&config.counter)?,
+            time: parse(&config.time)?,
+            remove_when: match config.remove_when {
+                Some(c) => Some((Template::parse(&c.value)?, c.equals)),
+                None => None,
+            },
+        })
+    }
+
+    /// Turn a webhook payload into protocol messages.
+    pub fn process(&self, payload: &Value) -> Vec<ApiMessage> {
+        let items = match &self.each {
+            Some(each) => {
+                let scope = Scope {
+                    root: payload,
+                    current: payload,
+                };
+                match each.select(scope) {
+                    Some(Value::Array(items)) => items.iter().collect(),
+                    Some(item) => vec![item],
+                    None => vec![],
+                }
+            }
+            None => vec![payload],
+        };
+
+        let now = Utc::now();
+        let mut res = vec![];
+        for (i, item) in items.into_iter().enumerate() {
+            let scope = Scope {
+                root: payload,
+                current: item,
+            };
+            let group = self.group.render(scope);
+            let id = match &self.id {
+                Some(id) => id.render(scope),
+                None => format!("{}-{}", now.timestamp_nanos_opt().unwrap_or_default(), i),
+            };
+
+            let remove = self
+                .remove_when
+                .as_ref()
+                .is_some_and(|(value, equals)| value.render(scope) == *equals);
+            if remove {
+                res.push(ApiMessage::Remove(RemoveMsg {
+                    group,
+                    items: vec![id],
+                    pinned: false,
+                }));
+                continue;
+            }
+
+            let msg = Message {
+                id,
+                counter: self
+                    .counter
+                    .as_ref()
+                    .and_then(|c| c.select(scope))
+                    .and_then(as_u64),
+                tags: self
+                    .tags
+                    .iter()
+                    .map(|t| t.render(scope))
+                    .filter(|t| !t.is_empty())
+                    .collect(),
+                body: self.body.render(scope),
+                time: Some(
+                    self.time
+                        .as_ref()
+                        .and_then(|t| t.select(scope))
+                        .and_then(as_time)
+                        .unwrap_or(now),
+                ),
+            };
+            res.push(ApiMessage::Put(PutMsg {
+                group,
+                items: vec![msg],
+                pinned: false,
+            }));
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::Preset;
+
+    fn alertmanager() -> Hook {
+        Hook::new(HookConfig {
+            path: "/alertmanager".into(),
+            group: "alerts".into(),
+            preset: Some(Preset::Alertmanager),
+            each: None,
+            id: None,
+            body: None,
+            tags: None,
+            counter: None,
+            time: None,
+            remove_when: None,
+        })
+        .unwrap()
+    }
+
+    /// A payload as sent by Alertmanager 0.25, with one alert in `status`.
+    fn payload(status: &str) -> Value {
+        let ends_at = match status {
+            "resolved" => "2023-03-01T10:05:00.000Z",
+            _ => "0001-01-01T00:00:00Z",
+        };
+        json!({
+            "receiver": "nadir",
+            "status": status,
+            "alerts": [{
+                "status": status,
+                "labels": {
+                    "alertname": "InstanceDown",
+                    "instance": "db1:9100",
+                    "job": "node",
+                    "severity": "critical"
+                },
+                "annotations": {
+                    "summary": "db1:9100 is down",
+                    "description": "db1:9100 of job node has been down for more than 1 minute."
+                },
+                "startsAt": "2023-03-01T10:00:00.000Z",
+                "endsAt": ends_at,
+                "generatorURL": "http://prometheus:9090/graph?g0.expr=up+%3D%3D+0",
+                "fingerprint": "8b7f1c0a2d3e4f56"
+            }],
+            "groupLabels": { "alertname": "InstanceDown" },
+            "commonLabels": { "alertname": "InstanceDown", "severity": "critical" },
+            "commonAnnotations": {},
+            "externalURL": "http://alertmanager:9093",
+            "version": "4",
+            "groupKey": "{}:{alertname=\"InstanceDown\"}",
+            "truncatedAlerts": 0
+        })
+    }
+
+    #[test]
+    fn alertmanager_firing() {
+        let msgs = alertmanager().process(&payload("firing"));
+        let put = match msgs.as_slice() {
+            [ApiMessage::Put(put)] => put,
+            other => panic!("expected one put, got {:?}", other),
+        };
+        assert_eq!(put.group, "alerts");
+        assert!(!put.pinned);
+        let msg = &put.items[0];
+        assert_eq!(msg.id, "8b7f1c0a2d3e4f56");
+        assert_eq!(msg.body, "db1:9100 is down");
+        assert_eq!(msg.tags, vec!["critical", "db1:9100"]);
+        assert_eq!(
+            msg.time.map(|t| t.to_rfc3339()).as_deref(),
+            Some("2023-03-01T10:00:00+00:00")
+        );
+    }
+
+    #[test]
+    fn alertmanager_resolved() {
+        let msgs = alertmanager().process(&payload("resolved"));
+        let remove = match msgs.as_slice() {
+            [ApiMessage::Remove(remove)] => remove,
+            other => panic!("expected one removal, got {:?}", other),
+        };
+        assert_eq!(remove.group, "alerts");
+        assert_eq!(remove.items, vec!["8b7f1c0a2d3e4f56"]);
+        assert!(!remove.pinned);
+    }
+
+    #[test]
+    fn alertmanager_fallbacks() {
+        let mut payload = payload("firing");
+        let alert = &mut payload["alerts"][0];
+        alert.as_object_mut().unwrap().remove("fingerprint");
+        alert["annotations"] = json!({});
+        alert["labels"].as_object_mut().unwrap().remove("instance");
+
+        let msgs = alertmanager().process(&payload);
+        let msg = match msgs.as_slice() {
+            [ApiMessage::Put(put)] => &put.items[0],
+            other => panic!("expected one put, got {:?}", other),
+        };
+        assert_eq!(msg.id, "InstanceDown");
+        assert_eq!(msg.body, "InstanceDown");
+        assert_eq!(msg.tags, vec!["critical"]);
+    }
+
+    #[test]
+    fn alertmanager_no_alerts() {
+        assert!(alertmanager().process(&json!({ "alerts": [] })).is_empty());
+    }
+}
@@ -0,0 +1,153 @@
+//! A backend that receives JSON webhooks and maps them into messages.
+//!
+//! ```toml
+//! listen = "127.0.0.1:9870"
+//!
+//! [frontend]
+//! url = "ws://localhost:7890"
+//!
+//! [[hooks]]
+//! path = "/alertmanager"
+//! group = "alerts"
+//! preset = "alertmanager"
+//!
+//! [[hooks]]
+//! path = "/gitea"
+//! group = "git"
+//! id = "{.repository.full_name}"
+//! body = "{.head_commit.message}"
+//! tags = ["{.pusher.login}", "{.repository.name}"]
+//! time = "{.head_commit.timestamp}"
+//! ```
+mod config;
+mod hook;
+
+use std::{convert::Infallible, sync::Arc};
+
+use clap::Clap;
+use hyper::{
+    body::HttpBody,
+    header::CONTENT_LENGTH,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use log::{info, warn};
+use nadir_backend_common::{err_and_exit, Link, Opt};
+
+use self::{config::Config, hook::Hook};
+
+/// Requests with larger bodies are rejected.
+const MAX_BODY: usize = 1024 * 1024;
+
+struct State {
+    link: Link,
+    hooks: Vec<(String, Hook)>,
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let opt = Opt::parse();
+    let config: Config = opt.load_config("./nadir-webhook.toml");
+
+    let (link, mut frontend) = Link::spawn(config.frontend);
+    for group in config.groups {
+        link.put_group(group);
+    }
+
+    let hooks = config
+        .hooks
+        .into_iter()
+        .map(|h| Ok((h.path.clone(), Hook::new(h)?)))
+        .collect::<Result<Vec<_>, String>>()
+        .unwrap_or_else(|e| err_and_exit(format_args!("Invalid hook.\nReason: {}", e)));
+
+    let state = Arc::new(State { link, hooks });
+
+    let make_svc = make_service_fn(move |_| {
+        let state = state.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(state.clone(), req))) }
+    });
+    let server = match Server::try_bind(&config.listen) {
+        Ok(s) => s.serve(make_svc),
+        Err(e) => err_and_exit(format_args!(
+            "Cannot listen on {}.\nReason: {}",
+            config.listen, e
+        )),
+    };
+    info!("listening on {}", config.listen);
+
+    tokio::spawn(async move {
+        // This backend doesn't react to the frontend
+        while frontend.recv().await.is_some() {}
+    });
+    if let Err(e) = server.await {
+        err_and_exit(format_args!("Server stopped.\nReason: {}", e))
+    }
+}
+
+async fn handle(state: Arc<State>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let hook = match state
+        .hooks
+        .iter()
+        .find(|(path, _)| path == req.uri().path())
+    {
+        Some((_, hook)) => hook,
+        None => return Ok(status(StatusCode::NOT_FOUND)),
+    };
+    if req.method() != Method::POST {
+        return Ok(status(StatusCode::METHOD_NOT_ALLOWED));
+    }
+
+    let length = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if length.is_some_and(|l| l > MAX_BODY) {
+        warn!("rejected request of {} bytes", length.unwrap_or_default());
+        return Ok(status(StatusCode::PAYLOAD_TOO_LARGE));
+    }
+    let body = match read_body(req.into_body()).await {
+        Ok(Some(b)) => b,
+        Ok(None) => {
+            warn!("rejected request larger than {} bytes", MAX_BODY);
+            return Ok(status(StatusCode::PAYLOAD_TOO_LARGE));
+        }
+        Err(e) => {
+            warn!("failed to read request: {}", e);
+            return Ok(status(StatusCode::BAD_REQUEST));
+        }
+    };
+    let payload = match serde_json::from_slice(&body) {
+        Ok(p) => p,
+        Err(e) => {
+            warn!("invalid payload: {}", e);
+            return Ok(status(StatusCode::BAD_REQUEST));
+        }
+    };
+
+    for msg in hook.process(&payload) {
+        state.link.send(msg);
+    }
+    Ok(status(StatusCode::NO_CONTENT))
+}
+
+/// Read the whole body, or `None` if it's larger than [`MAX_BODY`].
+async fn read_body(mut body: Body) -> Result<Option<Vec<u8>>, hyper::Error> {
+    let mut res = vec![];
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if res.len() + chunk.len() > MAX_BODY {
+            return Ok(None);
+        }
+        res.extend_from_slice(&chunk);
+    }
+    Ok(Some(res))
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    let mut resp = Response::new(Body::empty());
+    *resp.status_mut() = code;
+    resp
+}
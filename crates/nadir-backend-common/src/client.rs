@@ -0,0 +1,109 @@
+use std::{
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    client_async_tls_with_config,
+    tungstenite::{
+        self,
+        client::IntoClientRequest,
+        http::{header::AUTHORIZATION, HeaderValue},
+    },
+    Connector, MaybeTlsStream, WebSocketStream,
+};
+use url::Url;
+
+pub type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// How to reach a Nadir frontend. The `secret` and `tls_cert` fields mirror
+/// the ones in the frontend's own config file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrontendConfig {
+    /// WebSocket address of the frontend, e.g. `ws://localhost:7890`.
+    ///
+    /// `stdio:` makes [`Link`](crate::Link) exchange JSON lines through stdin
+    /// and stdout instead, for backends started by the frontend.
+    pub url: Url,
+
+    /// The pre-shared secret configured in the frontend, if any.
+    #[serde(default)]
+    pub secret: Option<String>,
+
+    /// A certificate to trust when connecting through `wss://`. Useful when
+    /// the frontend uses a self-signed certificate. The system roots are
+    /// still trusted when this is supplied.
+    #[serde(default)]
+    pub tls_cert: Option<PathBuf>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("url '{0}' has no host")]
+    NoHost(Url),
+
+    #[error("secret is not a valid header value")]
+    InvalidSecret,
+
+    #[error("failed to read certificate '{0}': {1}")]
+    Certificate(PathBuf, String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    WebSocket(Box<tungstenite::Error>),
+}
+
+impl From<tungstenite::Error> for ClientError {
+    fn from(e: tungstenite::Error) -> Self {
+        ClientError::WebSocket(Box::new(e))
+    }
+}
+
+/// Connect to the frontend described by `config`, sending the pre-shared
+/// secret in the handshake when one is set.
+pub async fn connect(config: &FrontendConfig) -> Result<WsStream, ClientError> {
+    let url = &config.url;
+    let mut request = url.as_str().into_client_request()?;
+    if let Some(secret) = &config.secret {
+        let value = HeaderValue::from_str(&format!("Bearer {}", secret))
+            .map_err(|_| ClientError::InvalidSecret)?;
+        request.headers_mut().insert(AUTHORIZATION, value);
+    }
+
+    let connector = match &config.tls_cert {
+        Some(path) => Some(Connector::Rustls(Arc::new(tls_config(path)?))),
+        None => None,
+    };
+
+    let host = url
+        .host_str()
+        .ok_or_else(|| ClientError::NoHost(url.clone()))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let stream = TcpStream::connect((host, port)).await?;
+
+    let (conn, _) = client_async_tls_with_config(request, stream, None, connector).await?;
+    Ok(conn)
+}
+
+fn tls_config(cert: &Path) -> Result<rustls::ClientConfig, ClientError> {
+    let file = std::fs::File::open(cert)?;
+    let certs = rustls::internal::pemfile::certs(&mut BufReader::new(file))
+        .map_err(|_| ClientError::Certificate(cert.to_owned(), "not a PEM file".into()))?;
+
+    let mut config = rustls::ClientConfig::new();
+    config
+        .root_store
+        .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+    for c in certs {
+        config
+            .root_store
+            .add(&c)
+            .map_err(|e| ClientError::Certificate(cert.to_owned(), e.to_string()))?;
+    }
+    Ok(config)
+}
@@ -1 +1,13 @@
+//! Common parts for Nadir backend adaptors.
+pub mod client;
+pub mod link;
+pub mod mirror;
+pub mod opt;
+pub mod template;
 
+pub use self::{
+    client::{connect, ClientError, FrontendConfig, WsStream},
+    link::{click, Link},
+    mirror::Mirror,
+    opt::{err_and_exit, Opt},
+};
@@ -0,0 +1,313 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use nadir_types::{
+    message::{
+        ApiMessage, PutGroupMsg, PutMsg, RemoveGroupMsg, RemoveMsg, SetGroupCounterMsg, UserAction,
+        UserActionMsg,
+    },
+    model::{Message, MessageGroup},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    select,
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+};
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+use crate::{client::connect, mirror::Mirror, FrontendConfig};
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A persistent link to a frontend.
+///
+/// Messages sent through the link are recorded in a [`Mirror`] and sent
+/// again whenever the connection is re-established, so backends don't need to
+/// care about the frontend restarting.
+///
+/// The frontend ignores messages to groups that don't exist, so groups used
+/// before being put are created with their ID as the title.
+#[derive(Debug, Clone)]
+pub struct Link {
+    tx: UnboundedSender<ApiMessage>,
+    /// Groups put through this link, shared by all clones.
+    groups: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Link {
+    /// Start connecting to the frontend in the background. The returned
+    /// receiver yields messages sent by the frontend.
+    pub fn spawn(config: FrontendConfig) -> (Link, UnboundedReceiver<ApiMessage>) {
+        let (tx, outgoing) = unbounded_channel();
+        let (incoming, rx) = unbounded_channel();
+        if config.url.scheme() == "stdio" {
+            tokio::spawn(stdio_loop(outgoing, incoming));
+        } else {
+            tokio::spawn(link_loop(config, outgoing, incoming));
+        }
+        let link = Link {
+            tx,
+            groups: Default::default(),
+        };
+        (link, rx)
+    }
+
+    pub fn send(&self, msg: ApiMessage) {
+        // Held while sending so the group is created before anyone else uses it
+        let mut groups = self.groups.lock().unwrap();
+        match &msg {
+            ApiMessage::PutGroup(PutGroupMsg { group }) => {
+                groups.insert(group.id.clone());
+            }
+            ApiMessage::RemoveGroup(RemoveGroupMsg { group }) => {
+                groups.remove(group);
+            }
+            ApiMessage::Put(PutMsg { group, .. })
+            | ApiMessage::SetGroupCounter(SetGroupCounterMsg { group, .. })
+                if groups.insert(group.clone()) =>
+            {
+                let _ = self.tx.send(ApiMessage::PutGroup(PutGroupMsg {
+                    group: MessageGroup {
+                        id: group.clone(),
+                        title: group.clone(),
+                        ..Default::default()
+                    },
+                }));
+            }
+            _ => {}
+        }
+        let _ = self.tx.send(msg);
+    }
+
+    pub fn put_group(&self, group: MessageGroup) {
+        self.send(ApiMessage::PutGroup(PutGroupMsg { group }))
+    }
+
+    pub fn put(&self, group: impl Into<String>, items: Vec<Message>) {
+        self.put_to(group.into(), items, false)
+    }
+
+    pub fn put_pinned(&self, group: impl Into<String>, items: Vec<Message>) {
+        self.put_to(group.into(), items, true)
+    }
+
+    pub fn remove(&self, group: impl Into<String>, items: Vec<String>) {
+        self.remove_from(group.into(), items, false)
+    }
+
+    pub fn remove_pinned(&self, group: impl Into<String>, items: Vec<String>) {
+        self.remove_from(group.into(), items, true)
+    }
+
+    fn put_to(&self, group: String, items: Vec<Message>, pinned: bool) {
+        if items.is_empty() {
+            return;
+        }
+        self.send(ApiMessage::Put(PutMsg {
+            group,
+            items,
+            pinned,
+        }))
+    }
+
+    fn remove_from(&self, group: String, items: Vec<String>, pinned: bool) {
+        if items.is_empty() {
+            return;
+        }
+        self.send(ApiMessage::Remove(RemoveMsg {
+            group,
+            items,
+            pinned,
+        }))
+    }
+
+    pub fn set_counter(&self, group: impl Into<String>, counter: u64) {
+        self.send(ApiMessage::SetGroupCounter(SetGroupCounterMsg {
+            group: group.into(),
+            counter,
+        }))
+    }
+}
+
+/// The action of a message from the frontend, if it's the user clicking a
+/// message. Acknowledgements are sent as user actions too, so backends reacting
+/// to clicks should check for them with this.
+pub fn click(msg: ApiMessage) -> Option<UserActionMsg> {
+    match msg {
+        ApiMessage::UserAction(action) if action.action == UserAction::Click => Some(action),
+        _ => None,
+    }
+}
+
+async fn link_loop(
+    config: FrontendConfig,
+    mut outgoing: UnboundedReceiver<ApiMessage>,
+    incoming: UnboundedSender<ApiMessage>,
+) {
+    let mut mirror = Mirror::new();
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        let mut conn = match connect(&config).await {
+            Ok(conn) => {
+                info!("connected to {}", config.url);
+                backoff = MIN_BACKOFF;
+                conn
+            }
+            Err(e) => {
+                warn!("failed to connect to {}: {}", config.url, e);
+                if !record_for(&mut outgoing, &mut mirror, backoff).await {
+                    return;
+                }
+                backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+                continue;
+            }
+        };
+
+        let resync = async {
+            for msg in mirror.replay() {
+                conn.feed(to_ws(&msg)).await?;
+            }
+            conn.flush().await
+        };
+        if let Err(e) = resync.await {
+            warn!("failed to resync with {}: {}", config.url, e);
+            if !record_for(&mut outgoing, &mut mirror, backoff).await {
+                return;
+            }
+            continue;
+        }
+
+        loop {
+            select! {
+                msg = outgoing.recv() => {
+                    let msg = match msg {
+                        Some(msg) => msg,
+                        None => {
+                            let _ = conn.close(None).await;
+                            return;
+                        }
+                    };
+                    mirror.apply(&msg);
+                    if let Err(e) = conn.send(to_ws(&msg)).await {
+                        warn!("connection to {} lost: {}", config.url, e);
+                        break;
+                    }
+                }
+                frame = conn.next() => match frame {
+                    Some(Ok(WsMessage::Text(t))) => match serde_json::from_str(&t) {
+                        Ok(msg) => {
+                            let _ = incoming.send(msg);
+                        }
+                        Err(e) => debug!("ignored message from frontend: {}", e),
+                    },
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => {
+                        warn!("connection to {} lost: {}", config.url, e);
+                        break;
+                    }
+                    None => {
+                        warn!("connection to {} closed", config.url);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Exchange JSON lines with a frontend that started this backend. There's
+/// nothing to reconnect to, so the backend exits when the frontend is gone.
+async fn stdio_loop(
+    mut outgoing: UnboundedReceiver<ApiMessage>,
+    incoming: UnboundedSender<ApiMessage>,
+) {
+    let mut stdout = tokio::io::stdout();
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        select! {
+            msg = outgoing.recv() => {
+                let msg = match msg {
+                    Some(msg) => msg,
+                    None => return,
+                };
+                let mut line = serde_json::to_vec(&msg).expect("messages are always serializable");
+                line.push(b'\n');
+                let res = async {
+                    stdout.write_all(&line).await?;
+                    stdout.flush().await
+                };
+                if let Err(e) = res.await {
+                    warn!("frontend closed stdout: {}", e);
+                    std::process::exit(0);
+                }
+            }
+            line = lines.next_line() => match line {
+                Ok(Some(line)) if line.trim().is_empty() => {}
+                Ok(Some(line)) => match serde_json::from_str(&line) {
+                    Ok(msg) => {
+                        let _ = incoming.send(msg);
+                    }
+                    Err(e) => debug!("ignored message from frontend: {}", e),
+                },
+                Ok(None) | Err(_) => {
+                    info!("frontend closed stdin");
+                    std::process::exit(0);
+                }
+            }
+        }
+    }
+}
+
+/// Keep recording outgoing messages for `duration` while not connected.
+/// Returns `false` if the link is dropped.
+async fn record_for(
+    outgoing: &mut UnboundedReceiver<ApiMessage>,
+    mirror: &mut Mirror,
+    duration: Duration,
+) -> bool {
+    let sleep = tokio::time::sleep(duration);
+    tokio::pin!(sleep);
+    loop {
+        select! {
+            msg = outgoing.recv() => match msg {
+                Some(msg) => mirror.apply(&msg),
+                None => return false,
+            },
+            _ = &mut sleep => return true,
+        }
+    }
+}
+
+fn to_ws(msg: &ApiMessage) -> WsMessage {
+    WsMessage::Text(serde_json::to_string(msg).expect("messages are always serializable"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(action: UserAction) -> ApiMessage {
+        ApiMessage::UserAction(UserActionMsg {
+            group: "g".into(),
+            message: "m".into(),
+            action,
+        })
+    }
+
+    #[test]
+    fn only_clicks() {
+        let clicked = click(action(UserAction::Click)).unwrap();
+        assert_eq!(
+            (clicked.group.as_str(), clicked.message.as_str()),
+            ("g", "m")
+        );
+        assert!(click(action(UserAction::Acknowledge)).is_none());
+        assert!(click(ApiMessage::Config).is_none());
+    }
+}
@@ -0,0 +1,184 @@
+use std::cmp::min;
+
+use indexmap::IndexMap;
+use nadir_types::{
+    message::{ApiMessage, PutGroupMsg, PutMsg, SetGroupCounterMsg},
+    model::{Message, MessageGroup},
+};
+
+/// The frontend keeps at most this many messages per group, whatever the
+/// group's capacity.
+const CAPACITY_HARD_MAX: usize = 400;
+
+/// A local copy of everything a backend has sent to the frontend, so the same
+/// state can be sent again after reconnecting.
+#[derive(Debug, Default)]
+pub struct Mirror {
+    groups: IndexMap<String, GroupState>,
+}
+
+#[derive(Debug, Default)]
+struct GroupState {
+    /// Group metadata. `None` if the group is created by someone else.
+    meta: Option<MessageGroup>,
+    counter: Option<u64>,
+    msgs: IndexMap<String, Message>,
+    pinned: IndexMap<String, Message>,
+}
+
+impl GroupState {
+    fn slot(&mut self, pinned: bool) -> &mut IndexMap<String, Message> {
+        if pinned {
+            &mut self.pinned
+        } else {
+            &mut self.msgs
+        }
+    }
+
+    /// Drop the oldest messages the frontend would have dropped too.
+    fn trim(&mut self) {
+        let (cap, pinned_cap) = match &self.meta {
+            Some(meta) => (meta.capacity, meta.pinned_capacity),
+            None => (CAPACITY_HARD_MAX as u32, CAPACITY_HARD_MAX as u32),
+        };
+        for (slot, cap) in [(&mut self.msgs, cap), (&mut self.pinned, pinned_cap)] {
+            let cap = min(cap as usize, CAPACITY_HARD_MAX);
+            if slot.len() > cap {
+                let excess = slot.len() - cap;
+                slot.drain(..excess);
+            }
+        }
+    }
+}
+
+impl Mirror {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the effect of a message that is being sent to the frontend.
+    pub fn apply(&mut self, msg: &ApiMessage) {
+        match msg {
+            ApiMessage::Put(msg) => {
+                let group = self.groups.entry(msg.group.clone()).or_default();
+                let slot = group.slot(msg.pinned);
+                for item in &msg.items {
+                    // Re-inserting moves the message to the newest position
+                    slot.shift_remove(&item.id);
+                    slot.insert(item.id.clone(), item.clone());
+                }
+                group.trim();
+            }
+            ApiMessage::Remove(msg) => {
+                if let Some(group) = self.groups.get_mut(&msg.group) {
+                    let slot = group.slot(msg.pinned);
+                    for id in &msg.items {
+                        slot.shift_remove(id);
+                    }
+                }
+            }
+            ApiMessage::PutGroup(msg) => {
+                let group = self.groups.entry(msg.group.id.clone()).or_default();
+                group.meta = Some(msg.group.clone());
+                group.trim();
+            }
+            ApiMessage::RemoveGroup(msg) => {
+                self.groups.shift_remove(&msg.group);
+            }
+            ApiMessage::SetGroupCounter(msg) => {
+                let group = self.groups.entry(msg.group.clone()).or_default();
+                group.counter = Some(msg.counter);
+            }
+            _ => {}
+        }
+    }
+
+    /// Messages that rebuild the recorded state on a fresh frontend.
+    pub fn replay(&self) -> Vec<ApiMessage> {
+        let mut res = vec![];
+        for (id, group) in &self.groups {
+            if let Some(meta) = &group.meta {
+                res.push(ApiMessage::PutGroup(PutGroupMsg {
+                    group: meta.clone(),
+                }));
+            }
+            if let Some(counter) = group.counter {
+                res.push(ApiMessage::SetGroupCounter(SetGroupCounterMsg {
+                    group: id.clone(),
+                    counter,
+                }));
+            }
+            for (pinned, slot) in [(false, &group.msgs), (true, &group.pinned)] {
+                if !slot.is_empty() {
+                    res.push(ApiMessage::Put(PutMsg {
+                        group: id.clone(),
+                        items: slot.values().cloned().collect(),
+                        pinned,
+                    }));
+                }
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put(group: &str, ids: &[&str]) -> ApiMessage {
+        ApiMessage::Put(PutMsg {
+            group: group.into(),
+            items: ids
+                .iter()
+                .map(|id| Message {
+                    id: id.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            pinned: false,
+        })
+    }
+
+    fn replayed_ids(mirror: &Mirror) -> Vec<String> {
+        mirror
+            .replay()
+            .into_iter()
+            .filter_map(|msg| match msg {
+                ApiMessage::Put(msg) => Some(msg.items),
+                _ => None,
+            })
+            .flatten()
+            .map(|m| m.id)
+            .collect()
+    }
+
+    #[test]
+    fn trims_to_capacity() {
+        let mut mirror = Mirror::new();
+        mirror.apply(&ApiMessage::PutGroup(PutGroupMsg {
+            group: MessageGroup {
+                id: "g".into(),
+                capacity: 3,
+                ..Default::default()
+            },
+        }));
+        mirror.apply(&put("g", &["a", "b", "c"]));
+        mirror.apply(&put("g", &["a", "d"]));
+        assert_eq!(replayed_ids(&mirror), ["c", "a", "d"]);
+
+        mirror.apply(&ApiMessage::PutGroup(PutGroupMsg {
+            group: MessageGroup {
+                id: "g".into(),
+                capacity: 1,
+                ..Default::default()
+            },
+        }));
+        assert_eq!(replayed_ids(&mirror), ["d"]);
+
+        let ids: Vec<_> = (0..CAPACITY_HARD_MAX + 10).map(|i| i.to_string()).collect();
+        let ids: Vec<_> = ids.iter().map(|s| s.as_str()).collect();
+        mirror.apply(&put("other", &ids));
+        assert_eq!(replayed_ids(&mirror).len(), 1 + CAPACITY_HARD_MAX);
+    }
+}
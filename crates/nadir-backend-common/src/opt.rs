@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+
+use clap::Clap;
+use serde::de::DeserializeOwned;
+
+/// Start options shared by backends.
+#[derive(Debug, Clap)]
+pub struct Opt {
+    /// Config path.
+    #[clap(short, long)]
+    pub config: Option<PathBuf>,
+}
+
+impl Opt {
+    /// Read and parse the config file, or `default_path` if none is supplied.
+    /// Exits the program if the file cannot be used.
+    pub fn load_config<T: DeserializeOwned>(&self, default_path: &str) -> T {
+        let config = self.config.clone().unwrap_or_else(|| default_path.into());
+        let config_file = match std::fs::read(&config) {
+            Ok(c) => c,
+            Err(e) => err_and_exit(format_args!(
+                "Cannot read config file at path '{}'.\nReason: {}",
+                config.display(),
+                e
+            )),
+        };
+        match toml::from_slice(&config_file) {
+            Ok(c) => c,
+            Err(e) => err_and_exit(format_args!(
+                "Failed to parse config file at '{}'\nReason: {}",
+                config.display(),
+                e
+            )),
+        }
+    }
+}
+
+pub fn err_and_exit(message: std::fmt::Arguments) -> ! {
+    eprintln!("Error: {}", message);
+    std::process::exit(1);
+}
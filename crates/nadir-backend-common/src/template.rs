@@ -0,0 +1,279 @@
+//! A tiny templating language over JSON values.
+//!
+//! A template is a string with `{selector}` placeholders. Selectors are paths
+//! like `.alerts[0].labels.severity`, starting from the current item, or
+//! `$.receiver` starting from the whole payload. Several selectors can be
+//! joined with `|`, and the first one that exists is used. Use `{{` and `}}`
+//! for literal braces.
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+/// A path into a JSON value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    from_root: bool,
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    /// Alternatives, the first one that exists is used.
+    Value(Vec<Selector>),
+}
+
+/// A parsed template string.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+/// Where selectors are evaluated.
+#[derive(Debug, Clone, Copy)]
+pub struct Scope<'a> {
+    pub root: &'a Value,
+    pub current: &'a Value,
+}
+
+impl Selector {
+    pub fn parse(s: &str) -> Result<Selector, String> {
+        let s = s.trim();
+        let (from_root, mut rest) = match s.strip_prefix('$') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        if !from_root && !rest.starts_with('.') {
+            return Err(format!("selector '{}' should start with '.' or '$'", s));
+        }
+
+        let mut steps = vec![];
+        while !rest.is_empty() {
+            if let Some(r) = rest.strip_prefix('.') {
+                let end = r.find(['.', '[']).unwrap_or(r.len());
+                if end > 0 {
+                    steps.push(Step::Key(r[..end].to_owned()));
+                }
+                rest = &r[end..];
+            } else if let Some(r) = rest.strip_prefix('[') {
+                let end = r
+                    .find(']')
+                    .ok_or_else(|| format!("unclosed '[' in selector '{}'", s))?;
+                let inner = r[..end].trim();
+                let step = match inner.parse() {
+                    Ok(i) => Step::Index(i),
+                    Err(_) => Step::Key(inner.trim_matches(|c| c == '"' || c == '\'').to_owned()),
+                };
+                steps.push(step);
+                rest = &r[end + 1..];
+            } else {
+                return Err(format!("unexpected '{}' in selector '{}'", rest, s));
+            }
+        }
+        Ok(Selector { from_root, steps })
+    }
+
+    pub fn select<'a>(&self, scope: Scope<'a>) -> Option<&'a Value> {
+        let mut value = if self.from_root {
+            scope.root
+        } else {
+            scope.current
+        };
+        for step in &self.steps {
+            value = match step {
+                Step::Key(k) => value.get(k)?,
+                Step::Index(i) => value.get(i)?,
+            };
+        }
+        if value.is_null() {
+            None
+        } else {
+            Some(value)
+        }
+    }
+}
+
+impl Template {
+    pub fn parse(s: &str) -> Result<Template, String> {
+        let mut segments = vec![];
+        let mut text = String::new();
+        let mut chars = s.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '{' if chars.peek().map(|x| x.1) == Some('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek().map(|x| x.1) == Some('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let end = s[i..]
+                        .find('}')
+                        .ok_or_else(|| format!("unclosed '{{' in template '{}'", s))?;
+                    let selectors = s[i + 1..i + end]
+                        .split('|')
+                        .map(Selector::parse)
+                        .collect::<Result<_, _>>()?;
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Value(selectors));
+                    while chars.peek().is_some_and(|x| x.0 <= i + end) {
+                        chars.next();
+                    }
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(Template { segments })
+    }
+
+    /// If this template is a single placeholder, get the raw value it refers
+    /// to.
+    pub fn select<'a>(&self, scope: Scope<'a>) -> Option<&'a Value> {
+        match self.segments.as_slice() {
+            [Segment::Value(sel)] => sel.iter().find_map(|s| s.select(scope)),
+            _ => None,
+        }
+    }
+
+    /// Render the template. Missing values are rendered as empty strings.
+    pub fn render(&self, scope: Scope) -> String {
+        let mut res = String::new();
+        for seg in &self.segments {
+            match seg {
+                Segment::Text(t) => res.push_str(t),
+                Segment::Value(sel) => match sel.iter().find_map(|s| s.select(scope)) {
+                    Some(Value::String(s)) => res.push_str(s),
+                    Some(v) => res.push_str(&v.to_string()),
+                    None => {}
+                },
+            }
+        }
+        res
+    }
+}
+
+/// Read a number, or a string containing one.
+pub fn as_u64(v: &Value) -> Option<u64> {
+    match v {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Read a UNIX timestamp or an RFC 3339 string.
+pub fn as_time(v: &Value) -> Option<DateTime<Utc>> {
+    match v {
+        Value::Number(n) => n.as_i64().and_then(|t| Utc.timestamp_opt(t, 0).single()),
+        Value::String(s) => DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|t| t.with_timezone(&Utc)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn render(template: &str, value: &Value) -> String {
+        let scope = Scope {
+            root: value,
+            current: value,
+        };
+        Template::parse(template).unwrap().render(scope)
+    }
+
+    #[test]
+    fn escaped_braces() {
+        let value = json!({ "a": 1 });
+        assert_eq!(render("{{literal}}", &value), "{literal}");
+        assert_eq!(render("{{{.a}}}", &value), "{1}");
+        assert_eq!(render("a }} b", &value), "a } b");
+    }
+
+    #[test]
+    fn fallbacks() {
+        let value = json!({ "b": "second", "c": "third", "n": null });
+        assert_eq!(render("{.a|.b|.c}", &value), "second");
+        assert_eq!(render("{ .n | .c }", &value), "third");
+        assert_eq!(render("x{.a|.z}y", &value), "xy");
+    }
+
+    #[test]
+    fn quoted_keys() {
+        let value = json!({
+            "labels": { "app.kubernetes.io/name": "web", "with space": "s" },
+            "list": [{ "k": "first" }]
+        });
+        assert_eq!(
+            render(r#"{.labels["app.kubernetes.io/name"]}"#, &value),
+            "web"
+        );
+        assert_eq!(render("{.labels['with space']}", &value), "s");
+        assert_eq!(render("{.list[0].k}", &value), "first");
+        assert_eq!(render("{.list[1].k}", &value), "");
+    }
+
+    #[test]
+    fn root_and_current() {
+        let root = json!({ "receiver": "team", "alerts": [{ "name": "a" }] });
+        let scope = Scope {
+            root: &root,
+            current: &root["alerts"][0],
+        };
+        let template = Template::parse("{$.receiver}/{.name}").unwrap();
+        assert_eq!(template.render(scope), "team/a");
+    }
+
+    #[test]
+    fn non_string_values() {
+        let value = json!({ "n": 3, "b": true, "o": { "x": 1 } });
+        assert_eq!(render("{.n} {.b} {.o}", &value), r#"3 true {"x":1}"#);
+        let template = Template::parse("{.n}").unwrap();
+        let scope = Scope {
+            root: &value,
+            current: &value,
+        };
+        assert_eq!(template.select(scope).and_then(as_u64), Some(3));
+        assert_eq!(Template::parse("n={.n}").unwrap().select(scope), None);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Template::parse("{.a").is_err());
+        assert!(Template::parse("a {.b|.c").is_err());
+        assert!(Template::parse("{a}").is_err());
+        assert!(Template::parse("{.a[0}").is_err());
+        assert!(Selector::parse("labels").is_err());
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(
+            as_time(&json!(0)).map(|t| t.to_rfc3339()).as_deref(),
+            Some("1970-01-01T00:00:00+00:00")
+        );
+        assert_eq!(
+            as_time(&json!("2023-03-01T10:00:00+01:00"))
+                .map(|t| t.to_rfc3339())
+                .as_deref(),
+            Some("2023-03-01T09:00:00+00:00")
+        );
+        assert_eq!(as_time(&json!("yesterday")), None);
+    }
+}
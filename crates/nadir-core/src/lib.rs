@@ -0,0 +1,13 @@
+//! The state of a Nadir frontend, independent of how it is shown.
+//!
+//! A [`Store`] holds the message groups and applies [`ApiMessage`]s to them
+//! with the same semantics as `nadir-notify`. Frontends show the groups in
+//! [`Store::groups`], and can follow what changed through
+//! [`Store::subscribe`].
+//!
+//! [`ApiMessage`]: nadir_types::message::ApiMessage
+pub mod model;
+mod store;
+pub mod util;
+
+pub use self::store::{Change, Store};
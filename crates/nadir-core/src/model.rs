@@ -0,0 +1,5 @@
+//! The groups and messages shown by a frontend.
+pub mod group_list;
+pub mod message_group;
+
+pub use self::{group_list::GroupRef, message_group::MessageGroup};
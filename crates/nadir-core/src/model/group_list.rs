@@ -1,8 +1,13 @@
-use std::iter::once;
+use std::{collections::HashMap, iter::once, sync::Arc};
 
+use chrono::{DateTime, Utc};
 use indexmap::IndexMap;
 
-use crate::view::group_view::GroupRef;
+use super::MessageGroup;
+use crate::util::DirtyCheckLock;
+
+/// A message group shared between the state and the views showing it.
+pub type GroupRef = Arc<DirtyCheckLock<MessageGroup>>;
 
 /// A list of message groups, sorted by their metadata.
 #[derive(Debug, Default)]
@@ -10,6 +15,10 @@ pub struct GroupList {
     /// The mapping from group IDs to groups themselves, sorted by their
     /// importance and then their ID.
     map: IndexMap<String, (i32, GroupRef)>,
+
+    /// IDs of muted groups, with the time snoozed ones are shown again.
+    /// Groups stay muted when they are removed.
+    mutes: HashMap<String, Option<DateTime<Utc>>>,
 }
 
 impl GroupList {
@@ -72,8 +81,30 @@ impl GroupList {
     pub fn iter(&self) -> impl Iterator<Item = (&str, &GroupRef)> {
         self.map.iter().map(|(k, (_, v))| (k.as_str(), v))
     }
-}
 
-struct GroupEntry {
-    // id:
+    /// Mute a group, until `until` if set. Returns false if it already was.
+    pub fn mute(&mut self, id: impl Into<String>, until: Option<DateTime<Utc>>) -> bool {
+        self.mutes.insert(id.into(), until) != Some(until)
+    }
+
+    /// Returns false if the group wasn't muted.
+    pub fn unmute(&mut self, id: impl AsRef<str>) -> bool {
+        self.mutes.remove(id.as_ref()).is_some()
+    }
+
+    /// Whether the group is muted, with the time it is shown again if it is
+    /// snoozed. Snoozes past their time still count until they're unmuted.
+    pub fn muted(&self, id: impl AsRef<str>) -> Option<Option<DateTime<Utc>>> {
+        self.mutes.get(id.as_ref()).copied()
+    }
+
+    /// Whether the group is hidden at `now`.
+    pub fn is_muted(&self, id: impl AsRef<str>, now: DateTime<Utc>) -> bool {
+        self.muted(id)
+            .is_some_and(|until| until.is_none_or(|t| now < t))
+    }
+
+    pub fn mutes(&self) -> impl Iterator<Item = (&str, Option<DateTime<Utc>>)> {
+        self.mutes.iter().map(|(k, v)| (k.as_str(), *v))
+    }
 }
@@ -0,0 +1,185 @@
+use std::{cmp::min, collections::HashMap};
+
+use chrono::{DateTime, Utc};
+use hashlink::lru_cache::LruCache;
+use nadir_types::{message::GroupSnapshot, model};
+
+/// A hard maximum value for all messages to prevent memory overflow.
+const CAPACITY_HARD_MAX: usize = 400;
+
+/// A group of message to show. This type should be put inside a `Arc<RwLock<_>>`
+/// to share between threads.
+#[derive(Debug)]
+pub struct MessageGroup {
+    meta: model::MessageGroup,
+    counter: u64,
+
+    pub msgs: LruCache<String, model::Message>,
+    pub pinned_msgs: LruCache<String, model::Message>,
+
+    /// Messages the user hasn't acknowledged yet, in either slot
+    unread: HashMap<String, Unread>,
+}
+
+/// A message put or changed since the user last acknowledged it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unread {
+    /// When the message was put or last changed
+    pub since: DateTime<Utc>,
+    /// Whether it stayed unread for too long
+    pub overdue: bool,
+}
+
+impl MessageGroup {
+    /// Create a new message group with the given capacity and pinned capacity.
+    pub fn new(meta: model::MessageGroup) -> MessageGroup {
+        MessageGroup {
+            counter: 0,
+            msgs: LruCache::new(min(meta.capacity as usize, CAPACITY_HARD_MAX)),
+            pinned_msgs: LruCache::new(min(meta.pinned_capacity as usize, CAPACITY_HARD_MAX)),
+            unread: HashMap::new(),
+            meta,
+        }
+    }
+
+    pub fn meta(&self) -> &model::MessageGroup {
+        &self.meta
+    }
+
+    pub fn set_meta(&mut self, meta: model::MessageGroup) {
+        self.msgs
+            .set_capacity(min(meta.capacity as usize, CAPACITY_HARD_MAX));
+        self.pinned_msgs
+            .set_capacity(min(meta.pinned_capacity as usize, CAPACITY_HARD_MAX));
+        self.meta = meta;
+        self.forget_unread();
+    }
+
+    pub fn set_counter(&mut self, counter: u64) {
+        self.counter = counter;
+    }
+
+    pub fn inc_counter(&mut self, counter: i64) {
+        if counter >= 0 {
+            self.counter = self.counter.saturating_add(counter as u64);
+        } else {
+            self.counter = self.counter.saturating_sub((-counter) as u64);
+        }
+    }
+
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+
+    pub fn id(&self) -> &str {
+        &self.meta.id
+    }
+
+    pub fn cap(&self) -> usize {
+        self.msgs.capacity()
+    }
+
+    pub fn cap_pinned(&self) -> usize {
+        self.pinned_msgs.capacity()
+    }
+
+    pub fn add_message(&mut self, notification: model::Message) {
+        self.add_messages(std::iter::once(notification))
+    }
+
+    /// Add messages to the not-pinned slot. New and changed messages are
+    /// unread.
+    pub fn add_messages(&mut self, messages: impl Iterator<Item = model::Message>) {
+        self.add_to_slot(false, messages)
+    }
+
+    pub fn add_pinned_message(&mut self, notification: model::Message) {
+        self.add_pinned_messages(std::iter::once(notification))
+    }
+
+    pub fn add_pinned_messages(&mut self, messages: impl Iterator<Item = model::Message>) {
+        self.add_to_slot(true, messages)
+    }
+
+    fn add_to_slot(&mut self, pinned: bool, messages: impl Iterator<Item = model::Message>) {
+        let now = Utc::now();
+        let slot = if pinned {
+            &mut self.pinned_msgs
+        } else {
+            &mut self.msgs
+        };
+        for msg in messages {
+            if slot.peek(&msg.id) != Some(&msg) {
+                self.unread.insert(
+                    msg.id.clone(),
+                    Unread {
+                        since: now,
+                        overdue: false,
+                    },
+                );
+            }
+            slot.insert(msg.id.clone(), msg);
+        }
+        self.forget_unread();
+    }
+
+    pub fn remove_msg<'a>(&mut self, ids: impl Iterator<Item = &'a str>) {
+        for id in ids {
+            self.msgs.remove(id);
+        }
+        self.forget_unread();
+    }
+
+    pub fn remove_pinned_msg<'a>(&mut self, ids: impl Iterator<Item = &'a str>) {
+        for id in ids {
+            self.pinned_msgs.remove(id);
+        }
+        self.forget_unread();
+    }
+
+    /// Stop tracking messages removed or pushed out of the group.
+    fn forget_unread(&mut self) {
+        let (msgs, pinned_msgs) = (&self.msgs, &self.pinned_msgs);
+        self.unread
+            .retain(|id, _| msgs.peek(id).is_some() || pinned_msgs.peek(id).is_some());
+    }
+
+    /// Whether the message was put or changed since it was last acknowledged.
+    pub fn unread(&self, id: &str) -> Option<Unread> {
+        self.unread.get(id).copied()
+    }
+
+    pub fn unread_messages(&self) -> impl Iterator<Item = (&str, Unread)> {
+        self.unread.iter().map(|(id, u)| (id.as_str(), *u))
+    }
+
+    /// Mark a message as read. Returns false if it already was.
+    pub fn acknowledge(&mut self, id: &str) -> bool {
+        self.unread.remove(id).is_some()
+    }
+
+    /// Mark the messages unread since `deadline` or earlier as overdue.
+    /// Returns the IDs of those that weren't yet.
+    pub fn mark_overdue(&mut self, deadline: DateTime<Utc>) -> Vec<String> {
+        self.unread
+            .iter_mut()
+            .filter(|(_, u)| !u.overdue && u.since <= deadline)
+            .map(|(id, u)| {
+                u.overdue = true;
+                id.clone()
+            })
+            .collect()
+    }
+
+    /// Copy the state of this group, with messages newest first.
+    pub fn snapshot(&self) -> GroupSnapshot {
+        GroupSnapshot {
+            group: self.meta.clone(),
+            counter: self.counter,
+            pinned: self.pinned_msgs.iter().rev().map(|x| x.1.clone()).collect(),
+            messages: self.msgs.iter().rev().map(|x| x.1.clone()).collect(),
+            muted: false,
+            muted_until: None,
+        }
+    }
+}
@@ -0,0 +1,650 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use log::warn;
+use nadir_types::{
+    message::{
+        ApiMessage, GroupSnapshot, PutGroupMsg, PutMsg, RemoveGroupMsg, RemoveMsg, RespSnapshotMsg,
+        SetGroupCounterMsg, SetGroupMuteMsg, UserAction,
+    },
+    model::{self, Message},
+};
+use tokio::sync::broadcast;
+
+use crate::{
+    model::{group_list::GroupList, MessageGroup},
+    util::DirtyCheckLock,
+};
+
+/// How many changes can be queued before the slowest subscribers start
+/// missing them.
+const CHANGES_CAPACITY: usize = 256;
+
+/// A change made to the state by a message.
+///
+/// Changes convert back into the messages making them, so applying them in
+/// order to a copy of the state keeps it in sync.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// A group was added or its metadata replaced.
+    PutGroup {
+        group: model::MessageGroup,
+    },
+    RemoveGroup {
+        group: String,
+    },
+    /// Messages were added or replaced. Messages pushed out of a full group
+    /// are not reported.
+    Put {
+        group: String,
+        items: Vec<Message>,
+        pinned: bool,
+    },
+    /// Messages were removed. Only IDs that were present are listed.
+    Remove {
+        group: String,
+        ids: Vec<String>,
+        pinned: bool,
+    },
+    SetGroupCounter {
+        group: String,
+        counter: u64,
+    },
+    /// A group was muted, snoozed or unmuted, whether it exists or not.
+    SetGroupMute {
+        group: String,
+        muted: bool,
+        until: Option<DateTime<Utc>>,
+    },
+}
+
+impl Change {
+    /// The ID of the changed group.
+    pub fn group(&self) -> &str {
+        match self {
+            Change::PutGroup { group } => &group.id,
+            Change::RemoveGroup { group }
+            | Change::Put { group, .. }
+            | Change::Remove { group, .. }
+            | Change::SetGroupCounter { group, .. }
+            | Change::SetGroupMute { group, .. } => group,
+        }
+    }
+}
+
+impl From<Change> for ApiMessage {
+    fn from(change: Change) -> Self {
+        match change {
+            Change::PutGroup { group } => ApiMessage::PutGroup(PutGroupMsg { group }),
+            Change::RemoveGroup { group } => ApiMessage::RemoveGroup(RemoveGroupMsg { group }),
+            Change::Put {
+                group,
+                items,
+                pinned,
+            } => ApiMessage::Put(PutMsg {
+                group,
+                items,
+                pinned,
+            }),
+            Change::Remove { group, ids, pinned } => ApiMessage::Remove(RemoveMsg {
+                group,
+                items: ids,
+                pinned,
+            }),
+            Change::SetGroupCounter { group, counter } => {
+                ApiMessage::SetGroupCounter(SetGroupCounterMsg { group, counter })
+            }
+            Change::SetGroupMute {
+                group,
+                muted,
+                until,
+            } => ApiMessage::SetGroupMute(SetGroupMuteMsg {
+                group,
+                muted,
+                until,
+            }),
+        }
+    }
+}
+
+/// The state of a frontend: its groups and their messages.
+///
+/// Messages are applied through [`Store::apply`] or [`Store::apply_all`],
+/// which report what they changed to every subscriber. Views read the groups
+/// directly through [`Store::groups`].
+#[derive(Debug, Clone)]
+pub struct Store {
+    groups: Arc<DirtyCheckLock<GroupList>>,
+    changes: broadcast::Sender<Change>,
+}
+
+impl Default for Store {
+    fn default() -> Self {
+        Store::new()
+    }
+}
+
+impl Store {
+    pub fn new() -> Store {
+        Store {
+            groups: Arc::new(DirtyCheckLock::new(GroupList::new())),
+            changes: broadcast::channel(CHANGES_CAPACITY).0,
+        }
+    }
+
+    /// The groups, sorted by importance. The list is marked dirty when groups
+    /// are added or removed, and each group when its messages change.
+    pub fn groups(&self) -> &Arc<DirtyCheckLock<GroupList>> {
+        &self.groups
+    }
+
+    /// Receive changes made by messages applied from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Change> {
+        self.changes.subscribe()
+    }
+
+    /// Receive changes made from now on, along with the state they apply to:
+    /// no change is both in the snapshot and received.
+    pub fn subscribe_with_snapshot(
+        &self,
+        group: Option<&str>,
+    ) -> (Vec<GroupSnapshot>, broadcast::Receiver<Change>) {
+        // Changes are sent while the groups are locked for writing
+        let groups = self.groups.read(false);
+        (snapshot(&groups, group), self.changes.subscribe())
+    }
+
+    /// Copy the state of a group, or of every group in the order they are
+    /// shown.
+    pub fn snapshot(&self, group: Option<&str>) -> Vec<GroupSnapshot> {
+        snapshot(&self.groups.read(false), group)
+    }
+
+    /// Apply a message. Returns a response if the message requires one.
+    pub fn apply(&self, msg: ApiMessage) -> Option<ApiMessage> {
+        self.apply_all(std::iter::once(msg)).pop()
+    }
+
+    /// Apply messages in order, locking the groups only once. Returns the
+    /// responses to messages requiring one.
+    pub fn apply_all(&self, msgs: impl IntoIterator<Item = ApiMessage>) -> Vec<ApiMessage> {
+        let mut groups = self.groups.write();
+        let mut responses = vec![];
+        for msg in msgs {
+            match apply(&mut groups, msg) {
+                Applied::Changed(change) => {
+                    let _ = self.changes.send(change);
+                }
+                Applied::Response(resp) => responses.push(resp),
+                Applied::Unchanged => {}
+            }
+        }
+        responses
+    }
+
+    /// Unmute the groups snoozed until `now` or earlier. Returns the messages
+    /// unmuting them.
+    pub fn unmute_expired(&self, now: DateTime<Utc>) -> Vec<ApiMessage> {
+        // Checked often, so only lock for writing when needed
+        let msgs: Vec<_> = self
+            .groups
+            .read(false)
+            .mutes()
+            .filter(|(_, until)| until.is_some_and(|t| t <= now))
+            .map(|(group, _)| {
+                ApiMessage::SetGroupMute(SetGroupMuteMsg {
+                    group: group.to_owned(),
+                    muted: false,
+                    until: None,
+                })
+            })
+            .collect();
+        if !msgs.is_empty() {
+            self.apply_all(msgs.clone());
+        }
+        msgs
+    }
+}
+
+/// Copy the state of a group, or of every group in order.
+fn snapshot(data: &GroupList, group: Option<&str>) -> Vec<GroupSnapshot> {
+    let groups = match group {
+        Some(id) => data.get_group(id).into_iter().collect(),
+        None => data.iter().map(|x| x.1).collect::<Vec<_>>(),
+    };
+    groups
+        .iter()
+        .map(|g| {
+            let mut snapshot = g.read(false).snapshot();
+            if let Some(until) = data.muted(&snapshot.group.id) {
+                snapshot.muted = true;
+                snapshot.muted_until = until;
+            }
+            snapshot
+        })
+        .collect()
+}
+
+enum Applied {
+    Changed(Change),
+    Response(ApiMessage),
+    Unchanged,
+}
+
+fn apply(data: &mut GroupList, msg: ApiMessage) -> Applied {
+    match msg {
+        ApiMessage::Put(msg) => {
+            let mut g = match data.get_group(&msg.group) {
+                Some(g) => g.write(),
+                None => return Applied::Unchanged,
+            };
+            if msg.pinned {
+                g.add_pinned_messages(msg.items.iter().cloned());
+            } else {
+                g.add_messages(msg.items.iter().cloned());
+            }
+            Applied::Changed(Change::Put {
+                group: msg.group,
+                items: msg.items,
+                pinned: msg.pinned,
+            })
+        }
+        ApiMessage::Remove(msg) => {
+            let mut g = match data.get_group(&msg.group) {
+                Some(g) => g.write(),
+                None => return Applied::Unchanged,
+            };
+            let slot = if msg.pinned { &g.pinned_msgs } else { &g.msgs };
+            let ids: Vec<_> = msg
+                .items
+                .into_iter()
+                .filter(|id| slot.peek(id).is_some())
+                .collect();
+            if ids.is_empty() {
+                return Applied::Unchanged;
+            }
+            let removed = ids.iter().map(|x| x.as_str());
+            if msg.pinned {
+                g.remove_pinned_msg(removed);
+            } else {
+                g.remove_msg(removed);
+            }
+            Applied::Changed(Change::Remove {
+                group: msg.group,
+                ids,
+                pinned: msg.pinned,
+            })
+        }
+        ApiMessage::PutGroup(msg) => {
+            match data.get_group(&msg.group.id).cloned() {
+                Some(g) => {
+                    // Re-adding refreshes the importance used for sorting
+                    g.write().set_meta(msg.group.clone());
+                    data.add_group(g);
+                }
+                None => data.add_group(Arc::new(DirtyCheckLock::new(MessageGroup::new(
+                    msg.group.clone(),
+                )))),
+            }
+            Applied::Changed(Change::PutGroup { group: msg.group })
+        }
+        ApiMessage::RemoveGroup(msg) => match data.remove_group(&msg.group) {
+            Some(_) => Applied::Changed(Change::RemoveGroup { group: msg.group }),
+            None => Applied::Unchanged,
+        },
+        ApiMessage::SetGroupCounter(msg) => match data.get_group(&msg.group) {
+            Some(g) => {
+                g.write().set_counter(msg.counter);
+                Applied::Changed(Change::SetGroupCounter {
+                    group: msg.group,
+                    counter: msg.counter,
+                })
+            }
+            None => Applied::Unchanged,
+        },
+        ApiMessage::SetGroupMute(msg) => {
+            let changed = if msg.muted {
+                data.mute(msg.group.clone(), msg.until)
+            } else {
+                data.unmute(&msg.group)
+            };
+            if !changed {
+                return Applied::Unchanged;
+            }
+            Applied::Changed(Change::SetGroupMute {
+                group: msg.group,
+                muted: msg.muted,
+                until: if msg.muted { msg.until } else { None },
+            })
+        }
+        ApiMessage::ReqSnapshot(msg) => {
+            Applied::Response(ApiMessage::RespSnapshot(RespSnapshotMsg {
+                groups: snapshot(data, msg.group.as_deref()),
+                reply_to: msg.msg_id,
+            }))
+        }
+        ApiMessage::Config => {
+            warn!("Config message is not yet supported");
+            Applied::Unchanged
+        }
+        ApiMessage::Subscribe(_) => {
+            warn!("Subscriptions are only supported on WebSocket connections");
+            Applied::Unchanged
+        }
+        ApiMessage::SetDnd(_) => {
+            warn!("Do not disturb is handled before messages are applied");
+            Applied::Unchanged
+        }
+        ApiMessage::UserAction(msg) if msg.action == UserAction::Acknowledge => {
+            // Read state isn't part of snapshots, so it's no change
+            if let Some(g) = data.get_group(&msg.group) {
+                if g.read(false).unread(&msg.message).is_some() {
+                    g.write().acknowledge(&msg.message);
+                }
+            }
+            Applied::Unchanged
+        }
+        ApiMessage::UserAction(_) | ApiMessage::RespSnapshot(_) => {
+            warn!("User actions and responses are only sent by the frontend");
+            Applied::Unchanged
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nadir_types::message::{ReqSnapshotMsg, UserActionMsg};
+
+    use super::*;
+
+    fn put_group(id: &str, importance: i32) -> ApiMessage {
+        ApiMessage::PutGroup(PutGroupMsg {
+            group: model::MessageGroup {
+                id: id.into(),
+                title: id.into(),
+                importance,
+                ..Default::default()
+            },
+        })
+    }
+
+    fn messages(ids: &[&str]) -> Vec<Message> {
+        ids.iter()
+            .map(|&id| Message {
+                id: id.into(),
+                body: id.into(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn put(group: &str, ids: &[&str], pinned: bool) -> ApiMessage {
+        ApiMessage::Put(PutMsg {
+            group: group.into(),
+            items: messages(ids),
+            pinned,
+        })
+    }
+
+    fn remove(group: &str, ids: &[&str]) -> ApiMessage {
+        ApiMessage::Remove(RemoveMsg {
+            group: group.into(),
+            items: ids.iter().map(|&id| id.into()).collect(),
+            pinned: false,
+        })
+    }
+
+    fn mute(group: &str, muted: bool, until: Option<DateTime<Utc>>) -> ApiMessage {
+        ApiMessage::SetGroupMute(SetGroupMuteMsg {
+            group: group.into(),
+            muted,
+            until,
+        })
+    }
+
+    fn group_ids(store: &Store) -> Vec<String> {
+        let groups = store.groups().read(false);
+        groups.iter().map(|x| x.0.to_owned()).collect()
+    }
+
+    #[test]
+    fn changes() {
+        let store = Store::new();
+        let mut changes = store.subscribe();
+        let responses = store.apply_all(vec![
+            put("missing", &["a"], false),
+            put_group("g", 0),
+            put("g", &["a", "b"], false),
+            put("g", &["p"], true),
+            remove("g", &["a", "missing"]),
+            remove("g", &["missing"]),
+            ApiMessage::SetGroupCounter(SetGroupCounterMsg {
+                group: "g".into(),
+                counter: 3,
+            }),
+            ApiMessage::RemoveGroup(RemoveGroupMsg {
+                group: "missing".into(),
+            }),
+        ]);
+        assert!(responses.is_empty());
+
+        let received: Vec<_> = std::iter::from_fn(|| changes.try_recv().ok()).collect();
+        assert_eq!(
+            received,
+            vec![
+                Change::PutGroup {
+                    group: model::MessageGroup {
+                        id: "g".into(),
+                        title: "g".into(),
+                        ..Default::default()
+                    }
+                },
+                Change::Put {
+                    group: "g".into(),
+                    items: messages(&["a", "b"]),
+                    pinned: false
+                },
+                Change::Put {
+                    group: "g".into(),
+                    items: messages(&["p"]),
+                    pinned: true
+                },
+                Change::Remove {
+                    group: "g".into(),
+                    ids: vec!["a".into()],
+                    pinned: false
+                },
+                Change::SetGroupCounter {
+                    group: "g".into(),
+                    counter: 3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn groups_sorted_by_importance() {
+        let store = Store::new();
+        store.apply_all(vec![
+            put_group("a", 0),
+            put_group("b", 1),
+            put_group("c", 0),
+        ]);
+        assert_eq!(group_ids(&store), vec!["b", "a", "c"]);
+
+        // Metadata updates reorder the groups but keep their messages
+        store.apply_all(vec![put("c", &["m"], false), put_group("c", 2)]);
+        assert_eq!(group_ids(&store), vec!["c", "b", "a"]);
+        assert_eq!(
+            store
+                .groups()
+                .read(false)
+                .get_group("c")
+                .unwrap()
+                .read(false)
+                .msgs
+                .len(),
+            1
+        );
+
+        store.apply(ApiMessage::RemoveGroup(RemoveGroupMsg {
+            group: "b".into(),
+        }));
+        assert_eq!(group_ids(&store), vec!["c", "a"]);
+    }
+
+    #[test]
+    fn snapshot() {
+        let store = Store::new();
+        store.apply_all(vec![
+            put_group("g", 0),
+            put("g", &["old", "new"], false),
+            put("g", &["p"], true),
+        ]);
+        let snapshot =
+            |group: Option<&str>| match store.apply(ApiMessage::ReqSnapshot(ReqSnapshotMsg {
+                msg_id: "id".into(),
+                group: group.map(Into::into),
+            })) {
+                Some(ApiMessage::RespSnapshot(resp)) => resp,
+                other => panic!("expected a snapshot, got {:?}", other),
+            };
+
+        let resp = snapshot(None);
+        assert_eq!(resp.reply_to, "id");
+        let group = &resp.groups[0];
+        let ids: Vec<_> = group.messages.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["new", "old"]);
+        assert_eq!(group.pinned[0].id, "p");
+        assert!(snapshot(Some("missing")).groups.is_empty());
+    }
+
+    #[test]
+    fn mirror() {
+        let store = Store::new();
+        store.apply_all(vec![
+            put_group("g", 0),
+            put("g", &["a", "b"], false),
+            put("g", &["p"], true),
+        ]);
+        let (snapshot, mut changes) = store.subscribe_with_snapshot(None);
+        let mirror = Store::new();
+        mirror.apply_all(snapshot.into_iter().flat_map(GroupSnapshot::into_messages));
+        assert_eq!(mirror.snapshot(None), store.snapshot(None));
+
+        store.apply_all(vec![
+            put("g", &["c"], false),
+            remove("g", &["a"]),
+            put_group("h", 1),
+            put("h", &["x"], false),
+            ApiMessage::SetGroupCounter(SetGroupCounterMsg {
+                group: "g".into(),
+                counter: 2,
+            }),
+            put_group("gone", 0),
+            ApiMessage::RemoveGroup(RemoveGroupMsg {
+                group: "gone".into(),
+            }),
+            mute("g", true, None),
+            mute("h", true, Some(Utc::now())),
+        ]);
+        mirror.apply_all(std::iter::from_fn(|| changes.try_recv().ok()).map(Into::into));
+        assert_eq!(mirror.snapshot(None), store.snapshot(None));
+    }
+
+    #[test]
+    fn mutes() {
+        let store = Store::new();
+        let now = Utc::now();
+        let later = now + chrono::Duration::hours(1);
+        store.apply_all(vec![
+            mute("g", true, None),
+            put_group("g", 0),
+            mute("h", true, Some(later)),
+            put_group("h", 0),
+        ]);
+        let mut changes = store.subscribe();
+        store.apply_all(vec![
+            mute("g", true, None),
+            mute("missing", false, None),
+            ApiMessage::RemoveGroup(RemoveGroupMsg { group: "g".into() }),
+            put_group("g", 0),
+        ]);
+        // Only the group changes are reported
+        assert!(matches!(changes.try_recv(), Ok(Change::RemoveGroup { .. })));
+        assert!(matches!(changes.try_recv(), Ok(Change::PutGroup { .. })));
+        assert!(changes.try_recv().is_err());
+
+        let snapshot = store.snapshot(None);
+        assert!(snapshot.iter().all(|g| g.muted));
+        assert_eq!(snapshot[1].muted_until, Some(later));
+
+        assert!(store.unmute_expired(now).is_empty());
+        let groups = store.groups().read(false);
+        assert!(groups.is_muted("g", later) && groups.is_muted("h", now));
+        assert!(!groups.is_muted("h", later));
+        drop(groups);
+        match &store.unmute_expired(later)[..] {
+            [ApiMessage::SetGroupMute(m)] => assert!(m.group == "h" && !m.muted),
+            other => panic!("expected h to be unmuted, got {:?}", other),
+        }
+        assert!(matches!(
+            changes.try_recv(),
+            Ok(Change::SetGroupMute { muted: false, .. })
+        ));
+        assert_eq!(store.groups().read(false).muted("h"), None);
+    }
+
+    #[test]
+    fn acknowledge() {
+        let store = Store::new();
+        let ack = |id: &str| {
+            ApiMessage::UserAction(UserActionMsg {
+                group: "g".into(),
+                message: id.into(),
+                action: UserAction::Acknowledge,
+            })
+        };
+        let unread = |store: &Store| {
+            let groups = store.groups().read(false);
+            let g = groups.get_group("g").unwrap().read(false);
+            let mut ids: Vec<_> = g.unread_messages().map(|(id, _)| id.to_owned()).collect();
+            ids.sort();
+            ids
+        };
+        store.apply_all(vec![
+            put_group("g", 0),
+            put("g", &["a", "b"], false),
+            put("g", &["p"], true),
+        ]);
+        assert_eq!(unread(&store), vec!["a", "b", "p"]);
+
+        store.apply_all(vec![ack("a"), ack("p"), ack("missing")]);
+        assert_eq!(unread(&store), vec!["b"]);
+
+        // Putting the same message again doesn't make it unread, changing it
+        // does
+        let mut changed = messages(&["b"]);
+        changed[0].body = "changed".into();
+        store.apply_all(vec![
+            put("g", &["a"], false),
+            ApiMessage::Put(PutMsg {
+                group: "g".into(),
+                items: changed,
+                pinned: true,
+            }),
+            remove("g", &["b"]),
+        ]);
+        assert_eq!(unread(&store), vec!["b"]);
+
+        let groups = store.groups().read(false);
+        let mut g = groups.get_group("g").unwrap().write();
+        let since = g.unread("b").unwrap().since;
+        assert!(g
+            .mark_overdue(since - chrono::Duration::seconds(1))
+            .is_empty());
+        assert_eq!(g.mark_overdue(since), vec!["b"]);
+        assert!(g.mark_overdue(since).is_empty());
+        assert!(g.unread("b").unwrap().overdue);
+    }
+}
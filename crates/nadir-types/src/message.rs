@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 use crate::model::{Message, MessageGroup};
@@ -11,6 +12,12 @@ pub enum ApiMessage {
     RemoveGroup(RemoveGroupMsg),
     SetGroupCounter(SetGroupCounterMsg),
     Config,
+    UserAction(UserActionMsg),
+    ReqSnapshot(ReqSnapshotMsg),
+    RespSnapshot(RespSnapshotMsg),
+    Subscribe(SubscribeMsg),
+    SetDnd(SetDndMsg),
+    SetGroupMute(SetGroupMuteMsg),
 }
 
 /// Add notifications in Nadir
@@ -25,6 +32,10 @@ pub struct PutMsg {
     /// Messages are added in reverse order, _i.e._ messages that appear later
     /// in this list will be added to the front.
     pub items: Vec<Message>,
+
+    /// Add the messages to the pinned slot instead.
+    #[serde(default)]
+    pub pinned: bool,
 }
 
 /// Remove notifications
@@ -36,6 +47,10 @@ pub struct RemoveMsg {
 
     /// The IDs of messages to remove
     pub items: Vec<String>,
+
+    /// Remove the messages from the pinned slot instead.
+    #[serde(default)]
+    pub pinned: bool,
 }
 
 /// Add or replace a namespace in Nadir
@@ -61,3 +76,129 @@ pub struct SetGroupCounterMsg {
     /// The counter ID
     pub counter: u64,
 }
+
+/// Sent by the frontend when the user performs an action on a message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct UserActionMsg {
+    /// The group ID
+    pub group: String,
+    /// The message ID
+    pub message: String,
+    /// What the user did
+    pub action: UserAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserAction {
+    /// The user pressed Space or Enter on the message.
+    Click,
+    /// The user marked the message as read.
+    Acknowledge,
+}
+
+/// Asks the frontend for the groups and messages it currently shows.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ReqSnapshotMsg {
+    /// A unique ID the response will refer to
+    pub msg_id: String,
+    /// The group ID. All groups are included if omitted.
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+/// Sent by the frontend in response to a [`ReqSnapshotMsg`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct RespSnapshotMsg {
+    /// The `msg_id` of the request
+    pub reply_to: String,
+    /// The requested groups, in the order they are shown
+    pub groups: Vec<GroupSnapshot>,
+}
+
+/// The state of a group in the frontend.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct GroupSnapshot {
+    pub group: MessageGroup,
+    pub counter: u64,
+    /// Messages in the pinned slot, newest first
+    pub pinned: Vec<Message>,
+    /// Messages in the not-pinned slot, newest first
+    pub messages: Vec<Message>,
+    /// Whether the user muted the group
+    #[serde(default)]
+    pub muted: bool,
+    /// When the group is shown again, if it is snoozed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub muted_until: Option<DateTime<Utc>>,
+}
+
+impl GroupSnapshot {
+    /// The messages recreating this group in another frontend.
+    pub fn into_messages(self) -> Vec<ApiMessage> {
+        let group = self.group.id.clone();
+        let mute = SetGroupMuteMsg {
+            group: group.clone(),
+            muted: self.muted,
+            until: self.muted_until,
+        };
+        let put = |mut items: Vec<Message>, pinned| {
+            // Later items are added to the front
+            items.reverse();
+            ApiMessage::Put(PutMsg {
+                group: group.clone(),
+                items,
+                pinned,
+            })
+        };
+        vec![
+            ApiMessage::PutGroup(PutGroupMsg { group: self.group }),
+            put(self.pinned, true),
+            put(self.messages, false),
+            ApiMessage::SetGroupCounter(SetGroupCounterMsg {
+                group: group.clone(),
+                counter: self.counter,
+            }),
+            ApiMessage::SetGroupMute(mute),
+        ]
+    }
+}
+
+/// Asks the frontend for a snapshot like [`ReqSnapshotMsg`], followed by every
+/// change made to the included groups from then on.
+///
+/// Only clients connected through WebSocket can subscribe.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SubscribeMsg {
+    /// A unique ID the snapshots will refer to
+    pub msg_id: String,
+    /// The group ID. All groups are included if omitted.
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+/// Turns do not disturb on or off in the frontend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SetDndMsg {
+    pub enabled: bool,
+}
+
+/// Sent by the frontend when the user mutes, snoozes or unmutes a group, and
+/// when a snooze ends.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SetGroupMuteMsg {
+    /// The group ID
+    pub group: String,
+    pub muted: bool,
+    /// When a snoozed group is shown again. A muted group without it stays
+    /// muted until the user unmutes it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<DateTime<Utc>>,
+}
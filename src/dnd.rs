@@ -0,0 +1,203 @@
+//! Do not disturb: hides the groups that aren't urgent, and sums up what they
+//! received once it ends.
+use std::{sync::Arc, time::Duration};
+
+use chrono::Local;
+use cursive::{
+    event::Event,
+    views::{Dialog, HideableView, LinearLayout},
+};
+use log::{info, warn};
+use nadir_core::{Change, Store};
+use parking_lot::Mutex;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    opt::{DndConfig, QuietHours},
+    CursiveHandle,
+};
+
+/// How often quiet hours are checked.
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(30);
+
+pub struct Dnd {
+    urgent_importance: Option<i32>,
+    handle: Option<CursiveHandle>,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    on: bool,
+    /// Messages put in hidden groups since it was turned on, by group
+    missed: Vec<Missed>,
+}
+
+#[derive(Debug)]
+struct Missed {
+    group: String,
+    title: String,
+    count: usize,
+}
+
+impl Dnd {
+    pub fn new(config: &DndConfig, handle: Option<CursiveHandle>) -> Dnd {
+        Dnd {
+            urgent_importance: config.urgent_importance,
+            handle,
+            state: Default::default(),
+        }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.state.lock().on
+    }
+
+    /// Whether groups of this importance are hidden now.
+    pub fn hides(&self, importance: i32) -> bool {
+        self.is_on() && self.urgent_importance.is_none_or(|u| importance < u)
+    }
+
+    pub fn toggle(&self) {
+        self.set(!self.is_on())
+    }
+
+    /// Turn it on or off. Turning it off shows the summary.
+    pub fn set(&self, on: bool) {
+        let mut state = self.state.lock();
+        if state.on == on {
+            return;
+        }
+        state.on = on;
+        let missed = summary(&std::mem::take(&mut state.missed));
+        drop(state);
+
+        info!("do not disturb is {}", if on { "on" } else { "off" });
+        if !missed.is_empty() {
+            info!("while it was on: {}", missed.join(", "));
+        }
+        if let Some(handle) = &self.handle {
+            let _ = handle.send(Box::new(move |c| {
+                c.call_on_name("dnd", |v: &mut HideableView<LinearLayout>| {
+                    v.set_visible(on)
+                });
+                if !missed.is_empty() {
+                    c.add_layer(
+                        Dialog::text(format!("While it was on:\n  {}", missed.join("\n  ")))
+                            .title("Do not disturb is off")
+                            .dismiss_button("OK"),
+                    );
+                }
+                c.on_event(Event::Refresh);
+            }));
+        }
+    }
+
+    fn missed(&self, group: &str, title: &str, count: usize) {
+        let mut state = self.state.lock();
+        match state.missed.iter_mut().find(|m| m.group == group) {
+            Some(m) => {
+                m.title = title.to_owned();
+                m.count += count;
+            }
+            None => state.missed.push(Missed {
+                group: group.to_owned(),
+                title: title.to_owned(),
+                count,
+            }),
+        }
+    }
+}
+
+fn summary(missed: &[Missed]) -> Vec<String> {
+    missed
+        .iter()
+        .map(|m| {
+            let s = if m.count == 1 { "" } else { "s" };
+            format!("{} message{} in {}", m.count, s, m.title)
+        })
+        .collect()
+}
+
+/// Count the messages put in hidden groups for the summary.
+pub async fn watch(dnd: Arc<Dnd>, store: Store) {
+    let mut changes = store.subscribe();
+    loop {
+        match changes.recv().await {
+            Ok(Change::Put { group, items, .. }) if dnd.is_on() => {
+                let meta = match store.groups().read(false).get_group(&group) {
+                    Some(g) => g.read(false).meta().clone(),
+                    None => continue,
+                };
+                if dnd.hides(meta.importance) {
+                    dnd.missed(&group, &meta.title, items.len());
+                }
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(n)) => {
+                warn!("do not disturb missed {} changes for its summary", n)
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Turn it on when quiet hours start and off when they end. Toggling it in
+/// between lasts until the next start or end.
+pub async fn schedule(dnd: Arc<Dnd>, quiet_hours: Vec<QuietHours>) {
+    let mut timer = tokio::time::interval(SCHEDULE_INTERVAL);
+    let mut quiet = false;
+    loop {
+        timer.tick().await;
+        let now = Local::now().time();
+        let new_quiet = quiet_hours.iter().any(|h| h.contains(now));
+        if new_quiet != quiet {
+            quiet = new_quiet;
+            dnd.set(quiet);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveTime;
+
+    use super::*;
+
+    #[test]
+    fn hides_and_sums_up() {
+        let dnd = Dnd::new(
+            &DndConfig {
+                urgent_importance: Some(5),
+                ..Default::default()
+            },
+            None,
+        );
+        assert!(!dnd.hides(0));
+        dnd.toggle();
+        assert!(dnd.hides(0) && !dnd.hides(5));
+
+        dnd.missed("mail", "Mail", 2);
+        dnd.missed("ci", "CI", 1);
+        dnd.missed("mail", "Inbox", 1);
+        let summary = summary(&dnd.state.lock().missed);
+        assert_eq!(summary, vec!["3 messages in Inbox", "1 message in CI"]);
+        dnd.set(false);
+        assert!(dnd.state.lock().missed.is_empty());
+        assert!(!dnd.hides(0));
+    }
+
+    #[test]
+    fn quiet_hours() {
+        let config: DndConfig = toml::from_str(
+            r#"quiet_hours = [{ start = "22:00", end = "07:30" }, { start = "12:00", end = "13:00" }]"#,
+        )
+        .unwrap();
+        let quiet = |time: &str| {
+            let time = NaiveTime::parse_from_str(time, "%H:%M").unwrap();
+            config.quiet_hours.iter().any(|h| h.contains(time))
+        };
+        assert!(quiet("23:00") && quiet("00:00") && quiet("07:29") && quiet("12:30"));
+        assert!(!quiet("07:30") && !quiet("13:00") && !quiet("21:59"));
+    }
+}
@@ -0,0 +1,69 @@
+//! Rings the terminal bell for important messages left unread for too long.
+use std::{io::Write, sync::Arc, time::Duration};
+
+use chrono::Utc;
+use cursive::event::Event;
+use log::warn;
+use nadir_core::Store;
+
+use crate::{dnd::Dnd, opt::EscalationConfig, CursiveHandle};
+
+/// How often unread messages are checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Mark the messages unread for too long as overdue, and ring the bell when
+/// some are.
+pub async fn escalate(
+    config: EscalationConfig,
+    store: Store,
+    dnd: Arc<Dnd>,
+    handle: Option<CursiveHandle>,
+) {
+    let min_importance = match config.min_importance {
+        Some(i) => i,
+        None => return,
+    };
+    let after = chrono::Duration::seconds(config.after as i64);
+    let mut timer = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        timer.tick().await;
+        let now = Utc::now();
+        let deadline = now - after;
+        let mut escalated = false;
+
+        let groups = store.groups().read(false);
+        for (id, group) in groups.iter() {
+            let read = group.read(false);
+            let importance = read.meta().importance;
+            if importance < min_importance || dnd.hides(importance) || groups.is_muted(id, now) {
+                continue;
+            }
+            // Only lock for writing when needed, as writing redraws the group
+            let pending = read
+                .unread_messages()
+                .any(|(_, u)| !u.overdue && u.since <= deadline);
+            drop(read);
+            if !pending {
+                continue;
+            }
+            let mut group = group.write();
+            let overdue = group.mark_overdue(deadline);
+            warn!(
+                "unread in {} for more than {}s: {}",
+                group.meta().title,
+                config.after,
+                overdue.join(", ")
+            );
+            escalated = true;
+        }
+        drop(groups);
+
+        if let (true, Some(handle)) = (escalated, &handle) {
+            let _ = handle.send(Box::new(|c| {
+                let mut stdout = std::io::stdout();
+                let _ = stdout.write_all(b"\x07").and_then(|_| stdout.flush());
+                c.on_event(Event::Refresh);
+            }));
+        }
+    }
+}
@@ -1,31 +1,126 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{collections::HashMap, net::SocketAddr, path::Path, sync::Arc};
 
-use futures::StreamExt;
-use log::{info, warn};
-use nadir_types::message::ApiMessage;
+use futures::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use nadir_core::{Change, Store};
+use nadir_types::message::{ApiMessage, RespSnapshotMsg, SubscribeMsg, UserAction};
+use parking_lot::Mutex;
 use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
     net::{TcpSocket, TcpStream},
     select,
-    sync::mpsc::UnboundedReceiver,
+    sync::{
+        broadcast,
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    },
+};
+use tokio_tungstenite::{
+    tungstenite::{
+        handshake::server::{Callback, ErrorResponse, Request, Response},
+        http::{header::AUTHORIZATION, StatusCode},
+        Message as WsMessage,
+    },
+    MaybeTlsStream,
 };
-use tokio_tungstenite::MaybeTlsStream;
 use url::Url;
 
-use crate::{model::group_list::GroupList, util::DirtyCheckLock, CursiveHandle};
+use crate::{
+    dnd::Dnd,
+    record::{ChangeLog, Recorder},
+    rules::Rules,
+    scripts::Scripts,
+    CursiveHandle,
+};
+
+/// Messages the frontend sends to every connected client, like user actions.
+pub type Outgoing = broadcast::Sender<ApiMessage>;
+
+/// A message received by the frontend.
+#[derive(Debug, Clone)]
+pub struct Incoming {
+    /// Where the message came from, like a peer address or a backend name.
+    pub source: Arc<str>,
+    pub msg: ApiMessage,
+}
+
+pub type IncomingSender = UnboundedSender<Incoming>;
+
+/// Routes replies, like snapshots, to the source of the request. Only sources
+/// that can be written to, like WebSocket connections and supervised
+/// backends, can be replied to.
+#[derive(Debug, Clone, Default)]
+pub struct Replies(Arc<Mutex<HashMap<Arc<str>, UnboundedSender<ApiMessage>>>>);
+
+impl Replies {
+    /// Receive replies to messages from `source` until the receiver is
+    /// dropped. A later registration of the same source takes over.
+    pub fn register(&self, source: Arc<str>) -> ReplyReceiver {
+        let (tx, rx) = unbounded_channel();
+        self.0.lock().insert(source.clone(), tx.clone());
+        ReplyReceiver {
+            replies: self.clone(),
+            source,
+            tx,
+            rx,
+        }
+    }
+
+    /// Send a reply to the source of a message. It's dropped if the source
+    /// can't be replied to.
+    pub fn send(&self, source: &str, msg: ApiMessage) {
+        match self.0.lock().get(source) {
+            Some(tx) => {
+                let _ = tx.send(msg);
+            }
+            None => debug!("dropped reply to {}", source),
+        }
+    }
+}
+
+pub struct ReplyReceiver {
+    replies: Replies,
+    source: Arc<str>,
+    tx: UnboundedSender<ApiMessage>,
+    rx: UnboundedReceiver<ApiMessage>,
+}
+
+impl ReplyReceiver {
+    pub async fn recv(&mut self) -> Option<ApiMessage> {
+        self.rx.recv().await
+    }
+}
+
+impl Drop for ReplyReceiver {
+    fn drop(&mut self) {
+        let mut senders = self.replies.0.lock();
+        if senders
+            .get(&self.source)
+            .is_some_and(|tx| tx.same_channel(&self.tx))
+        {
+            senders.remove(&self.source);
+        }
+    }
+}
 
 pub async fn start_server(
-    handle: CursiveHandle,
-    data: Arc<DirtyCheckLock<GroupList>>,
     addr: SocketAddr,
+    secret: Option<Arc<str>>,
+    ch_send: IncomingSender,
+    outgoing: Outgoing,
+    replies: Replies,
+    store: Store,
 ) {
-    let port = TcpSocket::new_v6()
-        .or_else(|_| TcpSocket::new_v4())
-        .expect("Failed to listten on socket");
+    let port = if addr.is_ipv6() {
+        TcpSocket::new_v6()
+    } else {
+        TcpSocket::new_v4()
+    }
+    .expect("Failed to listten on socket");
 
+    // Restart without waiting for the connections of the last run to time out
+    port.set_reuseaddr(true).expect("Failed to listen");
     port.bind(addr).expect("Failed to listen");
 
-    let (ch_send, ch_recv) = tokio::sync::mpsc::unbounded_channel();
-    tokio::spawn(batch_process_messages(ch_recv, handle, data));
     let listener = port.listen(1024).expect("failed to listen");
 
     info!("listening on {}", addr);
@@ -38,29 +133,42 @@ pub async fn start_server(
                 continue;
             }
         };
-        tokio::spawn(accept_connection(link, socket, ch_send.clone()));
+        tokio::spawn(accept_connection(
+            link,
+            socket,
+            secret.clone(),
+            ch_send.clone(),
+            outgoing.subscribe(),
+            replies.clone(),
+            store.clone(),
+        ));
     }
 }
 
 pub async fn connect_to_backends(
     backend: Url,
-    stream: tokio::sync::mpsc::UnboundedSender<ApiMessage>,
+    stream: IncomingSender,
+    outgoing: broadcast::Receiver<ApiMessage>,
+    replies: Replies,
+    store: Store,
 ) {
-    let request = match hyper::Request::builder().uri(backend.as_str()).body(()) {
-        Ok(req) => req,
-        Err(e) => {
-            log::error!("{}", e);
-            return;
-        }
-    };
-    let (conn, _) = match tokio_tungstenite::connect_async(request).await {
+    let conn = match connect(&backend, None).await {
         Ok(conn) => conn,
         Err(e) => {
             log::error!("{}", e);
             return;
         }
     };
-    match connection_loop(conn, stream).await {
+    match connection_loop(
+        conn,
+        backend.as_str().into(),
+        stream,
+        outgoing,
+        replies,
+        store,
+    )
+    .await
+    {
         Ok(_) => {}
         Err(e) => {
             log::error!("{}", e)
@@ -68,22 +176,158 @@ pub async fn connect_to_backends(
     };
 }
 
+pub type Connection = tokio_tungstenite::WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Connect to a WebSocket server, supplying `secret` like clients connecting
+/// to us do.
+pub async fn connect(
+    url: &Url,
+    secret: Option<&str>,
+) -> Result<Connection, tokio_tungstenite::tungstenite::Error> {
+    let mut request = hyper::Request::builder().uri(url.as_str());
+    if let Some(secret) = secret {
+        request = request.header(AUTHORIZATION, format!("Bearer {}", secret));
+    }
+    let request = request.body(())?;
+    let (conn, _) = tokio_tungstenite::connect_async(request).await?;
+    Ok(conn)
+}
+
 async fn accept_connection(
     link: TcpStream,
     _socket: SocketAddr,
-    stream: tokio::sync::mpsc::UnboundedSender<ApiMessage>,
+    secret: Option<Arc<str>>,
+    stream: IncomingSender,
+    outgoing: broadcast::Receiver<ApiMessage>,
+    replies: Replies,
+    store: Store,
 ) -> Result<(), tokio_tungstenite::tungstenite::Error> {
     info!("accepted connection to {}", _socket);
     let link = MaybeTlsStream::Plain(link);
-    let conn = tokio_tungstenite::accept_async(link).await?;
-    connection_loop(conn, stream).await
+    let conn = tokio_tungstenite::accept_hdr_async(link, SecretCheck(secret)).await?;
+    connection_loop(
+        conn,
+        _socket.to_string().into(),
+        stream,
+        outgoing,
+        replies,
+        store,
+    )
+    .await
+}
+
+/// Checks the pre-shared secret supplied as `Authorization: Bearer <secret>`
+/// in the handshake request.
+struct SecretCheck(Option<Arc<str>>);
+
+impl Callback for SecretCheck {
+    fn on_request(self, req: &Request, resp: Response) -> Result<Response, ErrorResponse> {
+        let secret = match self.0 {
+            Some(s) => s,
+            None => return Ok(resp),
+        };
+        let supplied = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        if supplied == Some(&*secret) {
+            Ok(resp)
+        } else {
+            warn!("rejected connection with bad secret");
+            let mut resp = ErrorResponse::new(Some("Invalid secret".into()));
+            *resp.status_mut() = StatusCode::UNAUTHORIZED;
+            Err(resp)
+        }
+    }
+}
+
+/// Changes sent to a client after a [`SubscribeMsg`].
+struct Subscription {
+    msg_id: String,
+    group: Option<String>,
+    changes: broadcast::Receiver<Change>,
+}
+
+impl Subscription {
+    /// Subscribe, returning the snapshot to send first.
+    fn new(msg: SubscribeMsg, store: &Store) -> (Subscription, ApiMessage) {
+        let (groups, changes) = store.subscribe_with_snapshot(msg.group.as_deref());
+        let snapshot = ApiMessage::RespSnapshot(RespSnapshotMsg {
+            reply_to: msg.msg_id.clone(),
+            groups,
+        });
+        let sub = Subscription {
+            msg_id: msg.msg_id,
+            group: msg.group,
+            changes,
+        };
+        (sub, snapshot)
+    }
+
+    /// The next change to send. A subscriber missing changes gets a new
+    /// snapshot instead.
+    async fn recv(&mut self, store: &Store) -> Option<ApiMessage> {
+        loop {
+            match self.changes.recv().await {
+                Ok(change) if self.group.as_deref().is_some_and(|g| g != change.group()) => {}
+                Ok(change) => return Some(change.into()),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("subscriber missed {} changes, sending a new snapshot", n);
+                    let msg = SubscribeMsg {
+                        msg_id: self.msg_id.clone(),
+                        group: self.group.take(),
+                    };
+                    let (sub, snapshot) = Subscription::new(msg, store);
+                    *self = sub;
+                    return Some(snapshot);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
 }
 
 async fn connection_loop(
-    mut conn: tokio_tungstenite::WebSocketStream<MaybeTlsStream<TcpStream>>,
-    stream: tokio::sync::mpsc::UnboundedSender<ApiMessage>,
+    mut conn: Connection,
+    source: Arc<str>,
+    stream: IncomingSender,
+    mut outgoing: broadcast::Receiver<ApiMessage>,
+    replies: Replies,
+    store: Store,
 ) -> Result<(), tokio_tungstenite::tungstenite::Error> {
-    while let Some(Ok(x)) = conn.next().await {
+    let mut replies = replies.register(source.clone());
+    let mut subscription: Option<Subscription> = None;
+    loop {
+        let x = select! {
+            x = conn.next() => match x {
+                Some(Ok(x)) => x,
+                _ => break,
+            },
+            msg = outgoing.recv() => {
+                match msg {
+                    Ok(msg) => send(&mut conn, &msg).await?,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("dropped {} outgoing messages", n);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+                continue;
+            }
+            Some(msg) = replies.recv() => {
+                send(&mut conn, &msg).await?;
+                continue;
+            }
+            Some(msg) = async {
+                match &mut subscription {
+                    Some(sub) => sub.recv(&store).await,
+                    None => futures::future::pending().await,
+                }
+            } => {
+                send(&mut conn, &msg).await?;
+                continue;
+            }
+        };
         let t = match x.to_text() {
             Ok(text) => text,
             Err(_) => continue,
@@ -99,62 +343,194 @@ async fn connection_loop(
 
         info!("recv message {:?}", value);
 
-        let _ = stream.send(value);
+        if let ApiMessage::Subscribe(msg) = value {
+            // A new subscription replaces the previous one
+            let (sub, snapshot) = Subscription::new(msg, &store);
+            subscription = Some(sub);
+            send(&mut conn, &snapshot).await?;
+            continue;
+        }
+
+        let _ = stream.send(Incoming {
+            source: source.clone(),
+            msg: value,
+        });
     }
     Ok(())
 }
 
+pub async fn send(
+    conn: &mut Connection,
+    msg: &ApiMessage,
+) -> Result<(), tokio_tungstenite::tungstenite::Error> {
+    let text = serde_json::to_string(msg).expect("messages are always serializable");
+    conn.send(WsMessage::Text(text)).await
+}
+
+/// Read messages as JSON lines from our stdin.
+pub async fn read_stdin(stream: IncomingSender) {
+    read_json_lines("stdin", tokio::io::stdin(), &stream).await;
+    info!("stdin closed");
+}
+
+/// Read messages as JSON lines from a named pipe. The pipe is opened for
+/// writing too, so it isn't closed when the last writer goes away.
+pub async fn read_fifo(path: &Path, stream: IncomingSender) {
+    let file = tokio::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .await;
+    match file {
+        Ok(file) => {
+            info!("reading {}", path.display());
+            read_json_lines(&path.display().to_string(), file, &stream).await
+        }
+        Err(e) => log::error!("failed to open {}: {}", path.display(), e),
+    }
+}
+
+async fn read_json_lines(source: &str, reader: impl AsyncRead + Unpin, stream: &IncomingSender) {
+    let mut lines = BufReader::new(reader).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => recv_line(source, &line, stream),
+            Ok(None) => return,
+            Err(e) => {
+                warn!("failed to read {}: {}", source, e);
+                return;
+            }
+        }
+    }
+}
+
+/// Parse a message sent as a line of JSON. Blank lines are skipped.
+pub fn recv_line(source: &str, line: &str, stream: &IncomingSender) {
+    if line.trim().is_empty() {
+        return;
+    }
+    match serde_json::from_str::<ApiMessage>(line) {
+        Ok(msg) => {
+            info!("recv message from {}: {:?}", source, msg);
+            let _ = stream.send(Incoming {
+                source: source.into(),
+                msg,
+            });
+        }
+        Err(e) => warn!("invalid message from {}: {}", source, e),
+    }
+}
+
 const BATCH_TIME: std::time::Duration = std::time::Duration::from_millis(10);
 
-async fn batch_process_messages(
-    mut stream: UnboundedReceiver<ApiMessage>,
-    handle: CursiveHandle,
-    data: Arc<DirtyCheckLock<GroupList>>,
+/// Apply messages from every source to the store, in batches so the screen
+/// isn't redrawn for every single message.
+///
+/// Messages are recorded as received, then rewritten by `rules` and
+/// `scripts`. Snapshot requests are answered through `replies`. `scripts`
+/// learn about user actions from `outgoing`, where the views also acknowledge
+/// messages and mute groups. Changes to the store are written to `changes`.
+/// Without a `handle` there is no screen to refresh.
+#[allow(clippy::too_many_arguments)]
+pub async fn batch_process_messages(
+    mut stream: UnboundedReceiver<Incoming>,
+    handle: Option<CursiveHandle>,
+    store: Store,
+    outgoing: Outgoing,
+    replies: Replies,
+    mut recorder: Option<Recorder>,
+    mut rules: Rules,
+    scripts: Scripts,
+    dnd: Arc<Dnd>,
+    mut changes: Option<ChangeLog>,
 ) {
+    let mut actions = outgoing.subscribe();
     let mut batch = vec![];
+    let mut emitted = vec![];
+    // Set once every sender is gone, e.g. after a replay ends, so the stream
+    // isn't polled again
+    let mut closed = false;
     loop {
         let mut paused = Box::pin(tokio::time::sleep(BATCH_TIME));
-        while let Some(Some(val)) = select! {
-            s = stream.recv() => Some(s),
-            _ = &mut paused => None
-        } {
-            batch.push(val);
-        }
-
-        let mut data = data.write();
-        for item in batch.drain(..) {
-            match item {
-                ApiMessage::Put(msg) => {
-                    let group = data.get_group(&msg.group).map(|g| g.write());
-                    if let Some(mut g) = group {
-                        g.add_messages(msg.items.into_iter());
+        loop {
+            let val = select! {
+                s = stream.recv(), if !closed => match s {
+                    Some(val) => val,
+                    None => {
+                        closed = true;
+                        continue;
                     }
-                }
-                ApiMessage::Remove(msg) => {
-                    let group = data.get_group(&msg.group).map(|g| g.write());
-                    if let Some(mut g) = group {
-                        g.remove_msg(msg.items.iter().map(|x| x.as_str()));
-                    }
-                }
-                ApiMessage::PutGroup(msg) => {
-                    data.add_group(Arc::new(DirtyCheckLock::new(
-                        crate::model::MessageGroup::new(msg.group),
-                    )));
-                }
-                ApiMessage::RemoveGroup(msg) => {
-                    data.remove_group(msg.group);
-                }
-                ApiMessage::SetGroupCounter(msg) => {
-                    let group = data.get_group(&msg.group).map(|g| g.write());
-                    if let Some(mut g) = group {
-                        g.set_counter(msg.counter);
+                },
+                action = actions.recv() => {
+                    match action {
+                        Ok(ApiMessage::UserAction(action)) => {
+                            emitted.extend(scripts.on_action(&action));
+                            if action.action == UserAction::Acknowledge {
+                                emitted.push(ApiMessage::UserAction(action));
+                            }
+                        }
+                        // Sent by the views, so that backends know too
+                        Ok(msg @ ApiMessage::SetGroupMute(_)) => emitted.push(msg),
+                        _ => {}
                     }
+                    continue;
                 }
-                ApiMessage::Config => {
-                    warn!("Config message is not yet supported");
+                _ = &mut paused => break,
+            };
+            if let Some(Err(e)) = recorder.as_mut().map(|r| r.record(&val)) {
+                log::error!("stopped recording: {}", e);
+                recorder = None;
+            }
+            batch.push(val);
+        }
+        if let Some(Err(e)) = recorder.as_mut().map(Recorder::flush) {
+            log::error!("stopped recording: {}", e);
+            recorder = None;
+        }
+
+        for Incoming { source, msg } in batch.drain(..) {
+            let msgs = scripts.apply_all(rules.apply_all(std::iter::once(msg), &store));
+            for msg in msgs {
+                if let Some(resp) = apply(&store, &dnd, msg, &mut changes) {
+                    replies.send(&source, resp);
                 }
             }
         }
-        let _ = handle.send(Box::new(|c| c.on_event(cursive::event::Event::Refresh)));
+        let expired = scripts.apply_all(rules.expired(std::time::Instant::now()));
+        for msg in expired.into_iter().chain(emitted.drain(..)) {
+            apply(&store, &dnd, msg, &mut changes);
+        }
+        for unmuted in store.unmute_expired(chrono::Utc::now()) {
+            let _ = outgoing.send(unmuted);
+        }
+        write_changes(&mut changes);
+        if let Some(handle) = &handle {
+            let _ = handle.send(Box::new(|c| c.on_event(cursive::event::Event::Refresh)));
+        }
+    }
+}
+
+/// Apply a message to the store, or to `dnd` for [`ApiMessage::SetDnd`].
+/// Returns the response, if any.
+fn apply(
+    store: &Store,
+    dnd: &Dnd,
+    msg: ApiMessage,
+    changes: &mut Option<ChangeLog>,
+) -> Option<ApiMessage> {
+    if let ApiMessage::SetDnd(m) = msg {
+        dnd.set(m.enabled);
+        return None;
+    }
+    let resp = store.apply(msg);
+    // Written as they happen, so that large batches can't fall behind
+    write_changes(changes);
+    resp
+}
+
+fn write_changes(changes: &mut Option<ChangeLog>) {
+    if let Some(Err(e)) = changes.as_mut().map(ChangeLog::write_pending) {
+        log::error!("stopped logging changes: {}", e);
+        *changes = None;
     }
 }
@@ -1,11 +1,18 @@
+pub mod dnd;
+pub mod escalation;
 pub mod fronend;
-pub mod model;
+pub mod mutes;
 pub mod opt;
+pub mod record;
+pub mod relay;
+pub mod rules;
+pub mod scripts;
+pub mod supervisor;
 pub mod ui;
-pub mod util;
 pub mod view;
+pub mod web;
 
-use std::sync::Arc;
+use std::{io::IsTerminal, os::unix::fs::FileTypeExt, sync::Arc};
 
 use chrono::Local;
 use clap::Clap;
@@ -18,18 +25,31 @@ use cursive::{
     Cursive, View,
 };
 
-use self::{
-    model::group_list::GroupList, opt::Opt, util::DirtyCheckLock,
-    view::group_list_view::GroupListView,
-};
+use nadir_core::{model::group_list::GroupList, util::DirtyCheckLock, Store};
+
+use self::{opt::Opt, view::group_list_view::GroupListView};
 
 pub type CursiveHandle = crossbeam::channel::Sender<Box<dyn FnOnce(&mut Cursive) + 'static + Send>>;
 
+/// How many messages to the clients can be queued before the slowest ones
+/// start missing them.
+const OUTGOING_CAPACITY: usize = 64;
+
 #[tokio::main]
 async fn main() {
     // Commandline options
     let opt = Arc::new(Opt::parse());
 
+    let store = Store::new();
+    let (outgoing, _) = tokio::sync::broadcast::channel(OUTGOING_CAPACITY);
+
+    if opt.headless {
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+        start_server(None, store, opt, outgoing).await;
+        let _ = tokio::signal::ctrl_c().await;
+        return;
+    }
+
     let mut siv = cursive::default();
     let theme = init_theme();
     siv.set_theme(theme);
@@ -38,7 +58,12 @@ async fn main() {
     cursive::logger::init();
     log::set_max_level(log::LevelFilter::Info);
 
-    let data = Arc::new(DirtyCheckLock::new(GroupList::new()));
+    let handle = siv.cb_sink().clone();
+
+    // Views send user actions through the sender stored in user data
+    siv.set_user_data(outgoing.clone());
+
+    let dnd = start_server(Some(handle.clone()), store.clone(), opt.clone(), outgoing).await;
 
     siv.add_fullscreen_layer(views::Layer::new(views::ResizedView::with_full_screen(
         views::LinearLayout::vertical()
@@ -48,7 +73,7 @@ async fn main() {
                     .hidden()
                     .with_name("debug"),
             )
-            .child(build_body(data.clone())),
+            .child(build_body(store.groups().clone(), dnd.clone())),
     )));
 
     siv.add_global_callback(Event::CtrlChar('d'), |c| {
@@ -56,10 +81,12 @@ async fn main() {
             v.set_visible(!v.is_visible())
         });
     });
-
-    let handle = siv.cb_sink().clone();
-
-    start_server(handle.clone(), data, opt.clone()).await;
+    siv.add_global_callback(Event::CtrlChar('n'), move |_| dnd.toggle());
+    let groups = store.groups().clone();
+    siv.add_global_callback(Event::CtrlChar('u'), move |c| {
+        let dialog = view::group_list_view::muted_groups_dialog(&groups.read(false));
+        c.add_layer(dialog)
+    });
 
     let crossterm_backend = cursive::backends::crossterm::Backend::init().unwrap();
     let buffered_backend = Box::new(cursive_buffered_backend::BufferedBackend::new(
@@ -71,10 +98,36 @@ async fn main() {
 }
 
 /// Testing function for updateing data
-async fn start_server(handle: CursiveHandle, data: Arc<DirtyCheckLock<GroupList>>, opt: Arc<Opt>) {
+async fn start_server(
+    handle: Option<CursiveHandle>,
+    store: Store,
+    opt: Arc<Opt>,
+    outgoing: fronend::Outgoing,
+) -> Arc<dnd::Dnd> {
+    let recorder = opt
+        .record
+        .as_ref()
+        .map(|path| match record::Recorder::create(path) {
+            Ok(r) => r,
+            Err(e) => err_and_exit(format_args!(
+                "Cannot create recording at '{}'.\nReason: {}",
+                path.display(),
+                e
+            )),
+        });
+    let changes = opt.headless.then(|| record::ChangeLog::stdout(&store));
+
     let config = opt.config.clone().unwrap_or_else(|| "./nadir.toml".into());
     let config_file = match tokio::fs::read(&config).await {
         Ok(c) => c,
+        // Replays only use the rules, so the default config file is optional
+        Err(e)
+            if e.kind() == std::io::ErrorKind::NotFound
+                && opt.replay.is_some()
+                && opt.config.is_none() =>
+        {
+            vec![]
+        }
         Err(e) => err_and_exit(format_args!(
             "Cannot read config file at path '{}'.\nReason: {}",
             config.display(),
@@ -89,10 +142,162 @@ async fn start_server(handle: CursiveHandle, data: Arc<DirtyCheckLock<GroupList>
             e
         )),
     };
+    let rules = match rules::Rules::new(config_file.rules.clone()) {
+        Ok(r) => r,
+        Err(e) => err_and_exit(format_args!(
+            "Invalid rule in '{}'.\nReason: {}",
+            config.display(),
+            e
+        )),
+    };
 
+    let config_dir = config.parent().unwrap_or_else(|| std::path::Path::new("."));
+    let script_paths: Vec<_> = config_file
+        .scripts
+        .iter()
+        .map(|p| config_dir.join(p))
+        .collect();
+    let scripts = match scripts::Scripts::load(&script_paths) {
+        Ok(s) => s,
+        Err(e) => err_and_exit(format_args!("Invalid script {}", e)),
+    };
+
+    let mutes_file = match &config_file.mutes_file {
+        Some(path) => Some(config_dir.join(path)),
+        // Replays and headless runs shouldn't touch the user's muted groups
+        None if opt.replay.is_some() || opt.headless => None,
+        None => mutes::default_path(),
+    };
+    if let Some(path) = mutes_file {
+        match mutes::load(&path) {
+            Ok(msgs) => store.apply_all(msgs),
+            Err(e) => err_and_exit(format_args!(
+                "Cannot read muted groups at '{}'.\nReason: {}",
+                path.display(),
+                e
+            )),
+        };
+        tokio::spawn(mutes::watch(store.clone(), path));
+    }
+
+    let dnd = Arc::new(dnd::Dnd::new(&config_file.dnd, handle.clone()));
+    tokio::spawn(dnd::watch(dnd.clone(), store.clone()));
+    if !config_file.dnd.quiet_hours.is_empty() {
+        tokio::spawn(dnd::schedule(
+            dnd.clone(),
+            config_file.dnd.quiet_hours.clone(),
+        ));
+    }
+    tokio::spawn(escalation::escalate(
+        config_file.escalation.clone(),
+        store.clone(),
+        dnd.clone(),
+        handle.clone(),
+    ));
+
+    // Every source of messages feeds the same batch processor
+    let (ch_send, ch_recv) = tokio::sync::mpsc::unbounded_channel();
+    let replies = fronend::Replies::default();
+    tokio::spawn(fronend::batch_process_messages(
+        ch_recv,
+        handle.clone(),
+        store.clone(),
+        outgoing.clone(),
+        replies.clone(),
+        recorder,
+        rules,
+        scripts,
+        dnd.clone(),
+        changes,
+    ));
+
+    if let Some(path) = &opt.replay {
+        let recording = match tokio::fs::read_to_string(path).await {
+            Ok(r) => r,
+            Err(e) => err_and_exit(format_args!(
+                "Cannot read recording at '{}'.\nReason: {}",
+                path.display(),
+                e
+            )),
+        };
+        tokio::spawn(record::replay(recording, opt.speed.unwrap_or(1.0), ch_send));
+        return dnd;
+    }
+
+    let secret: Option<Arc<str>> = config_file.secret.map(Into::into);
     for port in config_file.websocket_listen {
-        tokio::spawn(fronend::start_server(handle.clone(), data.clone(), port));
+        tokio::spawn(fronend::start_server(
+            port,
+            secret.clone(),
+            ch_send.clone(),
+            outgoing.clone(),
+            replies.clone(),
+            store.clone(),
+        ));
     }
+
+    for addr in config_file.http_listen {
+        tokio::spawn(web::start_server(addr, secret.clone(), store.clone()));
+    }
+
+    for path in config_file.fifo_listen {
+        match std::fs::metadata(&path) {
+            Ok(m) if m.file_type().is_fifo() => {}
+            Ok(_) => err_and_exit(format_args!(
+                "'{}' is not a named pipe.\nCreate it with `mkfifo`.",
+                path.display()
+            )),
+            Err(e) => err_and_exit(format_args!(
+                "Cannot read named pipe at '{}'.\nReason: {}",
+                path.display(),
+                e
+            )),
+        }
+        let ch_send = ch_send.clone();
+        tokio::spawn(async move { fronend::read_fifo(&path, ch_send).await });
+    }
+    if !std::io::stdin().is_terminal() {
+        tokio::spawn(fronend::read_stdin(ch_send.clone()));
+    }
+
+    let mut names = std::collections::HashSet::new();
+    for backend in &config_file.backends {
+        if !names.insert(backend.name()) {
+            err_and_exit(format_args!(
+                "Backend '{}' is configured more than once in '{}'.\nGive them different names.",
+                backend.name(),
+                config.display()
+            ));
+        }
+    }
+    let status = Arc::new(supervisor::Status::new(handle));
+    for backend in config_file.backends {
+        tokio::spawn(supervisor::supervise(
+            backend,
+            status.clone(),
+            ch_send.clone(),
+            outgoing.clone(),
+            replies.clone(),
+        ));
+    }
+
+    for relay in config_file.relays {
+        let relay = match relay::Relay::new(relay) {
+            Ok(r) => r,
+            Err(e) => err_and_exit(format_args!(
+                "Cannot read the host name to prefix relayed groups.\nReason: {}\nSet `namespace` in '{}'.",
+                e,
+                config.display()
+            )),
+        };
+        tokio::spawn(relay::relay(
+            relay,
+            store.clone(),
+            status.clone(),
+            outgoing.clone(),
+        ));
+    }
+    dnd
 }
 
 async fn time_update_loop(handle: CursiveHandle) -> ! {
@@ -137,6 +342,17 @@ fn init_stat() -> impl cursive::View {
 
     let time_view = views::NamedView::new("time", views::TextView::new(time));
 
+    let dnd_view = HideableView::new(
+        LinearLayout::horizontal()
+            .child(views::PaddedView::new(
+                Margins::lr(1, 1),
+                views::TextView::new("|").style(ColorStyle::front(Light(Black))),
+            ))
+            .child(views::TextView::new("DND").style(ColorStyle::front(Light(Yellow)))),
+    )
+    .hidden()
+    .with_name("dnd");
+
     let app_ver = views::TextView::new(format!("{} v{}", APP_NAME, APP_VER)).style(Secondary);
 
     views::ResizedView::new(
@@ -144,6 +360,7 @@ fn init_stat() -> impl cursive::View {
         SizeConstraint::Fixed(1),
         views::LinearLayout::horizontal()
             .child(stat_view)
+            .child(dnd_view)
             .child(bar)
             .child(time_view)
             .child(bar2)
@@ -178,8 +395,8 @@ fn init_palette() -> Palette {
     palette
 }
 
-fn build_body(data: Arc<DirtyCheckLock<GroupList>>) -> impl View {
-    views::ResizedView::with_full_screen(GroupListView::new(data, Box::new(build_empty_view)))
+fn build_body(data: Arc<DirtyCheckLock<GroupList>>, dnd: Arc<dnd::Dnd>) -> impl View {
+    views::ResizedView::with_full_screen(GroupListView::new(data, dnd, Box::new(build_empty_view)))
 }
 
 fn build_empty_view() -> Box<dyn View> {
@@ -1,5 +0,0 @@
-//! Type models and containers for this specific use
-pub mod group_list;
-pub mod message_group;
-
-pub use self::message_group::MessageGroup;
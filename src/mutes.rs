@@ -0,0 +1,78 @@
+//! Keeps the muted groups in a file, so that they stay muted after a restart.
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use log::warn;
+use nadir_core::{Change, Store};
+use nadir_types::message::{ApiMessage, SetGroupMuteMsg};
+use tokio::sync::broadcast::error::RecvError;
+
+/// Muted group IDs, with the time snoozed ones are shown again
+type Mutes = BTreeMap<String, Option<DateTime<Utc>>>;
+
+/// `$XDG_STATE_HOME/nadir/mutes.json`, or `~/.local/state/nadir/mutes.json`.
+pub fn default_path() -> Option<PathBuf> {
+    let state = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => Path::new(&std::env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(state.join("nadir/mutes.json"))
+}
+
+/// Read the messages muting the saved groups. There's none if the file
+/// doesn't exist yet.
+pub fn load(path: &Path) -> io::Result<Vec<ApiMessage>> {
+    let file = match std::fs::read(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mutes: Mutes = serde_json::from_slice(&file)?;
+    Ok(mutes
+        .into_iter()
+        .map(|(group, until)| {
+            ApiMessage::SetGroupMute(SetGroupMuteMsg {
+                group,
+                muted: true,
+                until,
+            })
+        })
+        .collect())
+}
+
+fn save(path: &Path, mutes: &Mutes) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // Replace the file at once, so that a crash can't leave half of it
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(mutes)?)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Save the muted groups whenever they change.
+pub async fn watch(store: Store, path: PathBuf) {
+    let mut changes = store.subscribe();
+    loop {
+        match changes.recv().await {
+            Ok(Change::SetGroupMute { .. }) => {}
+            Ok(_) => continue,
+            // Saving the current mutes covers the missed ones too
+            Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => return,
+        }
+        let mutes: Mutes = store
+            .groups()
+            .read(false)
+            .mutes()
+            .map(|(id, until)| (id.to_owned(), until))
+            .collect();
+        if let Err(e) = save(&path, &mutes) {
+            warn!("cannot save muted groups to '{}': {}", path.display(), e);
+        }
+    }
+}
@@ -1,5 +1,6 @@
-use std::{net::SocketAddr, path::PathBuf};
+use std::{collections::HashMap, net::SocketAddr, path::PathBuf};
 
+use chrono::NaiveTime;
 use clap::Clap;
 use serde::{Deserialize, Serialize};
 use url::Url;
@@ -10,6 +11,25 @@ pub struct Opt {
     /// Config path. Defaults to './nadir.toml'
     #[clap(short, long)]
     pub config: Option<PathBuf>,
+
+    /// Run without the TUI. Changes to the state are written to stdout as
+    /// JSON lines, and logs go to stderr.
+    #[clap(long)]
+    pub headless: bool,
+
+    /// Record incoming messages to this file.
+    #[clap(long)]
+    pub record: Option<PathBuf>,
+
+    /// Replay messages recorded with `--record` instead of the sources in the
+    /// config file. Its rules still apply.
+    #[clap(long)]
+    pub replay: Option<PathBuf>,
+
+    /// Replay speed, relative to the recording. Defaults to 1. 0 replays all
+    /// messages at once.
+    #[clap(long, requires = "replay")]
+    pub speed: Option<f64>,
 }
 
 /// Config file for this instance
@@ -19,6 +39,16 @@ pub struct Config {
     /// Socket addresses we listen to.
     pub websocket_listen: Vec<SocketAddr>,
 
+    /// Named pipes we read messages from as JSON lines, one message per line.
+    /// Create them with `mkfifo` first. Messages are also read from stdin when
+    /// it isn't a terminal.
+    pub fifo_listen: Vec<PathBuf>,
+
+    /// Addresses serving a read-only dashboard to browsers. Open
+    /// `http://<address>/` to see it, with `?secret=<secret>` if a secret is
+    /// set.
+    pub http_listen: Vec<SocketAddr>,
+
     /// Websocket addresses we automatically connect to at start.
     pub websocket_connect: Vec<Url>,
 
@@ -29,4 +59,188 @@ pub struct Config {
 
     /// A pre-shared secret to verify connections.
     pub secret: Option<String>,
+
+    /// Backends started at start and restarted when they exit.
+    pub backends: Vec<BackendConfig>,
+
+    /// Upstream frontends our groups are forwarded to.
+    pub relays: Vec<RelayConfig>,
+
+    /// Rules applied to incoming messages, in order.
+    pub rules: Vec<RuleConfig>,
+
+    /// Rhai scripts processing incoming messages after the rules, in order.
+    /// Relative paths start from the directory of this file.
+    pub scripts: Vec<PathBuf>,
+
+    /// Do not disturb, toggled with Ctrl-N or the `set_dnd` message.
+    pub dnd: DndConfig,
+
+    /// The file muted groups are saved to. Defaults to
+    /// `$XDG_STATE_HOME/nadir/mutes.json`, except with `--replay` or
+    /// `--headless`, which don't save them unless this is set. Relative paths
+    /// start from the directory of this file.
+    pub mutes_file: Option<PathBuf>,
+    /// Messages left unread for too long.
+    pub escalation: EscalationConfig,
+}
+
+/// A backend run as a child process. It speaks the protocol as JSON lines
+/// through its stdin and stdout, and its stderr goes to the debug view.
+/// Backends built on `nadir-backend-common` do so with `url = "stdio:"` in
+/// their `[frontend]` config.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackendConfig {
+    /// Name shown in the status bar and the log. Defaults to the file name of
+    /// the program.
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// The program and its arguments.
+    pub command: Vec<String>,
+
+    /// Working directory of the backend.
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+
+    /// Environment variables set for the backend.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+}
+
+impl BackendConfig {
+    pub fn name(&self) -> String {
+        match (&self.name, self.command.first()) {
+            (Some(name), _) => name.clone(),
+            (None, Some(program)) => std::path::Path::new(program)
+                .file_name()
+                .map_or_else(|| program.clone(), |n| n.to_string_lossy().into_owned()),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+/// Forwards groups to another frontend, like a wall display gathering the
+/// notifications of several machines. Group IDs are prefixed with
+/// `<namespace>/` and titles with `<namespace>: `, so that groups of
+/// different machines don't replace each other.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayConfig {
+    /// WebSocket address of the upstream frontend.
+    pub url: Url,
+
+    /// The `secret` of the upstream frontend, if it has one.
+    #[serde(default)]
+    pub secret: Option<String>,
+
+    /// Defaults to the host name.
+    #[serde(default)]
+    pub namespace: Option<String>,
+
+    /// IDs of the groups to forward. Every group is forwarded if empty.
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+/// A rule rewriting incoming messages before they are shown. Rules apply in
+/// order, each to the result of the previous ones.
+///
+/// A rule matches messages meeting all of its conditions, and every message
+/// if it has none. Rules with no condition other than `group` also apply to
+/// the group itself: they can drop it, and set its title and importance.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuleConfig {
+    /// Matches messages of the group with this ID.
+    pub group: Option<String>,
+
+    /// A regular expression matching any tag.
+    pub tag: Option<String>,
+
+    /// A regular expression matching the body.
+    pub body: Option<String>,
+
+    /// Matches messages with at least this counter.
+    pub min_counter: Option<u64>,
+
+    /// Drop the messages. No later rule applies to them.
+    pub drop: bool,
+
+    /// Replace the body. With `body`, only the matched part is replaced, and
+    /// `$name` or `${name}` is replaced by the named capture group and `$1`
+    /// by the numbered one.
+    pub rewrite: Option<String>,
+
+    /// Replace the tags.
+    pub tags: Option<Vec<String>>,
+
+    /// Move the messages to the group with this ID. The group is created
+    /// with this ID as its title if it doesn't exist.
+    pub move_to: Option<String>,
+
+    /// Put the messages in the pinned slot.
+    pub pin: bool,
+
+    /// Seconds before the messages are removed.
+    pub ttl: Option<u64>,
+
+    /// Set the title of the group.
+    pub title: Option<String>,
+
+    /// Set the importance of the group.
+    pub importance: Option<i32>,
+}
+
+/// While do not disturb is on, groups that aren't urgent are hidden. Their
+/// messages are still received, and summed up once it ends.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DndConfig {
+    /// Groups with at least this importance stay shown. Every group is hidden
+    /// if unset.
+    pub urgent_importance: Option<i32>,
+
+    /// Do not disturb turns on when these start and off when they end.
+    pub quiet_hours: Vec<QuietHours>,
+}
+
+/// Important messages left unread for too long ring the terminal bell, and
+/// are shown in red until they're acknowledged. Groups hidden by do not
+/// disturb or muted don't escalate until they're shown again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EscalationConfig {
+    /// Messages of groups with at least this importance escalate. None does
+    /// if unset.
+    pub min_importance: Option<i32>,
+
+    /// Seconds a message can stay unread. Defaults to 300.
+    pub after: u64,
+}
+
+impl Default for EscalationConfig {
+    fn default() -> Self {
+        EscalationConfig {
+            min_importance: None,
+            after: 300,
+        }
+    }
+}
+
+/// Daily hours in local time, like `{ start = "22:00", end = "07:00" }`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            // Past midnight
+            self.start <= time || time < self.end
+        }
+    }
 }
@@ -0,0 +1,137 @@
+//! Recording incoming messages to a file and replaying them.
+//!
+//! Recordings are JSON lines, one message per line:
+//!
+//! ```json
+//! {"time":"2021-04-01T12:00:00.123Z","source":"127.0.0.1:40212","message":{"_t":"put",...}}
+//! ```
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use nadir_core::{Change, Store};
+use nadir_types::message::ApiMessage;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::broadcast::{self, error::TryRecvError},
+    time::Instant,
+};
+
+use crate::fronend::{Incoming, IncomingSender};
+
+/// A line of a recording.
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    time: DateTime<Utc>,
+    source: String,
+    message: ApiMessage,
+}
+
+/// Writes incoming messages to a recording.
+pub struct Recorder {
+    file: BufWriter<File>,
+}
+
+impl Recorder {
+    /// Start a new recording, replacing the file if it exists.
+    pub fn create(path: &Path) -> io::Result<Recorder> {
+        Ok(Recorder {
+            file: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn record(&mut self, incoming: &Incoming) -> io::Result<()> {
+        let entry = Entry {
+            time: Utc::now(),
+            source: incoming.source.to_string(),
+            message: incoming.msg.clone(),
+        };
+        serde_json::to_writer(&mut self.file, &entry)?;
+        self.file.write_all(b"\n")
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// A line of a change log.
+#[derive(Debug, Serialize)]
+struct ChangeEntry<'a> {
+    time: DateTime<Utc>,
+    change: &'a ApiMessage,
+}
+
+/// Writes the changes made to a store as JSON lines, like recordings without
+/// the source. Each change is written as the message that makes it.
+pub struct ChangeLog {
+    changes: broadcast::Receiver<Change>,
+    out: BufWriter<Box<dyn Write + Send>>,
+}
+
+impl ChangeLog {
+    /// Log the changes made to `store` from now on to stdout.
+    pub fn stdout(store: &Store) -> ChangeLog {
+        ChangeLog {
+            changes: store.subscribe(),
+            out: BufWriter::new(Box::new(io::stdout())),
+        }
+    }
+
+    /// Write the changes made since the last call.
+    pub fn write_pending(&mut self) -> io::Result<()> {
+        loop {
+            let change = match self.changes.try_recv() {
+                Ok(change) => change,
+                Err(TryRecvError::Lagged(n)) => {
+                    warn!("missed logging {} changes", n);
+                    continue;
+                }
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            };
+            let entry = ChangeEntry {
+                time: Utc::now(),
+                change: &change.into(),
+            };
+            serde_json::to_writer(&mut self.out, &entry)?;
+            self.out.write_all(b"\n")?;
+        }
+        self.out.flush()
+    }
+}
+
+/// Send the messages of a recording with their original timing, sped up by
+/// `speed`. A speed of 0 sends them all at once.
+pub async fn replay(recording: String, speed: f64, stream: IncomingSender) {
+    let start = Instant::now();
+    let mut first = None;
+    for (i, line) in recording.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: Entry = match serde_json::from_str(line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("skipped line {} of the recording: {}", i + 1, e);
+                continue;
+            }
+        };
+
+        let first = *first.get_or_insert(entry.time);
+        if speed > 0.0 {
+            let offset = (entry.time - first).to_std().unwrap_or_default();
+            tokio::time::sleep_until(start + Duration::from_secs_f64(offset.as_secs_f64() / speed))
+                .await;
+        }
+        let _ = stream.send(Incoming {
+            source: entry.source.into(),
+            msg: entry.message,
+        });
+    }
+    info!("replay finished");
+}
@@ -0,0 +1,187 @@
+//! Forwards our groups to an upstream frontend, and the clicks on them back.
+use std::{io, sync::Arc, time::Duration};
+
+use futures::StreamExt;
+use log::{info, warn};
+use nadir_core::{Change, Store};
+use nadir_types::message::{ApiMessage, GroupSnapshot, RemoveGroupMsg, ReqSnapshotMsg};
+use tokio::{
+    select,
+    sync::broadcast::{self, error::RecvError},
+    time::Instant,
+};
+use tokio_tungstenite::tungstenite::{Error, Message as WsMessage};
+
+use crate::{
+    fronend::{self, Connection, Outgoing},
+    opt::RelayConfig,
+    supervisor::{Status, MAX_BACKOFF, MIN_BACKOFF},
+};
+
+/// How long to wait for the snapshot of the upstream frontend.
+const SYNC_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Relay {
+    config: RelayConfig,
+    namespace: String,
+    /// `<namespace>/`, prepended to group IDs
+    prefix: String,
+}
+
+impl Relay {
+    /// Fails if there's no namespace and the host name can't be read.
+    pub fn new(config: RelayConfig) -> io::Result<Relay> {
+        let namespace = match &config.namespace {
+            Some(ns) => ns.clone(),
+            None => std::fs::read_to_string("/proc/sys/kernel/hostname")?
+                .trim()
+                .to_owned(),
+        };
+        Ok(Relay {
+            prefix: format!("{}/", namespace),
+            namespace,
+            config,
+        })
+    }
+
+    fn forwarded(&self, group: &str) -> bool {
+        self.config.groups.is_empty() || self.config.groups.iter().any(|g| g == group)
+    }
+
+    /// Move a message to our namespace. Mutes aren't forwarded: each display
+    /// has its own.
+    fn prefix(&self, mut msg: ApiMessage) -> Option<ApiMessage> {
+        let group = match &mut msg {
+            ApiMessage::PutGroup(m) => {
+                m.group.title = format!("{}: {}", self.namespace, m.group.title);
+                &mut m.group.id
+            }
+            ApiMessage::RemoveGroup(m) => &mut m.group,
+            ApiMessage::Put(m) => &mut m.group,
+            ApiMessage::Remove(m) => &mut m.group,
+            ApiMessage::SetGroupCounter(m) => &mut m.group,
+            ApiMessage::SetGroupMute(_) => return None,
+            _ => return Some(msg),
+        };
+        group.insert_str(0, &self.prefix);
+        Some(msg)
+    }
+}
+
+/// Keep forwarding groups, reconnecting when the upstream frontend goes away.
+pub async fn relay(relay: Relay, store: Store, status: Arc<Status>, outgoing: Outgoing) {
+    let url = &relay.config.url;
+    let name = format!("relay {}", url.host_str().unwrap_or_else(|| url.as_str()));
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        let started = Instant::now();
+        match fronend::connect(url, relay.config.secret.as_deref()).await {
+            Ok(conn) => {
+                info!("relaying to {}", url);
+                status.set_running(&name, true);
+                match run(&relay, conn, &store, &outgoing).await {
+                    Ok(()) => warn!("upstream {} closed the connection", url),
+                    Err(e) => warn!("lost upstream {}: {}", url, e),
+                }
+            }
+            Err(e) => warn!("cannot connect to upstream {}: {}", url, e),
+        }
+        status.set_running(&name, false);
+
+        if started.elapsed() >= MAX_BACKOFF {
+            backoff = MIN_BACKOFF;
+        }
+        info!("reconnecting to {} in {}s", url, backoff.as_secs());
+        tokio::time::sleep(backoff).await;
+        backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+    }
+}
+
+/// Forward changes until the connection is lost.
+async fn run(
+    relay: &Relay,
+    mut conn: Connection,
+    store: &Store,
+    outgoing: &Outgoing,
+) -> Result<(), Error> {
+    loop {
+        let mut changes = sync(relay, &mut conn, store).await?;
+        loop {
+            select! {
+                change = changes.recv() => match change {
+                    Ok(change) if relay.forwarded(change.group()) => {
+                        if let Some(msg) = relay.prefix(change.into()) {
+                            fronend::send(&mut conn, &msg).await?
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(n)) => {
+                        warn!("relay missed {} changes, syncing again", n);
+                        break;
+                    }
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+                msg = conn.next() => match msg {
+                    Some(msg) => {
+                        if let Some(ApiMessage::UserAction(mut action)) = parse(&msg?) {
+                            if let Some(group) = action.group.strip_prefix(&relay.prefix) {
+                                action.group = group.to_owned();
+                                let _ = outgoing.send(ApiMessage::UserAction(action));
+                            }
+                        }
+                    }
+                    None => return Ok(()),
+                },
+            }
+        }
+    }
+}
+
+/// Replace the groups in our namespace upstream with the current ones, and
+/// receive the changes made from then on.
+async fn sync(
+    relay: &Relay,
+    conn: &mut Connection,
+    store: &Store,
+) -> Result<broadcast::Receiver<Change>, Error> {
+    // Responses go to every client, including the relays of other machines
+    let msg_id = format!("relay-sync-{}", relay.namespace);
+    let req = ApiMessage::ReqSnapshot(ReqSnapshotMsg {
+        msg_id: msg_id.clone(),
+        group: None,
+    });
+    fronend::send(conn, &req).await?;
+    let upstream = tokio::time::timeout(SYNC_TIMEOUT, async {
+        while let Some(msg) = conn.next().await {
+            match parse(&msg?) {
+                Some(ApiMessage::RespSnapshot(resp)) if resp.reply_to == msg_id => {
+                    return Ok(resp.groups)
+                }
+                _ => {}
+            }
+        }
+        Err(Error::ConnectionClosed)
+    })
+    .await
+    .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no snapshot from upstream"))??;
+
+    let (snapshot, changes) = store.subscribe_with_snapshot(None);
+    let stale = upstream
+        .into_iter()
+        .map(|g| g.group.id)
+        .filter(|id| id.starts_with(&relay.prefix))
+        .map(|group| ApiMessage::RemoveGroup(RemoveGroupMsg { group }));
+    let current = snapshot
+        .into_iter()
+        .filter(|g| relay.forwarded(&g.group.id))
+        .flat_map(GroupSnapshot::into_messages)
+        .filter_map(|msg| relay.prefix(msg));
+    for msg in stale.chain(current) {
+        fronend::send(conn, &msg).await?;
+    }
+    Ok(changes)
+}
+
+fn parse(msg: &WsMessage) -> Option<ApiMessage> {
+    serde_json::from_str(msg.to_text().ok()?).ok()
+}
@@ -0,0 +1,494 @@
+//! Rules rewriting incoming messages before they are applied.
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use nadir_core::Store;
+use nadir_types::{
+    message::{ApiMessage, PutGroupMsg, PutMsg, RemoveMsg},
+    model::{Message, MessageGroup},
+};
+use regex::Regex;
+
+use crate::opt::RuleConfig;
+
+/// A compiled [`RuleConfig`].
+#[derive(Debug)]
+struct Rule {
+    tag: Option<Regex>,
+    body: Option<Regex>,
+    config: RuleConfig,
+}
+
+impl Rule {
+    fn new(config: RuleConfig) -> Result<Rule, regex::Error> {
+        let compile = |pattern: &Option<String>| pattern.as_deref().map(Regex::new).transpose();
+        Ok(Rule {
+            tag: compile(&config.tag)?,
+            body: compile(&config.body)?,
+            config,
+        })
+    }
+
+    fn matches_group(&self, group: &str) -> bool {
+        self.config.group.as_deref().is_none_or(|g| g == group)
+    }
+
+    /// Whether the rule applies to groups too, not only to their messages.
+    fn applies_to_groups(&self) -> bool {
+        self.tag.is_none() && self.body.is_none() && self.config.min_counter.is_none()
+    }
+
+    fn matches(&self, group: &str, msg: &Message) -> bool {
+        self.matches_group(group)
+            && self
+                .tag
+                .as_ref()
+                .is_none_or(|re| msg.tags.iter().any(|t| re.is_match(t)))
+            && self.body.as_ref().is_none_or(|re| re.is_match(&msg.body))
+            && self
+                .config
+                .min_counter
+                .is_none_or(|min| msg.counter.is_some_and(|c| c >= min))
+    }
+}
+
+/// A message after the rules applied to it.
+struct Routed {
+    group: String,
+    pinned: bool,
+    msg: Message,
+    ttl: Option<u64>,
+}
+
+/// The rules of the config file, and the messages they will remove.
+#[derive(Debug, Default)]
+pub struct Rules {
+    rules: Vec<Rule>,
+    /// When messages put with a TTL expire, by group, ID and slot
+    expiry: HashMap<(String, String, bool), Instant>,
+    /// The earliest time in `expiry`
+    next_expiry: Option<Instant>,
+}
+
+impl Rules {
+    pub fn new(configs: Vec<RuleConfig>) -> Result<Rules, regex::Error> {
+        Ok(Rules {
+            rules: configs
+                .into_iter()
+                .map(Rule::new)
+                .collect::<Result<_, _>>()?,
+            ..Default::default()
+        })
+    }
+
+    /// Apply the rules to messages, in order. Groups that messages are moved
+    /// to are created if `store` doesn't have them.
+    pub fn apply_all(
+        &mut self,
+        msgs: impl IntoIterator<Item = ApiMessage>,
+        store: &Store,
+    ) -> Vec<ApiMessage> {
+        let mut res = vec![];
+        if self.rules.is_empty() {
+            res.extend(msgs);
+            return res;
+        }
+        let mut created = HashSet::new();
+        for msg in msgs {
+            match msg {
+                ApiMessage::PutGroup(m) => {
+                    if let Some(group) = self.apply_group(m.group) {
+                        res.push(ApiMessage::PutGroup(PutGroupMsg { group }));
+                    }
+                }
+                ApiMessage::SetGroupCounter(m) => {
+                    if self.apply_group(group_meta(&m.group)).is_some() {
+                        res.push(ApiMessage::SetGroupCounter(m));
+                    }
+                }
+                ApiMessage::Put(m) => self.apply_put(m, store, &mut created, &mut res),
+                ApiMessage::Remove(m) => self.apply_remove(m, &mut res),
+                ApiMessage::RemoveGroup(m) => {
+                    self.expiry.retain(|(group, _, _), _| *group != m.group);
+                    res.push(ApiMessage::RemoveGroup(m));
+                }
+                other => res.push(other),
+            }
+        }
+        res
+    }
+
+    /// Removals of the messages whose TTL is over at `now`.
+    pub fn expired(&mut self, now: Instant) -> Vec<ApiMessage> {
+        if self.next_expiry.is_none_or(|next| next > now) {
+            return vec![];
+        }
+        let mut removes: Vec<RemoveMsg> = vec![];
+        self.expiry.retain(|(group, id, pinned), at| {
+            if *at > now {
+                return true;
+            }
+            match removes
+                .iter_mut()
+                .find(|r| r.group == *group && r.pinned == *pinned)
+            {
+                Some(r) => r.items.push(id.clone()),
+                None => removes.push(RemoveMsg {
+                    group: group.clone(),
+                    items: vec![id.clone()],
+                    pinned: *pinned,
+                }),
+            }
+            false
+        });
+        self.next_expiry = self.expiry.values().min().copied();
+        removes.into_iter().map(ApiMessage::Remove).collect()
+    }
+
+    /// Returns `None` if the group is dropped.
+    fn apply_group(&self, mut group: MessageGroup) -> Option<MessageGroup> {
+        for rule in self.rules.iter().filter(|r| r.applies_to_groups()) {
+            if !rule.matches_group(&group.id) {
+                continue;
+            }
+            if rule.config.drop {
+                return None;
+            }
+            if let Some(title) = &rule.config.title {
+                group.title = title.clone();
+            }
+            if let Some(importance) = rule.config.importance {
+                group.importance = importance;
+            }
+        }
+        Some(group)
+    }
+
+    /// Returns `None` if the message is dropped.
+    fn route(&self, group: String, pinned: bool, msg: Message) -> Option<Routed> {
+        let mut routed = Routed {
+            group,
+            pinned,
+            msg,
+            ttl: None,
+        };
+        for rule in &self.rules {
+            if !rule.matches(&routed.group, &routed.msg) {
+                continue;
+            }
+            let config = &rule.config;
+            if config.drop {
+                return None;
+            }
+            if let Some(rewrite) = &config.rewrite {
+                routed.msg.body = match &rule.body {
+                    Some(re) => re.replace_all(&routed.msg.body, rewrite.as_str()).into(),
+                    None => rewrite.clone(),
+                };
+            }
+            if let Some(tags) = &config.tags {
+                routed.msg.tags = tags.clone();
+            }
+            if let Some(group) = &config.move_to {
+                routed.group = group.clone();
+            }
+            routed.pinned |= config.pin;
+            routed.ttl = config.ttl.or(routed.ttl);
+        }
+        Some(routed)
+    }
+
+    fn apply_put(
+        &mut self,
+        msg: PutMsg,
+        store: &Store,
+        created: &mut HashSet<String>,
+        res: &mut Vec<ApiMessage>,
+    ) {
+        // Messages keep their order within each group and slot they end up in
+        let mut puts: Vec<PutMsg> = vec![];
+        for item in msg.items {
+            let routed = match self.route(msg.group.clone(), msg.pinned, item) {
+                Some(r) => r,
+                None => continue,
+            };
+            let key = (routed.group.clone(), routed.msg.id.clone(), routed.pinned);
+            match routed.ttl {
+                Some(ttl) => {
+                    let at = Instant::now() + Duration::from_secs(ttl);
+                    self.expiry.insert(key, at);
+                    self.next_expiry = Some(self.next_expiry.map_or(at, |next| next.min(at)));
+                }
+                None => {
+                    self.expiry.remove(&key);
+                }
+            }
+
+            if routed.group != msg.group
+                && !created.contains(&routed.group)
+                && store
+                    .groups()
+                    .read(false)
+                    .get_group(&routed.group)
+                    .is_none()
+            {
+                created.insert(routed.group.clone());
+                if let Some(group) = self.apply_group(group_meta(&routed.group)) {
+                    res.push(ApiMessage::PutGroup(PutGroupMsg { group }));
+                }
+            }
+
+            match puts.last_mut() {
+                Some(put) if put.group == routed.group && put.pinned == routed.pinned => {
+                    put.items.push(routed.msg)
+                }
+                _ => puts.push(PutMsg {
+                    group: routed.group,
+                    items: vec![routed.msg],
+                    pinned: routed.pinned,
+                }),
+            }
+        }
+        res.extend(puts.into_iter().map(ApiMessage::Put));
+    }
+
+    fn apply_remove(&mut self, msg: RemoveMsg, res: &mut Vec<ApiMessage>) {
+        // Rules may have moved or pinned the messages, so they are removed
+        // from everywhere they could be
+        let mut slots = vec![(msg.group, msg.pinned)];
+        for rule in &self.rules {
+            for i in 0..slots.len() {
+                let (group, pinned) = &slots[i];
+                if !rule.matches_group(group) {
+                    continue;
+                }
+                let group = rule.config.move_to.as_ref().unwrap_or(group).clone();
+                let slot = (group, *pinned || rule.config.pin);
+                if !slots.contains(&slot) {
+                    slots.push(slot);
+                }
+            }
+        }
+        for (group, pinned) in slots {
+            for id in &msg.items {
+                self.expiry.remove(&(group.clone(), id.clone(), pinned));
+            }
+            res.push(ApiMessage::Remove(RemoveMsg {
+                group,
+                items: msg.items.clone(),
+                pinned,
+            }));
+        }
+    }
+}
+
+/// The metadata of a group that doesn't exist yet.
+fn group_meta(id: &str) -> MessageGroup {
+    MessageGroup {
+        id: id.to_owned(),
+        title: id.to_owned(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nadir_types::message::RemoveGroupMsg;
+
+    use super::*;
+
+    fn rules(toml: &str) -> Rules {
+        #[derive(serde::Deserialize)]
+        struct Config {
+            rules: Vec<RuleConfig>,
+        }
+        let config: Config = toml::from_str(toml).unwrap();
+        Rules::new(config.rules).unwrap()
+    }
+
+    fn put(group: &str, items: &[(&str, &[&str], &str)]) -> ApiMessage {
+        ApiMessage::Put(PutMsg {
+            group: group.into(),
+            items: items
+                .iter()
+                .map(|(id, tags, body)| Message {
+                    id: (*id).into(),
+                    tags: tags.iter().map(|&t| t.into()).collect(),
+                    body: (*body).into(),
+                    ..Default::default()
+                })
+                .collect(),
+            pinned: false,
+        })
+    }
+
+    fn remove(group: &str, ids: &[&str]) -> ApiMessage {
+        ApiMessage::Remove(RemoveMsg {
+            group: group.into(),
+            items: ids.iter().map(|&id| id.into()).collect(),
+            pinned: false,
+        })
+    }
+
+    /// Summarize messages as `<type> <group>[ pinned]: <items>`.
+    fn summary(msgs: &[ApiMessage]) -> Vec<String> {
+        msgs.iter()
+            .map(|msg| match msg {
+                ApiMessage::PutGroup(m) => format!(
+                    "put_group {}: {} {}",
+                    m.group.id, m.group.title, m.group.importance
+                ),
+                ApiMessage::Put(m) => {
+                    let items: Vec<_> = m
+                        .items
+                        .iter()
+                        .map(|i| format!("{}={}|{}", i.id, i.tags.join(","), i.body))
+                        .collect();
+                    let pinned = if m.pinned { " pinned" } else { "" };
+                    format!("put {}{}: {}", m.group, pinned, items.join(" "))
+                }
+                ApiMessage::Remove(m) => {
+                    let pinned = if m.pinned { " pinned" } else { "" };
+                    format!("remove {}{}: {}", m.group, pinned, m.items.join(" "))
+                }
+                other => format!("{:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rewrite_and_route() {
+        let mut rules = rules(
+            r#"
+            [[rules]]
+            group = "ci"
+            body = "^\\[bot\\] (?P<rest>.*)"
+            rewrite = "$rest"
+            tags = ["bot"]
+            move_to = "bots"
+
+            [[rules]]
+            tag = "^urgent$"
+            pin = true
+
+            [[rules]]
+            body = "flaky"
+            drop = true
+            "#,
+        );
+        let store = Store::new();
+        let res = rules.apply_all(
+            vec![put(
+                "ci",
+                &[
+                    ("a", &["alice"], "[bot] bump deps"),
+                    ("b", &["urgent"], "build broke"),
+                    ("c", &[], "flaky test"),
+                    ("d", &["bob"], "plain"),
+                ],
+            )],
+            &store,
+        );
+        assert_eq!(
+            summary(&res),
+            vec![
+                "put_group bots: bots 0",
+                "put bots: a=bot|bump deps",
+                "put ci pinned: b=urgent|build broke",
+                "put ci: d=bob|plain",
+            ]
+        );
+
+        // Removes reach the messages wherever rules put them
+        let res = rules.apply_all(vec![remove("ci", &["a", "b"])], &store);
+        assert_eq!(
+            summary(&res),
+            vec![
+                "remove ci: a b",
+                "remove bots: a b",
+                "remove ci pinned: a b",
+                "remove bots pinned: a b",
+            ]
+        );
+    }
+
+    #[test]
+    fn group_rules() {
+        let mut rules = rules(
+            r#"
+            [[rules]]
+            group = "noise"
+            importance = -5
+            title = "Noise"
+
+            [[rules]]
+            group = "spam"
+            drop = true
+
+            [[rules]]
+            group = "noise"
+            body = "ignored for groups"
+            title = "Not applied"
+            "#,
+        );
+        let group = |id: &str| {
+            ApiMessage::PutGroup(PutGroupMsg {
+                group: group_meta(id),
+            })
+        };
+        let res = rules.apply_all(
+            vec![
+                group("noise"),
+                group("spam"),
+                put("spam", &[("a", &[], "spam")]),
+                group("other"),
+                ApiMessage::RemoveGroup(RemoveGroupMsg {
+                    group: "spam".into(),
+                }),
+            ],
+            &Store::new(),
+        );
+        assert_eq!(
+            summary(&res),
+            vec![
+                "put_group noise: Noise -5",
+                "put_group other: other 0",
+                r#"RemoveGroup(RemoveGroupMsg { group: "spam" })"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn ttl() {
+        let mut rules = rules(
+            r#"
+            [[rules]]
+            group = "g"
+            tag = "short"
+            ttl = 60
+            "#,
+        );
+        let store = Store::new();
+        let start = Instant::now();
+        rules.apply_all(
+            vec![put(
+                "g",
+                &[
+                    ("a", &["short"], "a"),
+                    ("b", &["short"], "b"),
+                    ("c", &[], "c"),
+                ],
+            )],
+            &store,
+        );
+        assert!(rules.expired(start).is_empty());
+
+        // Putting a message again without a TTL keeps it
+        rules.apply_all(vec![put("g", &[("b", &[], "b")])], &store);
+        let later = start + Duration::from_secs(61);
+        assert_eq!(summary(&rules.expired(later)), vec!["remove g: a"]);
+        assert!(rules.expired(later).is_empty());
+    }
+}
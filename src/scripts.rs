@@ -0,0 +1,360 @@
+//! Rhai scripts processing messages after the rules.
+//!
+//! A script can define any of these functions:
+//!
+//! - `on_message(group, msg)` for every message put, with the fields of a
+//!   [`Message`] in `msg`.
+//! - `on_group(group)` for every group put, with the fields of a
+//!   [`MessageGroup`](nadir_types::model::MessageGroup).
+//! - `on_action(action)` when the user acts on a message, with the fields of a
+//!   [`UserActionMsg`].
+//!
+//! `on_message` and `on_group` keep the value if they return nothing, drop it
+//! if they return `false`, and replace it if they return another map. Every
+//! function can send new messages with `emit(msg)`, where `msg` is a map like
+//! the JSON of the protocol. Emitted messages skip the rules and the scripts.
+use std::{collections::HashSet, path::Path, sync::Arc};
+
+use log::{info, warn};
+use nadir_types::{
+    message::{ApiMessage, PutGroupMsg, UserActionMsg},
+    model::Message,
+};
+use parking_lot::Mutex;
+use rhai::{
+    serde::{from_dynamic, to_dynamic},
+    CallFnOptions, Dynamic, Engine, FuncArgs, Scope, AST,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Operations a script can run in one call, so that a script stuck in a loop
+/// can't stop messages from being processed.
+const MAX_OPERATIONS: u64 = 100_000;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_STRING_SIZE: usize = 64 * 1024;
+const MAX_COLLECTION_SIZE: usize = 1024;
+
+struct Script {
+    name: String,
+    ast: AST,
+    /// The names of the functions defined in the script
+    callbacks: HashSet<String>,
+}
+
+pub struct Scripts {
+    engine: Engine,
+    scripts: Vec<Script>,
+    /// Messages passed to `emit` during the current call
+    emitted: Arc<Mutex<Vec<Dynamic>>>,
+}
+
+impl Default for Scripts {
+    fn default() -> Self {
+        Scripts::new()
+    }
+}
+
+impl Scripts {
+    pub fn new() -> Scripts {
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(MAX_CALL_LEVELS)
+            .set_max_string_size(MAX_STRING_SIZE)
+            .set_max_array_size(MAX_COLLECTION_SIZE)
+            .set_max_map_size(MAX_COLLECTION_SIZE)
+            .disable_symbol("eval")
+            .on_print(|text| info!("[script] {}", text))
+            .on_debug(|text, source, pos| {
+                info!(
+                    "[script] {} {:?}: {}",
+                    source.unwrap_or_default(),
+                    pos,
+                    text
+                )
+            });
+
+        let emitted = Arc::new(Mutex::new(vec![]));
+        let sink = emitted.clone();
+        engine.register_fn("emit", move |msg: Dynamic| sink.lock().push(msg));
+        Scripts {
+            engine,
+            scripts: vec![],
+            emitted,
+        }
+    }
+
+    /// Load scripts from files. Their top-level statements run once now.
+    pub fn load(paths: &[impl AsRef<Path>]) -> Result<Scripts, String> {
+        let mut scripts = Scripts::new();
+        for path in paths {
+            let path = path.as_ref();
+            let source = std::fs::read_to_string(path)
+                .map_err(|e| format!("cannot read '{}': {}", path.display(), e))?;
+            scripts.add(&path.display().to_string(), &source)?;
+        }
+        Ok(scripts)
+    }
+
+    fn add(&mut self, name: &str, source: &str) -> Result<(), String> {
+        let ast = self
+            .engine
+            .compile(source)
+            .map_err(|e| format!("'{}': {}", name, e))?;
+        self.engine
+            .run_ast(&ast)
+            .map_err(|e| format!("'{}': {}", name, e))?;
+        self.scripts.push(Script {
+            name: name.to_owned(),
+            callbacks: ast.iter_functions().map(|f| f.name.to_owned()).collect(),
+            ast,
+        });
+        // Messages emitted at load have nowhere to go
+        self.emitted.lock().clear();
+        Ok(())
+    }
+
+    /// Pass messages through the scripts, in order. Messages emitted by the
+    /// scripts follow.
+    pub fn apply_all(&self, msgs: impl IntoIterator<Item = ApiMessage>) -> Vec<ApiMessage> {
+        let mut res = vec![];
+        if self.scripts.is_empty() {
+            res.extend(msgs);
+            return res;
+        }
+        for msg in msgs {
+            match msg {
+                ApiMessage::PutGroup(m) => {
+                    if let Some(group) = self.filter("on_group", None, m.group) {
+                        res.push(ApiMessage::PutGroup(PutGroupMsg { group }));
+                    }
+                }
+                ApiMessage::Put(mut m) => {
+                    let group = m.group.clone();
+                    m.items = m
+                        .items
+                        .into_iter()
+                        .filter_map(|item| self.filter::<Message>("on_message", Some(&group), item))
+                        .collect();
+                    res.push(ApiMessage::Put(m));
+                }
+                other => res.push(other),
+            }
+        }
+        res.extend(self.take_emitted());
+        res
+    }
+
+    /// Tell the scripts about a user action. Returns the messages they emit.
+    pub fn on_action(&self, action: &UserActionMsg) -> Vec<ApiMessage> {
+        if self.scripts.is_empty() {
+            return vec![];
+        }
+        let arg = match to_dynamic(action) {
+            Ok(a) => a,
+            Err(e) => {
+                warn!("cannot pass action to scripts: {}", e);
+                return vec![];
+            }
+        };
+        for script in self.scripts_with("on_action") {
+            if let Err(e) = self.call(script, "on_action", (arg.clone(),)) {
+                warn!("[{}] on_action failed: {}", script.name, e);
+            }
+        }
+        self.take_emitted()
+    }
+
+    /// Pass `value` through the `callback` of every script defining it.
+    /// Returns `None` if a script drops it.
+    fn filter<T: Serialize + DeserializeOwned>(
+        &self,
+        callback: &str,
+        group: Option<&str>,
+        mut value: T,
+    ) -> Option<T> {
+        for script in self.scripts_with(callback) {
+            let arg = match to_dynamic(&value) {
+                Ok(a) => a,
+                Err(e) => {
+                    warn!("cannot pass value to {}: {}", callback, e);
+                    return Some(value);
+                }
+            };
+            let res = match group {
+                Some(group) => self.call(script, callback, (group.to_owned(), arg)),
+                None => self.call(script, callback, (arg,)),
+            };
+            match res {
+                Ok(r) if r.is_unit() || r.as_bool() == Ok(true) => {}
+                Ok(r) if r.as_bool() == Ok(false) => return None,
+                Ok(r) => match from_dynamic(&r) {
+                    Ok(v) => value = v,
+                    Err(e) => warn!(
+                        "[{}] {} returned an invalid value: {}",
+                        script.name, callback, e
+                    ),
+                },
+                Err(e) => warn!("[{}] {} failed: {}", script.name, callback, e),
+            }
+        }
+        Some(value)
+    }
+
+    fn scripts_with<'a>(&'a self, callback: &'a str) -> impl Iterator<Item = &'a Script> {
+        self.scripts
+            .iter()
+            .filter(move |s| s.callbacks.contains(callback))
+    }
+
+    fn call(
+        &self,
+        script: &Script,
+        callback: &str,
+        args: impl FuncArgs,
+    ) -> Result<Dynamic, Box<rhai::EvalAltResult>> {
+        // The top-level statements already ran at load
+        let options = CallFnOptions::new().eval_ast(false);
+        self.engine
+            .call_fn_with_options(options, &mut Scope::new(), &script.ast, callback, args)
+    }
+
+    fn take_emitted(&self) -> Vec<ApiMessage> {
+        let emitted = std::mem::take(&mut *self.emitted.lock());
+        emitted
+            .iter()
+            .filter_map(|msg| match from_dynamic(msg) {
+                Ok(msg) => Some(msg),
+                Err(e) => {
+                    warn!("scripts emitted an invalid message: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nadir_types::{
+        message::{PutMsg, UserAction},
+        model::MessageGroup,
+    };
+
+    use super::*;
+
+    fn scripts(source: &str) -> Scripts {
+        let mut scripts = Scripts::new();
+        scripts.add("test", source).unwrap();
+        scripts
+    }
+
+    fn put(group: &str, bodies: &[&str]) -> ApiMessage {
+        ApiMessage::Put(PutMsg {
+            group: group.into(),
+            items: bodies
+                .iter()
+                .map(|&body| Message {
+                    id: body.into(),
+                    body: body.into(),
+                    tags: vec!["tag".into()],
+                    ..Default::default()
+                })
+                .collect(),
+            pinned: false,
+        })
+    }
+
+    fn bodies(msg: &ApiMessage) -> Vec<String> {
+        match msg {
+            ApiMessage::Put(m) => m.items.iter().map(|i| i.body.clone()).collect(),
+            other => panic!("expected a put, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn on_message() {
+        let scripts = scripts(
+            r#"
+            fn on_message(group, msg) {
+                if msg.body == "drop" { return false; }
+                if msg.body == "keep" { return; }
+                if msg.body == "copy" {
+                    emit(#{ _t: "put", group: "copies", items: [msg] });
+                    return;
+                }
+                msg.body = group + ": " + msg.body.to_upper();
+                msg.tags.push("seen");
+                msg
+            }
+            "#,
+        );
+        let res = scripts.apply_all(vec![put("g", &["drop", "keep", "copy", "hello"])]);
+        assert_eq!(res.len(), 2);
+        assert_eq!(bodies(&res[0]), vec!["keep", "copy", "g: HELLO"]);
+        match &res[0] {
+            ApiMessage::Put(m) => assert_eq!(m.items[2].tags, vec!["tag", "seen"]),
+            _ => unreachable!(),
+        }
+        match &res[1] {
+            ApiMessage::Put(m) => assert_eq!(m.group, "copies"),
+            other => panic!("expected the emitted put, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn on_group_and_action() {
+        let scripts = scripts(
+            r#"
+            fn on_group(group) {
+                if group.id == "hidden" { return false; }
+                group.importance = 5;
+                group
+            }
+            fn on_action(action) {
+                emit(#{ _t: "remove", group: action.group, items: [action.message] });
+            }
+            "#,
+        );
+        let group = |id: &str| {
+            ApiMessage::PutGroup(PutGroupMsg {
+                group: MessageGroup {
+                    id: id.into(),
+                    ..Default::default()
+                },
+            })
+        };
+        let res = scripts.apply_all(vec![group("hidden"), group("shown")]);
+        match &res[..] {
+            [ApiMessage::PutGroup(m)] => assert_eq!(m.group.importance, 5),
+            other => panic!("expected one group, got {:?}", other),
+        }
+
+        let res = scripts.on_action(&UserActionMsg {
+            group: "g".into(),
+            message: "m".into(),
+            action: UserAction::Click,
+        });
+        match &res[..] {
+            [ApiMessage::Remove(m)] => assert_eq!(m.items, vec!["m"]),
+            other => panic!("expected a remove, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn errors_keep_messages() {
+        let scripts = scripts(
+            r#"
+            fn on_message(group, msg) {
+                if msg.body == "loop" { loop {} }
+                if msg.body == "invalid" { return 42; }
+                msg.missing.call()
+            }
+            "#,
+        );
+        let res = scripts.apply_all(vec![put("g", &["loop", "invalid", "error"])]);
+        assert_eq!(bodies(&res[0]), vec!["loop", "invalid", "error"]);
+
+        assert!(Scripts::new().add("eval", r#"eval("1")"#).is_err());
+    }
+}
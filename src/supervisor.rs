@@ -0,0 +1,178 @@
+//! Runs backends as child processes and restarts them when they exit.
+use std::{collections::BTreeSet, io, process::Stdio, sync::Arc, time::Duration};
+
+use cursive::{
+    theme::{BaseColor::*, Color::*, ColorStyle},
+    views::TextView,
+};
+use log::{info, warn};
+use nadir_types::message::ApiMessage;
+use parking_lot::Mutex;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::{ChildStdin, Command},
+    select,
+    sync::broadcast,
+    time::Instant,
+};
+
+use crate::{
+    fronend::{self, IncomingSender, Outgoing, Replies, ReplyReceiver},
+    opt::BackendConfig,
+    CursiveHandle,
+};
+
+pub const MIN_BACKOFF: Duration = Duration::from_secs(1);
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Shows backends and relays that aren't running in the status bar, if there
+/// is one.
+pub struct Status {
+    handle: Option<CursiveHandle>,
+    down: Mutex<BTreeSet<String>>,
+}
+
+impl Status {
+    pub fn new(handle: Option<CursiveHandle>) -> Self {
+        Status {
+            handle,
+            down: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    pub fn set_running(&self, name: &str, running: bool) {
+        let mut down = self.down.lock();
+        let changed = if running {
+            down.remove(name)
+        } else {
+            down.insert(name.to_owned())
+        };
+        let handle = match &self.handle {
+            Some(handle) if changed => handle,
+            _ => return,
+        };
+        let (text, color) = if down.is_empty() {
+            ("NOMINAL".to_owned(), Light(Green))
+        } else {
+            let names: Vec<_> = down.iter().map(String::as_str).collect();
+            (format!("DOWN: {}", names.join(", ")), Light(Red))
+        };
+        let _ = handle.send(Box::new(move |c| {
+            c.call_on_name("stat", |v: &mut TextView| {
+                v.set_content(text);
+                v.set_style(ColorStyle::front(color));
+            });
+        }));
+    }
+}
+
+/// Keep a backend running forever.
+pub async fn supervise(
+    config: BackendConfig,
+    status: Arc<Status>,
+    stream: IncomingSender,
+    outgoing: Outgoing,
+    replies: Replies,
+) {
+    let name = config.name();
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        let started = Instant::now();
+        match run(&name, &config, &status, &stream, &outgoing, &replies).await {
+            Ok(code) => warn!("backend {} exited with {}", name, code),
+            Err(e) => warn!("backend {} failed to start: {}", name, e),
+        }
+        status.set_running(&name, false);
+
+        // Backends that ran for a while are restarted quickly again
+        if started.elapsed() >= MAX_BACKOFF {
+            backoff = MIN_BACKOFF;
+        }
+        info!("restarting {} in {}s", name, backoff.as_secs());
+        tokio::time::sleep(backoff).await;
+        backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+    }
+}
+
+/// Run a backend until it exits.
+async fn run(
+    name: &str,
+    config: &BackendConfig,
+    status: &Status,
+    stream: &IncomingSender,
+    outgoing: &Outgoing,
+    replies: &Replies,
+) -> io::Result<std::process::ExitStatus> {
+    let (program, args) = config
+        .command
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .envs(&config.env)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    if let Some(dir) = &config.dir {
+        command.current_dir(dir);
+    }
+    let mut child = command.spawn()?;
+    info!("started backend {}", name);
+    status.set_running(name, true);
+
+    // Written from another task, so a backend not reading its stdin can't
+    // stop its stdout from being read
+    let stdin = child.stdin.take().expect("stdin is piped");
+    let writer = tokio::spawn(write_messages(
+        stdin,
+        outgoing.subscribe(),
+        replies.register(name.into()),
+    ));
+    let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped")).lines();
+    let mut stderr = BufReader::new(child.stderr.take().expect("stderr is piped")).lines();
+    let (mut stdout_open, mut stderr_open) = (true, true);
+
+    let res = loop {
+        select! {
+            line = stdout.next_line(), if stdout_open => match line {
+                Ok(Some(line)) => fronend::recv_line(name, &line, stream),
+                Ok(None) | Err(_) => stdout_open = false,
+            },
+            line = stderr.next_line(), if stderr_open => match line {
+                Ok(Some(line)) => info!("[{}] {}", name, line),
+                Ok(None) | Err(_) => stderr_open = false,
+            },
+            // Waiting only after the output is closed keeps its last lines
+            res = child.wait(), if !stdout_open && !stderr_open => break res,
+        }
+    };
+    writer.abort();
+    res
+}
+
+async fn write_messages(
+    mut stdin: ChildStdin,
+    mut outgoing: broadcast::Receiver<ApiMessage>,
+    mut replies: ReplyReceiver,
+) {
+    loop {
+        let msg = tokio::select! {
+            msg = outgoing.recv() => match msg {
+                Ok(msg) => msg,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("dropped {} outgoing messages", n);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            Some(msg) = replies.recv() => msg,
+        };
+        let mut line = serde_json::to_vec(&msg).expect("messages are always serializable");
+        line.push(b'\n');
+        if stdin.write_all(&line).await.is_err() || stdin.flush().await.is_err() {
+            return;
+        }
+    }
+}
@@ -2,6 +2,9 @@ pub mod group_list_view;
 pub mod group_view;
 pub mod tag_view;
 
+#[cfg(test)]
+mod tests;
+
 use std::fmt::Display;
 
 /// Format a number to at most 4 chars
@@ -22,25 +25,3 @@ impl Display for SimplifyNumber {
         }
     }
 }
-
-struct Separated<I>(char, char, char, I);
-
-impl<'a, I, S> Display for Separated<I>
-where
-    I: Iterator<Item = S> + Clone,
-    S: Into<&'a str>,
-{
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut it = self.3.clone();
-        let first = it.next();
-        if first.is_none() {
-            return Ok(());
-        }
-        let first = first.unwrap();
-        write!(f, "{}{}", self.0, first.into())?;
-        for rest in it {
-            write!(f, "{}{}", self.1, rest.into())?;
-        }
-        write!(f, "{}", self.2)
-    }
-}
@@ -1,23 +1,27 @@
 use std::sync::Arc;
 
+use chrono::{Local, Utc};
 use cursive::{
+    theme::PaletteColor,
     traits::Nameable,
     view::ViewWrapper,
-    views::{LinearLayout, ResizedView},
+    views::{Dialog, LinearLayout, ResizedView, SelectView, TextView},
     wrap_impl, Vec2, View,
 };
 use log::debug;
 
-use super::group_view::GroupView;
-use crate::{model::group_list::GroupList, util::DirtyCheckLock};
-
-const MIN_SIZE_FOR_EACH_LAYOUT: usize = 3;
+use super::group_view::{set_group_mute, GroupView};
+use crate::dnd::Dnd;
+use nadir_core::{model::group_list::GroupList, util::DirtyCheckLock};
 
 /// A view to dynamically reorder message groups
 pub struct GroupListView {
     pub data: Arc<DirtyCheckLock<GroupList>>,
     pub if_empty: Box<dyn Fn() -> Box<dyn View>>,
+    pub dnd: Arc<Dnd>,
 
+    /// Whether the shown groups were picked with do not disturb on
+    dnd_shown: bool,
     layout: GroupListViewLayout,
     view: ResizedView<LinearLayout>,
 }
@@ -26,17 +30,19 @@ pub struct GroupListView {
 struct GroupListViewLayout {
     pub last_size: Vec2,
     pub size_changed: bool,
-    pub children_sizes: Vec<usize>,
 }
 
 impl GroupListView {
     pub fn new(
         data: Arc<DirtyCheckLock<GroupList>>,
+        dnd: Arc<Dnd>,
         if_empty: Box<dyn Fn() -> Box<dyn View>>,
     ) -> Self {
         GroupListView {
             data,
             if_empty,
+            dnd,
+            dnd_shown: false,
             layout: Default::default(),
             view: ResizedView::with_full_screen(LinearLayout::vertical()),
         }
@@ -46,8 +52,12 @@ impl GroupListView {
     //     self.data.is_dirty() || self.data.read(false).iter().any(|i| i.is_dirty())
     // }
 
+    fn is_dirty(&self) -> bool {
+        self.data.is_dirty() || self.dnd.is_on() != self.dnd_shown
+    }
+
     fn dirty_check_and_layout_update(&mut self) {
-        let is_dirty = self.data.is_dirty();
+        let is_dirty = self.is_dirty();
         debug!(
             "group list: dirty check: dirty {}, size {:?}",
             is_dirty, self.layout.last_size
@@ -72,15 +82,29 @@ impl GroupListView {
                 inner.remove_child(i);
             }
 
+            self.dnd_shown = self.dnd.is_on();
+            let now = Utc::now();
+            let mut hidden = 0;
             if guard.is_empty() {
                 inner.add_child((self.if_empty)());
             } else {
-                for (i, (n, v)) in guard.iter().enumerate() {
+                for (n, v) in guard.iter() {
+                    if self.dnd.hides(v.read(false).meta().importance) || guard.is_muted(n, now) {
+                        hidden += 1;
+                        continue;
+                    }
                     v.set_dirty(true);
                     inner.add_child(GroupView::new(v.clone()).with_name(format!("v-group-{}", n)));
-                    inner.set_weight(i, 1);
+                    inner.set_weight(inner.len() - 1, 1);
                 }
             }
+            if hidden > 0 {
+                let s = if hidden == 1 { "" } else { "s" };
+                inner.add_child(
+                    TextView::new(format!("- {} group{} hidden", hidden, s))
+                        .style(PaletteColor::Secondary),
+                );
+            }
 
             let _ = inner.set_focus_index(focus);
         }
@@ -93,7 +117,7 @@ impl ViewWrapper for GroupListView {
     wrap_impl!(self.view: ResizedView<LinearLayout>);
 
     fn wrap_needs_relayout(&self) -> bool {
-        self.view.needs_relayout() || self.data.is_dirty()
+        self.view.needs_relayout() || self.is_dirty()
     }
 
     fn wrap_required_size(&mut self, req: Vec2) -> Vec2 {
@@ -105,3 +129,31 @@ impl ViewWrapper for GroupListView {
         self.view.layout(size)
     }
 }
+
+/// A dialog listing the muted groups, unmuting the one picked.
+pub fn muted_groups_dialog(list: &GroupList) -> Dialog {
+    let mut mutes: Vec<_> = list.mutes().collect();
+    if mutes.is_empty() {
+        return Dialog::info("No group is muted");
+    }
+    mutes.sort();
+    let mut select = SelectView::new();
+    for (id, until) in mutes {
+        let mut label = match list.get_group(id) {
+            Some(g) => g.read(false).meta().title.clone(),
+            None => id.to_owned(),
+        };
+        if let Some(until) = until {
+            let until = until.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+            label.push_str(&format!(" (until {})", until));
+        }
+        select.add_item(label, id.to_owned());
+    }
+    select.set_on_submit(|c, group: &String| {
+        c.pop_layer();
+        set_group_mute(c, group, false, None);
+    });
+    Dialog::around(select)
+        .title("Unmute a group")
+        .dismiss_button("Cancel")
+}
@@ -1,19 +1,29 @@
-use std::sync::Arc;
-
+use chrono::{DateTime, Duration, Utc};
 use cursive::{
+    event::{Event, EventResult, Key},
     traits::Finder,
     view::{Selector, ViewWrapper},
-    views::{HideableView, LinearLayout, NamedView, PaddedView, TextView},
-    Vec2, View,
+    views::{Dialog, HideableView, LinearLayout, NamedView, PaddedView, SelectView, TextView},
+    Cursive, Vec2, View,
 };
-use indexmap::IndexSet;
 use log::debug;
+use nadir_core::model::GroupRef;
+use nadir_types::{
+    message::{ApiMessage, SetGroupMuteMsg, UserAction, UserActionMsg},
+    model::Message,
+};
 use smol_str::SmolStr;
 
 use super::tag_view::TagView;
-use crate::{model::MessageGroup, util::DirtyCheckLock};
+use crate::fronend::Outgoing;
 
-pub type GroupRef = Arc<DirtyCheckLock<MessageGroup>>;
+/// How long a group can be snoozed, in minutes.
+const SNOOZE_MINUTES: [(&str, i64); 4] = [
+    ("15 minutes", 15),
+    ("1 hour", 60),
+    ("4 hours", 4 * 60),
+    ("1 day", 24 * 60),
+];
 
 pub struct GroupView {
     pub group: GroupRef,
@@ -25,8 +35,6 @@ pub struct GroupView {
 
 #[derive(Debug)]
 struct GroupViewLayout {
-    pub order_buf: IndexSet<SmolStr>,
-    pub reorder: Vec<usize>,
     pub last_size: Vec2,
     pub size_changed: bool,
 }
@@ -36,8 +44,6 @@ impl Default for GroupViewLayout {
         GroupViewLayout {
             last_size: Vec2::default(),
             size_changed: true,
-            order_buf: IndexSet::new(),
-            reorder: vec![],
         }
     }
 }
@@ -52,10 +58,6 @@ impl GroupView {
         }
     }
 
-    fn name(&self) -> String {
-        self.group.read(false).meta().title.clone()
-    }
-
     /// Get a reference to the group view's folded.
     pub fn folded(&self) -> bool {
         self.folded
@@ -70,6 +72,12 @@ impl GroupView {
         self.group.is_dirty()
     }
 
+    /// The ID of the currently focused message, if any.
+    fn focused_message(&mut self) -> Option<SmolStr> {
+        let body = self.view.find_name::<LinearLayout>("msgs")?;
+        body.get_child(body.get_focus_index()).and_then(tag_view_id)
+    }
+
     fn available_vertical_space(&self) -> usize {
         self.layout.last_size.y.saturating_sub(1)
     }
@@ -112,19 +120,18 @@ impl GroupView {
 
         // Calculate available spaces
         let max_entry_cnt = self.available_vertical_space();
-        let max_pinned_cnt = (max_entry_cnt + 1) / 2;
+        let max_pinned_cnt = max_entry_cnt.div_ceil(2);
 
         // Acquire read lock
         let group = self.group.read(true);
 
         // Set group name
         let counter = group.counter();
-        let content;
-        if counter > 1 {
-            content = format!("- [{}] {}", counter, group.meta().title);
+        let content = if counter > 1 {
+            format!("- [{}] {}", counter, group.meta().title)
         } else {
-            content = format!("- {}", group.meta().title);
-        }
+            format!("- {}", group.meta().title)
+        };
         self.view
             .call_on::<TextView, _, _>(&Selector::Name("group-name"), |v| {
                 v.set_content(content);
@@ -137,10 +144,7 @@ impl GroupView {
             .expect("The messages view should always be present");
 
         let mut focus = body.get_focus_index();
-        let focused_view = body
-            .get_child(focus)
-            .and_then(|x| x.downcast_ref::<TagView>());
-        let focused_id = focused_view.map(|x| x.id.clone());
+        let focused_id = body.get_child(focus).and_then(tag_view_id);
 
         debug!(
             "Handle focus on {}: focused: {}, id {:?}",
@@ -157,13 +161,18 @@ impl GroupView {
             body.remove_child(i);
         }
 
+        let tag_view = |item: &Message| match group.unread(&item.id) {
+            Some(unread) => TagView::from(item).unread(unread.overdue),
+            None => TagView::from(item),
+        };
+
         for (_id, pinned_item) in group.pinned_msgs.iter().rev().take(max_pinned_cnt) {
             body.add_child(
                 LinearLayout::horizontal()
                     .child(TextView::new("P "))
-                    .child(TagView::from(pinned_item)),
+                    .child(tag_view(pinned_item)),
             );
-            if focused_id.as_ref().map_or(false, |x| x == _id) {
+            if focused_id.as_ref().is_some_and(|x| x == _id) {
                 let index = body.len() - 1;
                 focus = index;
             }
@@ -173,8 +182,8 @@ impl GroupView {
         let remaining_size = max_entry_cnt - std::cmp::min(max_entry_cnt, pinned_size);
 
         for (_id, item) in group.msgs.iter().rev().take(remaining_size) {
-            body.add_child(PaddedView::lrtb(2, 0, 0, 0, TagView::from(item)));
-            if focused_id.as_ref().map_or(false, |x| x == _id) {
+            body.add_child(PaddedView::lrtb(2, 0, 0, 0, tag_view(item)));
+            if focused_id.as_ref().is_some_and(|x| x == _id) {
                 let index = body.len() - 1;
                 focus = index;
             }
@@ -209,8 +218,107 @@ impl ViewWrapper for GroupView {
     fn wrap_layout(&mut self, size: Vec2) {
         self.view.layout(size);
     }
+
+    fn wrap_on_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Key(Key::Enter) | Event::Char(' ') => match self.focused_message() {
+                Some(message) => {
+                    let group = self.group.read(false).id().to_owned();
+                    EventResult::with_cb(move |c| {
+                        send_user_action(c, &group, &message, UserAction::Click)
+                    })
+                }
+                None => self.view.on_event(event),
+            },
+            Event::Char('a') => match self.focused_message() {
+                Some(message) => {
+                    let group = self.group.read(false).id().to_owned();
+                    EventResult::with_cb(move |c| {
+                        send_user_action(c, &group, &message, UserAction::Acknowledge)
+                    })
+                }
+                None => self.view.on_event(event),
+            },
+            Event::Char('A') => {
+                let group = self.group.read(false);
+                let id = group.id().to_owned();
+                let unread: Vec<_> = group
+                    .unread_messages()
+                    .map(|(message, _)| message.to_owned())
+                    .collect();
+                EventResult::with_cb(move |c| {
+                    for message in &unread {
+                        send_user_action(c, &id, message, UserAction::Acknowledge)
+                    }
+                })
+            }
+            Event::Char('m') => {
+                let group = self.group.read(false).id().to_owned();
+                EventResult::with_cb(move |c| set_group_mute(c, &group, true, None))
+            }
+            Event::Char('s') => {
+                let group = self.group.read(false);
+                let (id, title) = (group.id().to_owned(), group.meta().title.clone());
+                EventResult::with_cb(move |c| c.add_layer(snooze_dialog(id.clone(), &title)))
+            }
+            _ => self.view.on_event(event),
+        }
+    }
+}
+
+/// Get the message ID of a child view in the message list.
+fn tag_view_id(view: &dyn View) -> Option<SmolStr> {
+    if let Some(v) = view.downcast_ref::<PaddedView<TagView>>() {
+        return Some(v.get_inner().id.clone());
+    }
+    view.downcast_ref::<LinearLayout>()?
+        .get_child(1)?
+        .downcast_ref::<TagView>()
+        .map(|v| v.id.clone())
+}
+
+fn snooze_dialog(group: String, title: &str) -> Dialog {
+    let mut select = SelectView::new();
+    for &(label, minutes) in &SNOOZE_MINUTES {
+        select.add_item(label, minutes);
+    }
+    select.set_on_submit(move |c, &minutes| {
+        c.pop_layer();
+        let until = Utc::now() + Duration::minutes(minutes);
+        set_group_mute(c, &group, true, Some(until));
+    });
+    Dialog::around(select)
+        .title(format!("Snooze {}", title))
+        .dismiss_button("Cancel")
+}
+
+/// Report a user action to all connected clients.
+pub fn send_user_action(c: &mut Cursive, group: &str, message: &str, action: UserAction) {
+    send(
+        c,
+        ApiMessage::UserAction(UserActionMsg {
+            group: group.to_owned(),
+            message: message.to_owned(),
+            action,
+        }),
+    )
 }
 
-fn do_diff_check(source: &MessageGroup, last: &mut Vec<SmolStr>, order: &mut Vec<usize>) {
-    // source.msgs
+/// Mute, snooze or unmute a group. The mute is applied once it has gone
+/// through `outgoing`, so that connected clients know about it.
+pub fn set_group_mute(c: &mut Cursive, group: &str, muted: bool, until: Option<DateTime<Utc>>) {
+    send(
+        c,
+        ApiMessage::SetGroupMute(SetGroupMuteMsg {
+            group: group.to_owned(),
+            muted,
+            until,
+        }),
+    )
+}
+
+fn send(c: &mut Cursive, msg: ApiMessage) {
+    if let Some(outgoing) = c.user_data::<Outgoing>() {
+        let _ = outgoing.send(msg);
+    }
 }
@@ -1,6 +1,6 @@
 use chrono::{DateTime, Datelike, Duration, Local};
 use cursive::{
-    theme::{ColorStyle, Effect, Style},
+    theme::{BaseColor, ColorStyle, Effect, Style},
     utils::markup::StyledString,
     Rect, Vec2, View,
 };
@@ -92,6 +92,7 @@ struct TagViewLayout {
 }
 
 impl TagView {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: SmolStr,
         multiline: bool,
@@ -116,6 +117,16 @@ impl TagView {
         }
     }
 
+    /// Show the body in bold, or in bold red if it stayed unread too long.
+    pub fn unread(mut self, overdue: bool) -> Self {
+        let mut style = Style::from(Effect::Bold);
+        if overdue {
+            style = style.combine(ColorStyle::front(BaseColor::Red.light()));
+        }
+        self.content = StyledString::styled(self.content.source(), style);
+        self
+    }
+
     fn print_counter(&self) -> bool {
         self.counter > 1
     }
@@ -322,7 +333,11 @@ impl View for TagView {
                 cur_print.x += 1;
             }
 
-            cur_print = self.do_print_content(cur_print, printer, size.x - cur_print.x - time_size);
+            cur_print = self.do_print_content(
+                cur_print,
+                printer,
+                size.x.saturating_sub(cur_print.x + time_size),
+            );
         });
         if self.timestamp.is_some() {
             cur_print.x += 1;
@@ -0,0 +1,311 @@
+//! Golden tests rendering views with the puppet backend.
+//!
+//! Each test renders views at some terminal sizes and compares the screens
+//! with `golden/<test>.txt`. Run with `UPDATE_GOLDEN=1` to rewrite the files
+//! after an intended change, and review the diff.
+use std::{path::PathBuf, sync::Arc};
+
+use chrono::{TimeZone, Utc};
+use cursive::{
+    backends::puppet::{self, observed::ObservedScreen},
+    event::{Event, Key},
+    theme::Effect,
+    views::{LinearLayout, ResizedView, TextView},
+    Cursive, Vec2, View,
+};
+use nadir_core::{
+    model::{group_list::GroupList, GroupRef, MessageGroup},
+    util::DirtyCheckLock,
+};
+use nadir_types::model::{self, Message};
+
+use crate::{dnd::Dnd, opt::DndConfig};
+
+use super::{group_list_view::GroupListView, group_view::GroupView, tag_view::TagView};
+
+/// Render a view on a screen of `size`, after sending it `events`.
+fn render(view: impl View, size: (usize, usize), events: &[Event]) -> String {
+    let backend = puppet::Backend::init(Some(Vec2::from(size)));
+    let frames = backend.stream();
+    let mut siv = Cursive::new();
+    siv.add_fullscreen_layer(view);
+    let mut runner = siv.into_runner(backend);
+    runner.refresh();
+    for event in events {
+        runner.on_event(event.clone());
+        runner.refresh();
+    }
+    let frame = frames.try_iter().last().expect("a frame was drawn");
+    format_screen(&frame, size)
+}
+
+/// Print the text of a screen, then mark reversed cells with `#` and bold ones
+/// with `*`.
+fn format_screen(screen: &ObservedScreen, (w, h): (usize, usize)) -> String {
+    let mut text = String::new();
+    let mut effects = String::new();
+    for y in 0..h {
+        text.push('|');
+        effects.push('|');
+        for x in 0..w {
+            let cell = screen[Vec2::new(x, y)].as_ref();
+            if let Some(letter) = cell.and_then(|c| c.letter.as_option()) {
+                text.push_str(letter);
+            } else if cell.is_none() {
+                text.push(' ');
+            }
+            let has = |effect| cell.is_some_and(|c| c.style.effects.contains(effect));
+            effects.push(if has(Effect::Reverse) {
+                '#'
+            } else if has(Effect::Bold) {
+                '*'
+            } else {
+                '.'
+            });
+        }
+        text.push_str("|\n");
+        effects.push_str("|\n");
+    }
+    format!("{}\n{}", text, effects)
+}
+
+/// Compare screens with the golden file of `name`.
+fn check(name: &str, screens: &[(String, String)]) {
+    let mut actual = String::new();
+    for (title, screen) in screens {
+        actual.push_str(&format!("## {}\n{}\n", title, screen));
+    }
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "src/view/golden"]
+        .iter()
+        .collect::<PathBuf>()
+        .join(format!("{}.txt", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "cannot read {}: {}\nRun with UPDATE_GOLDEN=1 to create it.",
+            path.display(),
+            e
+        )
+    });
+    if expected != actual {
+        panic!(
+            "screens differ from {}\nRun with UPDATE_GOLDEN=1 to update it.\n\n{}",
+            path.display(),
+            actual
+        );
+    }
+}
+
+fn message(id: &str, tags: &[&str], body: &str) -> Message {
+    Message {
+        id: id.into(),
+        counter: None,
+        tags: tags.iter().map(|&t| t.into()).collect(),
+        body: body.into(),
+        time: None,
+    }
+}
+
+fn group(id: &str, importance: i32, pinned: &[Message], msgs: &[Message]) -> MessageGroup {
+    let mut group = MessageGroup::new(model::MessageGroup {
+        id: id.into(),
+        title: id.to_uppercase(),
+        importance,
+        ..Default::default()
+    });
+    // Messages are shown newest first
+    group.add_pinned_messages(pinned.iter().rev().cloned());
+    group.add_messages(msgs.iter().rev().cloned());
+    for msg in pinned.iter().chain(msgs) {
+        group.acknowledge(&msg.id);
+    }
+    group
+}
+
+fn widths(msg: &Message, widths: &[usize]) -> Vec<(String, String)> {
+    widths
+        .iter()
+        .map(|&w| {
+            let screen = render(TagView::from(msg), (w, 1), &[]);
+            (format!("{}x1", w), screen)
+        })
+        .collect()
+}
+
+#[test]
+fn tag_view_truncation() {
+    let msg = message(
+        "m",
+        &["alpha", "a-much-longer-tag", "mid-tag", "x"],
+        "The body of the message",
+    );
+    check("tag_view_truncation", &widths(&msg, &[80, 48, 32, 20, 12]));
+}
+
+#[test]
+fn tag_view_wide_tags() {
+    let mut msg = message(
+        "m",
+        &["日本語のタグ", "🎉🎉party", "中文"],
+        "本文 with ümlauts",
+    );
+    msg.counter = Some(12345);
+    check("tag_view_wide_tags", &widths(&msg, &[60, 36, 24, 15]));
+}
+
+#[test]
+fn tag_view_counter_and_time() {
+    let mut msg = message("m", &[], "No tags but a counter");
+    msg.counter = Some(3);
+    // Times in the future are shown as "now"
+    msg.time = Some(Utc.with_ymd_and_hms(2999, 1, 1, 0, 0, 0).unwrap());
+    let plain = message("p", &[], "Just a body");
+    let mut screens = widths(&msg, &[40, 24]);
+    screens.extend(widths(&plain, &[24]));
+    check("tag_view_counter_and_time", &screens);
+}
+
+#[test]
+fn tag_view_focus() {
+    let list = || {
+        LinearLayout::vertical()
+            .child(TagView::from(&message("a", &["tag"], "first")))
+            .child(TagView::from(&message("b", &["tag"], "second")))
+            .child(TextView::new("footer"))
+    };
+    check(
+        "tag_view_focus",
+        &[
+            ("first focused".into(), render(list(), (30, 3), &[])),
+            (
+                "second focused".into(),
+                render(list(), (30, 3), &[Event::Key(Key::Down)]),
+            ),
+        ],
+    );
+}
+
+#[test]
+fn group_view_unread() {
+    let mut group = group("mail", 0, &[], &[]);
+    group.add_message(message("overdue", &["bob"], "overdue"));
+    group.mark_overdue(Utc::now());
+    group.add_message(message("unread", &["alice"], "unread"));
+    group.add_message(message("read", &["me"], "read"));
+    group.acknowledge("read");
+    let group = Arc::new(DirtyCheckLock::new(group));
+    check(
+        "group_view_unread",
+        &[(
+            "30x4".into(),
+            render(group_view_in_list(group), (30, 4), &[]),
+        )],
+    );
+}
+
+/// Host a group view like [`GroupListView`] does, which lays out its
+/// children after asking for their size.
+fn group_view_in_list(group: GroupRef) -> impl View {
+    ResizedView::with_full_screen(LinearLayout::vertical().child(GroupView::new(group)))
+}
+
+#[test]
+fn group_view() {
+    let group = || {
+        Arc::new(DirtyCheckLock::new(group(
+            "mail",
+            0,
+            &[message("p1", &["me"], "pinned one")],
+            &[
+                message("m1", &["alice"], "newest"),
+                message("m2", &["bob"], "older"),
+                message("m3", &["carol"], "oldest"),
+            ],
+        )))
+    };
+    let counted = group();
+    counted.write().set_counter(42);
+    check(
+        "group_view",
+        &[
+            (
+                "40x6".into(),
+                render(group_view_in_list(group()), (40, 6), &[]),
+            ),
+            (
+                "40x3, cut off".into(),
+                render(group_view_in_list(group()), (40, 3), &[]),
+            ),
+            (
+                "40x6, focus moved".into(),
+                render(
+                    group_view_in_list(group()),
+                    (40, 6),
+                    &[Event::Key(Key::Down), Event::Key(Key::Down)],
+                ),
+            ),
+            (
+                "40x6, counter".into(),
+                render(group_view_in_list(counted), (40, 6), &[]),
+            ),
+        ],
+    );
+}
+
+#[test]
+fn group_list_view() {
+    let list = || {
+        let mut list = GroupList::new();
+        list.add_group(Arc::new(DirtyCheckLock::new(group(
+            "low",
+            0,
+            &[],
+            &[message("l1", &["src"], "low importance")],
+        ))));
+        list.add_group(Arc::new(DirtyCheckLock::new(group(
+            "high",
+            5,
+            &[],
+            &[
+                message("h1", &["src"], "high importance"),
+                message("h2", &[], "second"),
+            ],
+        ))));
+        Arc::new(DirtyCheckLock::new(list))
+    };
+    let view_with = |data, dnd| {
+        GroupListView::new(
+            data,
+            Arc::new(dnd),
+            Box::new(|| Box::new(TextView::new("empty"))),
+        )
+    };
+    let view = |data| view_with(data, Dnd::new(&Default::default(), None));
+    let dnd = Dnd::new(
+        &DndConfig {
+            urgent_importance: Some(5),
+            ..Default::default()
+        },
+        None,
+    );
+    dnd.set(true);
+    check(
+        "group_list_view",
+        &[
+            ("50x8".into(), render(view(list()), (50, 8), &[])),
+            ("50x4".into(), render(view(list()), (50, 4), &[])),
+            (
+                "empty".into(),
+                render(
+                    view(Arc::new(DirtyCheckLock::new(GroupList::new()))),
+                    (50, 2),
+                    &[],
+                ),
+            ),
+            ("dnd".into(), render(view_with(list(), dnd), (50, 8), &[])),
+        ],
+    );
+}
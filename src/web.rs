@@ -0,0 +1,133 @@
+//! A read-only dashboard showing the groups in a browser.
+//!
+//! `GET /` serves a self-contained page, which follows `GET /events`: a
+//! stream of [Server-Sent Events][sse] carrying the snapshot of every group
+//! as JSON, sent on connect and after each burst of changes.
+//!
+//! [sse]: https://html.spec.whatwg.org/multipage/server-sent-events.html
+use std::{convert::Infallible, net::SocketAddr, sync::Arc, time::Duration};
+
+use hyper::{
+    body::{Bytes, Sender},
+    header::{AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use log::info;
+use nadir_core::{Change, Store};
+use tokio::{
+    select,
+    sync::broadcast::{self, error::RecvError},
+};
+
+use crate::err_and_exit;
+
+const INDEX: &str = include_str!("web/index.html");
+
+/// Changes arriving within this time are sent in one snapshot.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Idle event streams send a comment this often, so that proxies and the
+/// browser keep them open, and closed ones are noticed.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+struct State {
+    store: Store,
+    secret: Option<Arc<str>>,
+}
+
+pub async fn start_server(addr: SocketAddr, secret: Option<Arc<str>>, store: Store) {
+    let state = Arc::new(State { store, secret });
+    let make_svc = make_service_fn(move |_| {
+        let state = state.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(state.clone(), req))) }
+    });
+    let server = match Server::try_bind(&addr) {
+        Ok(s) => s.serve(make_svc),
+        Err(e) => err_and_exit(format_args!("Cannot listen on {}.\nReason: {}", addr, e)),
+    };
+    info!("dashboard on http://{}", addr);
+    if let Err(e) = server.await {
+        log::error!("dashboard stopped: {}", e);
+    }
+}
+
+async fn handle(state: Arc<State>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if req.method() != Method::GET {
+        return Ok(status(StatusCode::METHOD_NOT_ALLOWED));
+    }
+    if !authorized(&state, &req) {
+        return Ok(status(StatusCode::UNAUTHORIZED));
+    }
+    let resp = match req.uri().path() {
+        "/" => Response::builder()
+            .header(CONTENT_TYPE, "text/html; charset=utf-8")
+            .body(Body::from(INDEX)),
+        "/events" => {
+            let (sender, body) = Body::channel();
+            tokio::spawn(send_events(
+                state.store.clone(),
+                state.store.subscribe(),
+                sender,
+            ));
+            Response::builder()
+                .header(CONTENT_TYPE, "text/event-stream")
+                .header(CACHE_CONTROL, "no-cache")
+                .body(body)
+        }
+        _ => return Ok(status(StatusCode::NOT_FOUND)),
+    };
+    Ok(resp.expect("valid response"))
+}
+
+/// Browsers can't set headers on event streams, so the secret is accepted as
+/// the `secret` query parameter too. The page passes its own query on.
+fn authorized(state: &State, req: &Request<Body>) -> bool {
+    let secret = match &state.secret {
+        Some(s) => s,
+        None => return true,
+    };
+    let bearer = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let query = req.uri().query().unwrap_or_default();
+    bearer == Some(&**secret)
+        || url::form_urlencoded::parse(query.as_bytes())
+            .any(|(k, v)| k == "secret" && *v == **secret)
+}
+
+/// Send a snapshot now and after changes, until the client goes away.
+async fn send_events(store: Store, mut changes: broadcast::Receiver<Change>, mut sender: Sender) {
+    loop {
+        let snapshot = serde_json::to_string(&store.snapshot(None)).expect("serializable");
+        let event = format!("data: {}\n\n", snapshot);
+        if sender.send_data(Bytes::from(event)).await.is_err() {
+            return;
+        }
+
+        loop {
+            select! {
+                change = changes.recv() => match change {
+                    // Missed changes are covered by the next snapshot anyway
+                    Ok(_) | Err(RecvError::Lagged(_)) => break,
+                    Err(RecvError::Closed) => return,
+                },
+                _ = tokio::time::sleep(KEEP_ALIVE) => {
+                    if sender.send_data(Bytes::from_static(b": keep-alive\n\n")).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+        tokio::time::sleep(DEBOUNCE).await;
+        changes = changes.resubscribe();
+    }
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    let mut resp = Response::new(Body::empty());
+    *resp.status_mut() = code;
+    resp
+}
@@ -0,0 +1,240 @@
+//! Runs the frontend headless and talks to it like a backend.
+use std::{
+    io::{BufRead, BufReader},
+    net::TcpListener,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    time::Duration,
+};
+
+use futures::{SinkExt, StreamExt};
+use nadir_types::message::ApiMessage;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{tungstenite::Message as WsMessage, MaybeTlsStream, WebSocketStream};
+
+type Conn = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Where the frontend started by [`start`] saves its muted groups.
+fn mutes_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("nadir-{}-{}.json", name, std::process::id()))
+}
+
+/// Start a headless frontend listening on a free port, and connect to it.
+async fn start(name: &str) -> (Child, PathBuf, Conn) {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let config = std::env::temp_dir().join(format!("nadir-{}-{}.toml", name, std::process::id()));
+    std::fs::write(
+        &config,
+        format!(
+            "websocket_listen = [\"127.0.0.1:{}\"]\nmutes_file = {:?}\n",
+            port,
+            mutes_file(name).display().to_string()
+        ),
+    )
+    .unwrap();
+
+    let frontend = Command::new(env!("CARGO_BIN_EXE_nadir-notify"))
+        .arg("--headless")
+        .arg("-c")
+        .arg(&config)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let url = format!("ws://127.0.0.1:{}", port);
+    let mut conn = None;
+    for _ in 0..50 {
+        if let Ok((c, _)) = tokio_tungstenite::connect_async(url.as_str()).await {
+            conn = Some(c);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    (
+        frontend,
+        config,
+        conn.expect("frontend should be listening"),
+    )
+}
+
+/// Open another connection to the frontend `conn` is connected to.
+async fn connect_again(conn: &Conn) -> Conn {
+    let addr = match conn.get_ref() {
+        MaybeTlsStream::Plain(s) => s.peer_addr().unwrap(),
+        _ => unreachable!("tests connect without TLS"),
+    };
+    let url = format!("ws://{}", addr);
+    tokio_tungstenite::connect_async(url.as_str())
+        .await
+        .unwrap()
+        .0
+}
+
+async fn send(conn: &mut Conn, msgs: &[&str]) {
+    for msg in msgs {
+        conn.send(WsMessage::Text(msg.to_string())).await.unwrap();
+    }
+}
+
+async fn recv(conn: &mut Conn) -> ApiMessage {
+    let msg = tokio::time::timeout(Duration::from_secs(5), conn.next())
+        .await
+        .expect("frontend should respond")
+        .unwrap()
+        .unwrap();
+    serde_json::from_str(msg.to_text().unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn snapshot_over_websocket() {
+    let (mut frontend, config, mut conn) = start("headless").await;
+    let mut other = connect_again(&conn).await;
+
+    send(
+        &mut conn,
+        &[
+        r#"{"_t":"put_group","group":{"id":"low","title":"Low"}}"#,
+        r#"{"_t":"put_group","group":{"id":"high","title":"High","importance":1}}"#,
+        r#"{"_t":"put","group":"high","items":[{"id":"a","body":"first"},{"id":"b","body":"second"}]}"#,
+        r#"{"_t":"put","group":"high","items":[{"id":"p","body":"pinned"}],"pinned":true}"#,
+        r#"{"_t":"set_group_counter","group":"high","counter":7}"#,
+        r#"{"_t":"req_snapshot","msg_id":"all"}"#,
+        r#"{"_t":"req_snapshot","msg_id":"missing","group":"nope"}"#,
+        ],
+    )
+    .await;
+
+    let mut responses = vec![];
+    while responses.len() < 2 {
+        if let ApiMessage::RespSnapshot(resp) = recv(&mut conn).await {
+            responses.push(resp);
+        }
+    }
+    // Snapshots only go to the connection that asked for them
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), other.next())
+            .await
+            .is_err()
+    );
+    frontend.kill().unwrap();
+    frontend.wait().unwrap();
+    let _ = std::fs::remove_file(&config);
+
+    let all = responses.iter().find(|r| r.reply_to == "all").unwrap();
+    let ids: Vec<_> = all.groups.iter().map(|g| g.group.id.as_str()).collect();
+    assert_eq!(ids, vec!["high", "low"]);
+    let high = &all.groups[0];
+    assert_eq!(high.counter, 7);
+    let bodies: Vec<_> = high.messages.iter().map(|m| m.body.as_str()).collect();
+    assert_eq!(bodies, vec!["second", "first"]);
+    assert_eq!(high.pinned[0].body, "pinned");
+    assert!(responses
+        .iter()
+        .any(|r| r.reply_to == "missing" && r.groups.is_empty()));
+
+    // Every change to the state was written to stdout, requests weren't
+    let stdout = BufReader::new(frontend.stdout.take().unwrap());
+    let changes: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|l| serde_json::from_str(&l.unwrap()).unwrap())
+        .collect();
+    let kinds: Vec<_> = changes.iter().map(|c| c["change"]["_t"].clone()).collect();
+    assert_eq!(
+        kinds,
+        vec!["put_group", "put_group", "put", "put", "set_group_counter"]
+    );
+}
+
+#[tokio::test]
+async fn subscribe_to_changes() {
+    let (mut frontend, config, mut conn) = start("subscribe").await;
+
+    send(
+        &mut conn,
+        &[
+            r#"{"_t":"put_group","group":{"id":"a","title":"A"}}"#,
+            r#"{"_t":"put_group","group":{"id":"b","title":"B"}}"#,
+            r#"{"_t":"put","group":"a","items":[{"id":"old","body":"old"}]}"#,
+        ],
+    )
+    .await;
+    // Let the messages be applied before subscribing
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    send(
+        &mut conn,
+        &[r#"{"_t":"subscribe","msg_id":"sub","group":"a"}"#],
+    )
+    .await;
+    let snapshot = match recv(&mut conn).await {
+        ApiMessage::RespSnapshot(resp) => resp,
+        other => panic!("expected a snapshot, got {:?}", other),
+    };
+    assert_eq!(snapshot.reply_to, "sub");
+    assert_eq!(snapshot.groups.len(), 1);
+    assert_eq!(snapshot.groups[0].messages[0].id, "old");
+
+    send(
+        &mut conn,
+        &[
+            r#"{"_t":"put","group":"b","items":[{"id":"other","body":"filtered"}]}"#,
+            r#"{"_t":"put","group":"a","items":[{"id":"new","body":"new"}]}"#,
+            r#"{"_t":"remove","group":"a","items":["old","missing"]}"#,
+        ],
+    )
+    .await;
+    let changes = [recv(&mut conn).await, recv(&mut conn).await];
+    frontend.kill().unwrap();
+    frontend.wait().unwrap();
+    let _ = std::fs::remove_file(&config);
+
+    match &changes[0] {
+        ApiMessage::Put(put) => {
+            assert_eq!(put.group, "a");
+            assert_eq!(put.items[0].body, "new");
+        }
+        other => panic!("expected a put, got {:?}", other),
+    }
+    match &changes[1] {
+        ApiMessage::Remove(remove) => assert_eq!(remove.items, vec!["old"]),
+        other => panic!("expected a remove, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn mutes_survive_restarts() {
+    // A snooze that ended while the frontend wasn't running
+    let mutes = mutes_file("mutes");
+    std::fs::write(&mutes, r#"{"muted":null,"snoozed":"2000-01-01T00:00:00Z"}"#).unwrap();
+    let (mut frontend, config, mut conn) = start("mutes").await;
+
+    send(
+        &mut conn,
+        &[
+            r#"{"_t":"put_group","group":{"id":"muted","title":"Muted"}}"#,
+            r#"{"_t":"put_group","group":{"id":"snoozed","title":"Snoozed"}}"#,
+            r#"{"_t":"req_snapshot","msg_id":"all"}"#,
+        ],
+    )
+    .await;
+    let snapshot = loop {
+        if let ApiMessage::RespSnapshot(resp) = recv(&mut conn).await {
+            break resp;
+        }
+    };
+    frontend.kill().unwrap();
+    frontend.wait().unwrap();
+    let saved = std::fs::read_to_string(&mutes).unwrap();
+    let _ = std::fs::remove_file(&config);
+    let _ = std::fs::remove_file(&mutes);
+
+    let muted: Vec<_> = snapshot.groups.iter().map(|g| g.muted).collect();
+    assert_eq!(muted, vec![true, false]);
+    let saved: serde_json::Value = serde_json::from_str(&saved).unwrap();
+    assert_eq!(saved, serde_json::json!({ "muted": null }));
+}